@@ -0,0 +1,36 @@
+//! Find the version of `regex-automata` that is built, so `re_capabilities`
+//! can report it without a copy that goes stale when the lockfile changes.
+
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+
+    // The lockfile is in the workspace root, which may be above this crate
+    let Some(lockfile) = manifest_dir
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.is_file())
+    else {
+        println!("cargo:rerun-if-changed=Cargo.lock");
+        return;
+    };
+    println!("cargo:rerun-if-changed={}", lockfile.display());
+
+    if let Some(version) = locked_version(&lockfile, "regex-automata") {
+        println!("cargo:rustc-env=REGEX_AUTOMATA_VERSION={version}");
+    }
+}
+
+/// The version of `name` in the lockfile at `path`. Each package is a
+/// `[[package]]` table with `name` and `version` keys.
+fn locked_version(path: &Path, name: &str) -> Option<String> {
+    let lock = fs::read_to_string(path).ok()?;
+    let name_line = format!("name = \"{name}\"");
+    lock.split("[[package]]")
+        .find(|pkg| pkg.lines().any(|line| line == name_line))?
+        .lines()
+        .find_map(|line| line.strip_prefix("version = "))
+        .map(|version| version.trim_matches('"').to_owned())
+}
@@ -0,0 +1,357 @@
+//! The compiled regex. This is built straight from the HIR that we parse, so a
+//! pattern is only parsed once and anything we rewrite in the HIR (such as
+//! ASCII case folding) is compiled exactly as it is. It wraps
+//! `regex_automata::meta::Regex`, the engine behind `regex::bytes::Regex`,
+//! with the same settings and the parts of the `regex::bytes` API that we use.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use regex_automata::nfa::thompson::pikevm::PikeVM;
+use regex_automata::nfa::thompson::{self, pikevm};
+use regex_automata::util::captures;
+use regex_automata::util::look::LookMatcher;
+use regex_automata::{meta, Anchored, Input, MatchKind, PatternID};
+use regex_syntax::hir::Hir;
+
+use crate::error::Error;
+
+/// The `regex` crate's default limit on the size of the compiled pattern
+const DEFAULT_SIZE_LIMIT: usize = 10 * (1 << 20);
+/// The `regex` crate's default capacity of the lazy DFA's cache
+const DFA_CACHE_CAPACITY: usize = 2 * (1 << 20);
+
+/// Memory used by the NFA that a pattern compiles to, in bytes. Every search
+/// starts from this NFA, and it is what the size limit is checked against.
+/// Caches that are filled in while searching, like the lazy DFA's, are not
+/// counted.
+pub fn nfa_size(hir: &Hir, size_limit: Option<usize>) -> Result<usize, Error> {
+    let config = thompson::Config::new()
+        .utf8(false)
+        .nfa_size_limit(Some(size_limit.unwrap_or(DEFAULT_SIZE_LIMIT)));
+    let nfa = thompson::Compiler::new()
+        .configure(config)
+        .build_from_hir(hir)?;
+    Ok(nfa.memory_usage())
+}
+
+/// A compiled pattern that searches bytes
+#[derive(Debug)]
+pub struct Regex {
+    meta: meta::Regex,
+}
+
+impl Regex {
+    /// Compile a parsed pattern. Settings that change how the pattern is
+    /// parsed are already part of the HIR; the line terminator is also needed
+    /// here since it affects how `(?m)^` and `$` are searched for.
+    pub fn from_hir(
+        hir: &Hir,
+        size_limit: Option<usize>,
+        line_terminator: Option<u8>,
+    ) -> Result<Self, Error> {
+        let mut config = meta::Config::new()
+            .match_kind(MatchKind::LeftmostFirst)
+            .utf8_empty(false)
+            .nfa_size_limit(Some(size_limit.unwrap_or(DEFAULT_SIZE_LIMIT)))
+            .hybrid_cache_capacity(DFA_CACHE_CAPACITY);
+        if let Some(term) = line_terminator {
+            config = config.line_terminator(term);
+        }
+        let meta = meta::Builder::new().configure(config).build_from_hir(hir)?;
+        Ok(Self { meta })
+    }
+
+    /// Number of groups, including the entire match
+    pub fn captures_len(&self) -> usize {
+        self.meta.captures_len()
+    }
+
+    /// Name of each group, starting with the entire match, which has none
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.meta.group_info().pattern_names(PatternID::ZERO)
+    }
+
+    pub fn is_match(&self, haystack: &[u8]) -> bool {
+        self.meta.is_match(haystack)
+    }
+
+    /// Every match without its groups, which is faster to search for
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h [u8]) -> impl Iterator<Item = Match<'h>> + 'r
+    where
+        'h: 'r,
+    {
+        self.meta
+            .find_iter(haystack)
+            .map(move |m| Match::new(haystack, m.range()))
+    }
+
+    /// Every match with its groups
+    pub fn captures_iter<'r, 'h>(
+        &'r self,
+        haystack: &'h [u8],
+    ) -> impl Iterator<Item = Captures<'h>> + 'r
+    where
+        'h: 'r,
+    {
+        self.meta
+            .captures_iter(haystack)
+            .map(move |caps| Captures::new(haystack, caps))
+    }
+
+    /// The first match that starts at or after `start`. Text before `start`
+    /// is still used for assertions like `\b`.
+    pub fn captures_at<'h>(&self, haystack: &'h [u8], start: usize) -> Option<Captures<'h>> {
+        let input = Input::new(haystack).span(start..haystack.len());
+        let mut caps = self.meta.create_captures();
+        self.meta.search_captures(&input, &mut caps);
+        caps.is_match().then(|| Captures::new(haystack, caps))
+    }
+
+    /// Replace the first match, expanding group references in `rep`
+    pub fn replace<'h>(&self, haystack: &'h [u8], rep: &[u8]) -> Cow<'h, [u8]> {
+        self.replacen(haystack, 1, rep)
+    }
+
+    /// Replace every match, expanding group references in `rep`
+    pub fn replace_all<'h>(&self, haystack: &'h [u8], rep: &[u8]) -> Cow<'h, [u8]> {
+        self.replacen(haystack, usize::MAX, rep)
+    }
+
+    /// Replace up to `limit` matches. The text is borrowed if nothing matched.
+    fn replacen<'h>(&self, haystack: &'h [u8], limit: usize, rep: &[u8]) -> Cow<'h, [u8]> {
+        let mut replacements = self.replacements(haystack, rep, limit).peekable();
+        if replacements.peek().is_none() {
+            return Cow::Borrowed(haystack);
+        }
+
+        let mut dest = Vec::with_capacity(haystack.len());
+        let mut last_end = 0;
+        for (range, expanded) in replacements {
+            dest.extend_from_slice(&haystack[last_end..range.start]);
+            dest.extend_from_slice(&expanded);
+            last_end = range.end;
+        }
+        dest.extend_from_slice(&haystack[last_end..]);
+        Cow::Owned(dest)
+    }
+
+    /// The first `limit` matches, each as the range of the entire match and
+    /// `rep` with its group references expanded for that match
+    pub fn replacements<'a>(
+        &'a self,
+        haystack: &'a [u8],
+        rep: &'a [u8],
+        limit: usize,
+    ) -> impl Iterator<Item = (Range<usize>, Vec<u8>)> + 'a {
+        self.captures_iter(haystack).take(limit).map(|cap_match| {
+            let mut expanded = Vec::new();
+            cap_match.expand(rep, &mut expanded);
+            (cap_match.get(0).unwrap().range(), expanded)
+        })
+    }
+}
+
+/// Finds the shortest match that starts at a given position. The PikeVM runs
+/// every possible match in step, so with `earliest` it stops at the first
+/// position where any of them ends. Assertions like `$` and `\b` see the
+/// whole text.
+pub struct ShortestSearcher {
+    vm: PikeVM,
+    cache: pikevm::Cache,
+}
+
+impl ShortestSearcher {
+    /// Compile the same pattern as `Regex::from_hir`. There is no size limit
+    /// since that was already checked when compiling the `Regex`.
+    pub fn new(hir: &Hir, line_terminator: Option<u8>) -> Result<Self, Error> {
+        let mut look_matcher = LookMatcher::new();
+        if let Some(term) = line_terminator {
+            look_matcher.set_line_terminator(term);
+        }
+        let config = thompson::Config::new()
+            .utf8(false)
+            .look_matcher(look_matcher);
+        let nfa = thompson::Compiler::new()
+            .configure(config)
+            .build_from_hir(hir)?;
+        let vm = PikeVM::new_from_nfa(nfa)?;
+        let cache = vm.create_cache();
+        Ok(Self { vm, cache })
+    }
+
+    /// The shortest match that starts exactly at `start`
+    pub fn captures_at<'h>(&mut self, haystack: &'h [u8], start: usize) -> Option<Captures<'h>> {
+        let input = Input::new(haystack)
+            .span(start..haystack.len())
+            .anchored(Anchored::Yes)
+            .earliest(true);
+        let mut caps = self.vm.create_captures();
+        self.vm.search(&mut self.cache, &input, &mut caps);
+        caps.is_match().then(|| Captures::new(haystack, caps))
+    }
+}
+
+/// The groups of one match
+#[derive(Debug)]
+pub struct Captures<'h> {
+    haystack: &'h [u8],
+    caps: captures::Captures,
+}
+
+impl<'h> Captures<'h> {
+    /// Groups found in `haystack` by a search that matched
+    pub fn new(haystack: &'h [u8], caps: captures::Captures) -> Self {
+        Self { haystack, caps }
+    }
+
+    /// The group with index `i`, if it participated in the match
+    pub fn get(&self, i: usize) -> Option<Match<'h>> {
+        self.caps
+            .get_group(i)
+            .map(|span| Match::new(self.haystack, span.range()))
+    }
+
+    /// The group called `name`, if it participated in the match
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        self.caps
+            .get_group_by_name(name)
+            .map(|span| Match::new(self.haystack, span.range()))
+    }
+
+    /// Append `replacement` to `dest` with group references like `$1`
+    /// replaced by the group's content, the same as `regex`
+    pub fn expand(&self, replacement: &[u8], dest: &mut Vec<u8>) {
+        self.caps
+            .interpolate_bytes_into(self.haystack, replacement, dest);
+    }
+}
+
+/// Where a match or group was found, with the text it covers
+#[derive(Clone, Copy, Debug)]
+pub struct Match<'h> {
+    haystack: &'h [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    fn new(haystack: &'h [u8], range: Range<usize>) -> Self {
+        Self {
+            haystack,
+            start: range.start,
+            end: range.end,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn as_bytes(&self) -> &'h [u8] {
+        &self.haystack[self.start..self.end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::bytes::RegexBuilder;
+    use regex_syntax::ParserBuilder;
+
+    use super::*;
+
+    /// Compile `pattern` without unicode, both with our engine and with
+    /// `regex::bytes` as we used to
+    fn both(pattern: &str, line_terminator: Option<u8>) -> (Regex, regex::bytes::Regex) {
+        let mut parser = ParserBuilder::new();
+        let mut builder = RegexBuilder::new(pattern);
+        parser.utf8(false).unicode(false);
+        builder.unicode(false);
+        if let Some(term) = line_terminator {
+            parser.line_terminator(term);
+            builder.line_terminator(term);
+        }
+        let hir = parser.build().parse(pattern).unwrap();
+        let ours = Regex::from_hir(&hir, None, line_terminator).unwrap();
+        (ours, builder.build().unwrap())
+    }
+
+    /// The range of each group in each match
+    type Groups = Vec<Vec<Option<Range<usize>>>>;
+
+    /// Every group of every match, found by our engine and by `regex::bytes`
+    fn all_groups(pattern: &str, line_terminator: Option<u8>, haystack: &[u8]) -> (Groups, Groups) {
+        let (ours, theirs) = both(pattern, line_terminator);
+        let ours = ours
+            .captures_iter(haystack)
+            .map(|caps| {
+                (0..ours.captures_len())
+                    .map(|i| caps.get(i).map(|m| m.range()))
+                    .collect()
+            })
+            .collect();
+        let theirs = theirs
+            .captures_iter(haystack)
+            .map(|caps| caps.iter().map(|m| m.map(|m| m.range())).collect())
+            .collect();
+        (ours, theirs)
+    }
+
+    #[test]
+    fn test_empty_matches_split_chars() {
+        // Without unicode, empty matches can be at every byte, including
+        // inside a char
+        let haystack = "aé😀".as_bytes();
+        for pattern in ["", "x*", r"\xC3?", r"(a|\xF0)?"] {
+            let (ours, theirs) = all_groups(pattern, None, haystack);
+            assert_eq!(ours, theirs, "{pattern}");
+        }
+        let (ours, _) = all_groups("", None, haystack);
+        assert_eq!(ours.len(), haystack.len() + 1);
+    }
+
+    #[test]
+    fn test_expand() {
+        let (ours, theirs) = both(r"(?P<name>\w)(\d)?", None);
+        let haystack = b"a1 b c2";
+        for rep in ["$name-${1}x", "$2|$1x|$3", "${name}$$", "$0 $10 ${"] {
+            assert_eq!(
+                ours.replace_all(haystack, rep.as_bytes()),
+                theirs.replace_all(haystack, rep.as_bytes()),
+                "{rep}"
+            );
+            assert_eq!(
+                ours.replace(haystack, rep.as_bytes()),
+                theirs.replace(haystack, rep.as_bytes()),
+                "{rep}"
+            );
+        }
+        // Nothing is copied without a match
+        assert!(matches!(ours.replace_all(b"!", b"x"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_line_terminator() {
+        let haystack = b"ab;cd\nef;\n;gh";
+        for pattern in [r"(?m)^\w+$", r"(?m)$", r"(?m)^", r"(?m)(\w)$"] {
+            for term in [None, Some(b';')] {
+                let (ours, theirs) = all_groups(pattern, term, haystack);
+                assert_eq!(ours, theirs, "{pattern} {term:?}");
+            }
+        }
+        let (ours, _) = all_groups(r"(?m)^\w+$", Some(b';'), haystack);
+        assert_eq!(ours, [vec![Some(0..2)], vec![Some(11..13)]]);
+    }
+}
@@ -1,13 +1,16 @@
 //! All the messy-ish error handling code
 
+use std::borrow::Cow;
 use std::ops::Range;
 use std::str;
 
-use regex_syntax::ast::Span as ReSpan;
+use regex_automata::meta::BuildError;
+use regex_automata::nfa::thompson;
+use regex_syntax::ast::{self, Span as ReSpan};
 use rustc_lexer::unescape::EscapeError;
 use serde::Serialize;
 
-use crate::strops::{utf16_index_bytes, utf16_index_chars, StrType};
+use crate::strops::{utf16_index_bytes, utf16_index_chars, utf32_index_bytes, StrType};
 
 /// Wrapper so we can serialize regex errors
 #[derive(Debug, Serialize)]
@@ -17,45 +20,173 @@ use crate::strops::{utf16_index_bytes, utf16_index_chars, StrType};
 pub enum Error {
     /// An error from regex
     RegexSyntax(Box<ReSyntax>),
+    /// The pattern uses lookahead or lookbehind, which regex doesn't support
+    UnsupportedLookaround(Box<UnsupportedLookaround>),
+    /// A counted repetition is larger than the configured limit
+    RepetitionTooLarge(Box<RepetitionTooLarge>),
     /// Regex compiled larger than the limit (unlikely, unless we set a limit)
     RegexCompiledTooBig(String),
     /// Unspecified error (very unlikely)
     RegexUnspecified(String),
     /// Error with an input string. The second argument indicates which
     Unescape(Box<Unescape>),
+    /// An options object from js could not be read
+    InvalidOptions(String),
+    /// An offset passed from js is out of bounds or not on a char boundary
+    InvalidOffset(String),
+    /// The replacement has a `$` that isn't a valid group reference, only
+    /// checked in strict mode
+    InvalidReplacement(Box<InvalidReplacement>),
+    /// A replacement result isn't valid utf8, with the offset of the first
+    /// invalid byte. Only returned if requested with `OnInvalidUtf8::Error`.
+    InvalidUtf8Result { offset: usize },
+    /// The text is longer than the configured maximum, in utf8 bytes. This
+    /// is checked before matching.
+    TextTooLong { len: usize, max: usize },
+    /// The pattern uses a feature that can't be written as a glob
+    NotGlobConvertible(String),
+    /// The pattern isn't a single character class
+    NotAClass,
 }
 
-/// Add automatic conversion from regex error to our error type
-impl From<regex::Error> for Error {
-    fn from(value: regex::Error) -> Self {
-        let err_string = value.to_string();
-        match value {
-            // This should be unreachable because our builder checked the syntax
-            // already
-            regex::Error::Syntax(_) => unreachable!(),
-            regex::Error::CompiledTooBig(_) => Self::RegexCompiledTooBig(err_string),
-            _ => Self::RegexUnspecified(err_string),
+impl Error {
+    /// Error for a pattern that failed to compile, which is usually because
+    /// it is larger than `size_limit`
+    fn from_build_error(size_limit: Option<usize>, message: String) -> Self {
+        match size_limit {
+            Some(limit) => Self::RegexCompiledTooBig(format!(
+                "Compiled regex exceeds size limit of {limit} bytes."
+            )),
+            None => Self::RegexUnspecified(message),
         }
     }
+
+    /// Error for a pattern that failed to parse, with lines numbered from
+    /// `line_base`. Lookaround gets its own error, since it is common in other
+    /// engines and deserves a clearer message.
+    pub fn from_syntax(value: regex_syntax::Error, line_base: usize) -> Self {
+        if let regex_syntax::Error::Parse(e) = &value {
+            if *e.kind() == ast::ErrorKind::UnsupportedLookAround {
+                return Self::UnsupportedLookaround(Box::new(UnsupportedLookaround::new(
+                    e.pattern(),
+                    e.span(),
+                    line_base,
+                )));
+            }
+        }
+        Self::RegexSyntax(Box::new(ReSyntax::new(value, line_base)))
+    }
+
+    /// Human readable description of the error
+    fn message(&self) -> Cow<'_, str> {
+        match self {
+            Self::RegexSyntax(e) => Cow::Borrowed(&e.message),
+            Self::UnsupportedLookaround(e) => Cow::Borrowed(&e.message),
+            Self::RepetitionTooLarge(e) => Cow::Borrowed(&e.message),
+            Self::Unescape(e) => Cow::Borrowed(&e.message),
+            Self::InvalidReplacement(e) => Cow::Borrowed(&e.message),
+            Self::RegexCompiledTooBig(msg)
+            | Self::RegexUnspecified(msg)
+            | Self::InvalidOptions(msg)
+            | Self::InvalidOffset(msg)
+            | Self::NotGlobConvertible(msg) => Cow::Borrowed(msg),
+            Self::InvalidUtf8Result { offset } => {
+                Cow::Owned(format!("result is not valid utf8 at offset {offset}"))
+            }
+            Self::NotAClass => Cow::Borrowed("pattern is not a single character class"),
+            Self::TextTooLong { len, max } => Cow::Owned(format!(
+                "text is {len} bytes long, more than the maximum of {max}"
+            )),
+        }
+    }
+
+    /// Render the error for a monospace display: each line of `pattern` that
+    /// the error points at, underlined with `^` (or `-` for the auxiliary span,
+    /// such as the first of two duplicate names), then the message. Columns
+    /// are counted in chars so the underline lines up for non-ASCII patterns.
+    pub fn render(&self, pattern: &str) -> String {
+        // The primary span goes last so it is drawn over the auxiliary one
+        let spans: Vec<(&Span, char)> = match self {
+            Self::RegexSyntax(e) => e
+                .auxiliary_span
+                .as_ref()
+                .map(|sp| (sp, '-'))
+                .into_iter()
+                .chain(std::iter::once((&e.span, '^')))
+                .collect(),
+            Self::UnsupportedLookaround(e) => vec![(&e.span, '^')],
+            Self::RepetitionTooLarge(e) => vec![(&e.span, '^')],
+            _ => Vec::new(),
+        };
+
+        let mut ret = String::new();
+        for line in pattern.split('\n') {
+            let line_start = line.as_ptr() as usize - pattern.as_ptr() as usize;
+            let line_end = line_start + line.len();
+
+            // Underline for each span that starts on this line
+            let mut underline: Vec<char> = Vec::new();
+            for &(span, mark) in &spans {
+                let (start, end) = (span.start.offset, span.end.offset);
+                if !(line_start..=line_end).contains(&start) {
+                    continue;
+                }
+                let col_start = line[..start - line_start].chars().count();
+                let col_end = line[..end.min(line_end) - line_start].chars().count();
+                // Always mark at least one column, e.g. for the end of input
+                let col_end = col_end.max(col_start + 1);
+                if underline.len() < col_end {
+                    underline.resize(col_end, ' ');
+                }
+                underline[col_start..col_end].fill(mark);
+            }
+
+            if !underline.is_empty() {
+                ret.push_str(line);
+                ret.push('\n');
+                ret.extend(underline);
+                ret.push('\n');
+            }
+        }
+
+        ret.push_str(&self.message());
+        ret
+    }
+}
+
+/// Add automatic conversion from regex build errors to our error type. We
+/// compile from a parsed pattern, so there are no syntax errors here.
+impl From<BuildError> for Error {
+    fn from(value: BuildError) -> Self {
+        Self::from_build_error(value.size_limit(), value.to_string())
+    }
 }
 
-/// Automatic conversion from
+/// The same as for `BuildError`, for when only the NFA is compiled
+impl From<thompson::BuildError> for Error {
+    fn from(value: thompson::BuildError) -> Self {
+        Self::from_build_error(value.size_limit(), value.to_string())
+    }
+}
+
+/// Automatic conversion from regex syntax errors, with lines numbered from
+/// `DEFAULT_LINE_BASE`
 impl From<regex_syntax::Error> for Error {
     fn from(value: regex_syntax::Error) -> Self {
-        Self::RegexSyntax(Box::new(value.into()))
+        Self::from_syntax(value, DEFAULT_LINE_BASE)
     }
 }
 
-impl From<(Unescape, &'static str)> for Error {
-    fn from(value: (Unescape, &'static str)) -> Self {
+impl From<(Unescape, UnescapeSource)> for Error {
+    fn from(value: (Unescape, UnescapeSource)) -> Self {
         let mut err = value.0;
         err.source = Some(value.1);
         Self::Unescape(Box::new(err))
     }
 }
 
-impl From<(Box<Unescape>, &'static str)> for Error {
-    fn from(value: (Box<Unescape>, &'static str)) -> Self {
+impl From<(Box<Unescape>, UnescapeSource)> for Error {
+    fn from(value: (Box<Unescape>, UnescapeSource)) -> Self {
         let mut err = value.0;
         err.source = Some(value.1);
         Self::Unescape(err)
@@ -79,21 +210,38 @@ pub struct ReSyntax {
     span: Span,
     /// Location of the error with js offsets
     span_utf16: Span,
+    /// Location of the error with char (unicode scalar) offsets
+    span_char: Span,
     /// If applicable, second location of the error (e.g. for duplicates)
     auxiliary_span: Option<Span>,
     /// Auxiliary span with js offsets
     auxiliary_span_utf16: Option<Span>,
+    /// Auxiliary span with char offsets
+    auxiliary_span_char: Option<Span>,
+    /// Byte offset up to which the pattern parsed without error. Clients can
+    /// use this to fall back to partial highlighting
+    valid_prefix_len: usize,
 }
 
-/// Convert regex syntax errors into our common error type
+/// Convert regex syntax errors into our common error type, with lines numbered
+/// from `DEFAULT_LINE_BASE`
 impl From<regex_syntax::Error> for ReSyntax {
     fn from(value: regex_syntax::Error) -> Self {
+        Self::new(value, DEFAULT_LINE_BASE)
+    }
+}
+
+impl ReSyntax {
+    fn new(value: regex_syntax::Error, line_base: usize) -> Self {
         if let regex_syntax::Error::Parse(e) = value {
-            let (span_u8, span_u16) = convert_re_spans(e.pattern(), e.span());
-            let (aux_span_u8, aux_span_u16) = e
+            let (span_u8, span_u16, span_char) = convert_re_spans(e.pattern(), e.span(), line_base);
+            let aux = e
                 .auxiliary_span()
-                .map(|sp| convert_re_spans(e.pattern(), sp))
-                .unzip();
+                .map(|sp| convert_re_spans(e.pattern(), sp, line_base));
+            let (aux_span_u8, aux_span_u16, aux_span_char) = match aux {
+                Some((u8, u16, ch)) => (Some(u8), Some(u16), Some(ch)),
+                None => (None, None, None),
+            };
             // AST error
             Self {
                 kind: format!("{:?}", e.kind()),
@@ -101,11 +249,14 @@ impl From<regex_syntax::Error> for ReSyntax {
                 pattern: e.pattern().to_owned(),
                 span: span_u8,
                 span_utf16: span_u16,
+                span_char,
+                valid_prefix_len: e.span().start.offset,
                 auxiliary_span: aux_span_u8,
                 auxiliary_span_utf16: aux_span_u16,
+                auxiliary_span_char: aux_span_char,
             }
         } else if let regex_syntax::Error::Translate(e) = value {
-            let (span_u8, span_u16) = convert_re_spans(e.pattern(), e.span());
+            let (span_u8, span_u16, span_char) = convert_re_spans(e.pattern(), e.span(), line_base);
             // HIR error
             Self {
                 kind: format!("{:?}", e.kind()),
@@ -113,8 +264,11 @@ impl From<regex_syntax::Error> for ReSyntax {
                 pattern: e.pattern().to_owned(),
                 span: span_u8,
                 span_utf16: span_u16,
+                span_char,
+                valid_prefix_len: e.span().start.offset,
                 auxiliary_span: None,
                 auxiliary_span_utf16: None,
+                auxiliary_span_char: None,
             }
         } else {
             Self {
@@ -125,6 +279,106 @@ impl From<regex_syntax::Error> for ReSyntax {
     }
 }
 
+/// Lookaround used in a pattern
+#[derive(Debug, Serialize)]
+pub struct UnsupportedLookaround {
+    /// Either "lookahead" or "lookbehind"
+    kind: &'static str,
+    /// Explanation with possible alternatives
+    message: String,
+    /// Location of the lookaround's opening, e.g. `(?<=`
+    span: Span,
+    /// Location of the lookaround with js offsets
+    span_utf16: Span,
+    /// Location of the lookaround with char offsets
+    span_char: Span,
+}
+
+impl UnsupportedLookaround {
+    fn new(pattern: &str, span: &ReSpan, line_base: usize) -> Self {
+        let (span_u8, span_u16, span_char) = convert_re_spans(pattern, span, line_base);
+        let opening = &pattern[span.start.offset..span.end.offset];
+        let kind = if opening.contains('<') {
+            "lookbehind"
+        } else {
+            "lookahead"
+        };
+        Self {
+            kind,
+            message: format!(
+                "{kind} ('{opening}') is not supported; try matching the surrounding \
+                text as well and using a capture group for the part you need"
+            ),
+            span: span_u8,
+            span_utf16: span_u16,
+            span_char,
+        }
+    }
+}
+
+/// Counted repetition like `a{1000}` with a bound over the limit
+#[derive(Debug, Serialize)]
+pub struct RepetitionTooLarge {
+    /// The largest bound of the repetition
+    pub count: u32,
+    /// The limit that was exceeded
+    pub limit: u32,
+    message: String,
+    /// Location of the repetition operator, e.g. `{1000}`
+    span: Span,
+    /// Location of the repetition with js offsets
+    span_utf16: Span,
+    /// Location of the repetition with char offsets
+    span_char: Span,
+}
+
+impl RepetitionTooLarge {
+    pub fn new(pattern: &str, span: &ReSpan, count: u32, limit: u32, line_base: usize) -> Self {
+        let (span_u8, span_u16, span_char) = convert_re_spans(pattern, span, line_base);
+        Self {
+            count,
+            limit,
+            message: format!("repetition count {count} is larger than the limit of {limit}"),
+            span: span_u8,
+            span_utf16: span_u16,
+            span_char,
+        }
+    }
+}
+
+/// A `$` in a replacement that doesn't start a valid group reference
+#[derive(Debug, Serialize)]
+pub struct InvalidReplacement {
+    message: String,
+    /// Location of the `$` and the character after it, within the replacement
+    span: Span,
+    /// Location of the reference with js offsets
+    span_utf16: Span,
+    /// Location of the reference with char offsets
+    span_char: Span,
+}
+
+impl InvalidReplacement {
+    pub fn new(rep: &str, range: Range<usize>, message: String) -> Self {
+        let (span, span_utf16, span_char) = Span::from_offsets(rep, range, DEFAULT_LINE_BASE);
+        Self {
+            message,
+            span,
+            span_utf16,
+            span_char,
+        }
+    }
+}
+
+/// Number given to the first line when nothing else is requested, the same
+/// as `regex_syntax`. See `BuildOptions::line_base`.
+pub const DEFAULT_LINE_BASE: usize = 1;
+
+/// Convert a 1-based line number from `regex_syntax` to count from `line_base`
+fn rebase_line(line: usize, line_base: usize) -> usize {
+    line - 1 + line_base
+}
+
 /// Direct serializable map of `regex_syntax::ast::Span`
 #[derive(Default, Debug, PartialEq, Serialize)]
 pub struct Span {
@@ -133,27 +387,23 @@ pub struct Span {
 }
 
 impl Span {
-    #[allow(unused)]
     pub fn new(start: Position, end: Position) -> Self {
         Self { start, end }
     }
 
-    /// Returns a utf8 and utf16 span
-    pub fn from_offsets(s: &str, range: Range<usize>) -> (Self, Self) {
+    /// Returns a utf8, utf16, and char span, numbering lines from `line_base`
+    pub fn from_offsets(s: &str, range: Range<usize>, line_base: usize) -> (Self, Self, Self) {
         assert!(range.start < range.end);
-        let (start_u8, start_u16) = Position::from_offset(s, range.start);
-        let (mut end_u8, mut end_u16) = Position::from_offset(s, range.end);
+        let (start_u8, start_u16, start_char) = Position::from_offset(s, range.start, line_base);
+        let (mut end_u8, mut end_u16, mut end_char) =
+            Position::from_offset(s, range.end, line_base);
         end_u8.increment_line();
         end_u16.increment_line();
+        end_char.increment_line();
         (
-            Self {
-                start: start_u8,
-                end: end_u8,
-            },
-            Self {
-                start: start_u16,
-                end: end_u16,
-            },
+            Self::new(start_u8, end_u8),
+            Self::new(start_u16, end_u16),
+            Self::new(start_char, end_char),
         )
     }
 }
@@ -177,10 +427,10 @@ impl Position {
         }
     }
 
-    /// Return utf8 and utf16 positions from a single utf8 byte index. Somewhat
-    /// inefficient algorithm, but simple
-    fn from_offset(s: &str, offset: usize) -> (Self, Self) {
-        let mut line = 1;
+    /// Return utf8, utf16, and char positions from a single utf8 byte index.
+    /// Somewhat inefficient algorithm, but simple
+    fn from_offset(s: &str, offset: usize, line_base: usize) -> (Self, Self, Self) {
+        let mut line = line_base;
         let newline_idx = s[..offset]
             .bytes()
             .enumerate()
@@ -198,10 +448,13 @@ impl Position {
         let col_u8 = offset - newline_idx;
         let col_u16 = utf16_index_bytes(&s[newline_idx..], offset - newline_idx);
         let offset_u16 = utf16_index_bytes(s, offset);
+        let col_char = utf32_index_bytes(&s[newline_idx..], offset - newline_idx);
+        let offset_char = utf32_index_bytes(s, offset);
 
         (
             Self::new(offset, line, col_u8),
             Self::new(offset_u16, line, col_u16),
+            Self::new(offset_char, line, col_char),
         )
     }
 
@@ -211,8 +464,9 @@ impl Position {
     }
 }
 
-/// Creates a utf8 span and a utf16 span
-fn convert_re_spans(s: &str, span: &ReSpan) -> (Span, Span) {
+/// Creates a utf8 span, a utf16 span, and a char span, numbering lines from
+/// `line_base`
+pub fn convert_re_spans(s: &str, span: &ReSpan, line_base: usize) -> (Span, Span, Span) {
     let off16_start = utf16_index_bytes(s, span.start.offset);
     let off16_end = utf16_index_bytes(s, span.end.offset);
 
@@ -226,29 +480,43 @@ fn convert_re_spans(s: &str, span: &ReSpan) -> (Span, Span) {
     let span_u8 = Span {
         start: Position {
             offset: span.start.offset,
-            line: span.start.line,
+            line: rebase_line(span.start.line, line_base),
             column: span.start.column,
         },
         end: Position {
             offset: span.end.offset,
-            line: span.end.line,
+            line: rebase_line(span.end.line, line_base),
             column: span.end.column,
         },
     };
     let span_u16 = Span {
         start: Position {
             offset: off16_start,
-            line: span.start.line,
+            line: rebase_line(span.start.line, line_base),
             column: col16_start,
         },
         end: Position {
             offset: off16_end,
-            line: span.end.line,
+            line: rebase_line(span.end.line, line_base),
             column: col16_end,
         },
     };
 
-    (span_u8, span_u16)
+    // Columns from regex_syntax already count chars
+    let span_char = Span {
+        start: Position {
+            offset: utf32_index_bytes(s, span.start.offset),
+            line: rebase_line(span.start.line, line_base),
+            column: span.start.column,
+        },
+        end: Position {
+            offset: utf32_index_bytes(s, span.end.offset),
+            line: rebase_line(span.end.line, line_base),
+            column: span.end.column,
+        },
+    };
+
+    (span_u8, span_u16, span_char)
 }
 
 /// Error while unescaping a string
@@ -262,19 +530,35 @@ pub struct Unescape {
     pub span: Span,
     /// Location of the error with js offsets
     pub span_utf16: Span,
+    /// Location of the error with char offsets
+    pub span_char: Span,
     /// Where this error came from
-    pub source: Option<&'static str>,
+    pub source: Option<UnescapeSource>,
+}
+
+/// Which input string an unescape error came from
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum UnescapeSource {
+    /// The text being searched
+    Text,
+    /// The pattern
+    RegExp,
+    /// The replacement string
+    Replacement,
 }
 
 impl Unescape {
     /// Create a pattern error
     pub fn from_pat(s: &str, pat: &str, idx: usize, type_: StrType) -> Self {
-        let (span, span_utf16) = Span::from_offsets(s, idx..(idx + pat.len()));
+        let (span, span_utf16, span_char) =
+            Span::from_offsets(s, idx..(idx + pat.len()), DEFAULT_LINE_BASE);
         Self {
             message: format!("pattern '{pat}' may not be contained in {type_} strings"),
             kind: "Pattern".to_owned(),
             span,
             span_utf16,
+            span_char,
             source: None,
         }
     }
@@ -316,13 +600,14 @@ fn escape_error_message(err: &EscapeError) -> String {
 impl From<(&str, Range<usize>, EscapeError)> for Unescape {
     fn from(value: (&str, Range<usize>, EscapeError)) -> Self {
         let (s, range, err) = value;
-        let (span, span_utf16) = Span::from_offsets(s, range);
+        let (span, span_utf16, span_char) = Span::from_offsets(s, range, DEFAULT_LINE_BASE);
         let message = escape_error_message(&err);
         Self {
             message,
             kind: format!("{err:?}"),
             span,
             span_utf16,
+            span_char,
             source: None,
         }
     }
@@ -335,18 +620,132 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::reversed_empty_ranges)]
     fn test_span_offset() {
         let s = "abc😊\ndef";
         assert_eq!(
-            Span::from_offsets(s, 0..1),
-            (make_span(0..1, 1..2, 0..1), make_span(0..1, 1..2, 0..1))
+            Span::from_offsets(s, 0..1, DEFAULT_LINE_BASE),
+            (
+                make_span(0..1, 1..2, 0..1),
+                make_span(0..1, 1..2, 0..1),
+                make_span(0..1, 1..2, 0..1)
+            )
         );
         assert_eq!(
-            Span::from_offsets(s, 2..9),
-            (make_span(2..9, 1..3, 2..1), make_span(2..7, 1..3, 2..1))
+            Span::from_offsets(s, 2..9, DEFAULT_LINE_BASE),
+            (
+                make_span(2..9, 1..3, 2..1),
+                make_span(2..7, 1..3, 2..1),
+                make_span(2..6, 1..3, 2..1)
+            )
         );
     }
 
+    #[test]
+    fn test_span_char() {
+        // Astral chars are 4 bytes, 2 utf16 code units, and 1 char
+        let s = "ab\n😀😀xy";
+        let (span_u8, span_u16, span_char) = Span::from_offsets(s, 11..12, DEFAULT_LINE_BASE);
+        assert_eq!(span_u8.start, Position::new(11, 2, 8));
+        assert_eq!(span_u16.start, Position::new(7, 2, 4));
+        assert_eq!(span_char.start, Position::new(5, 2, 2));
+
+        // Spans from the regex parser
+        let err: ReSyntax = regex_syntax::Parser::new()
+            .parse("😀😀(")
+            .unwrap_err()
+            .into();
+        assert_eq!(err.span.start.offset, 8);
+        assert_eq!(err.span_utf16.start.offset, 4);
+        assert_eq!(err.span_char.start.offset, 2);
+        assert_eq!(err.span_utf16.start.column, 5);
+        assert_eq!(err.span_char.start.column, 3);
+    }
+
+    #[test]
+    fn test_line_base() {
+        let s = "ab\ncd(";
+        let parse_err = || regex_syntax::Parser::new().parse(s).unwrap_err();
+        let span = Span::from_offsets(s, 3..4, DEFAULT_LINE_BASE).0;
+        let err: ReSyntax = parse_err().into();
+        assert_eq!((span.start.line, span.end.line), (2, 3));
+        assert_eq!(err.span.start.line, 2);
+        assert_eq!(err.span_utf16.start.line, 2);
+
+        // Every line number moves down by one, and nothing else changes
+        let span_zero = Span::from_offsets(s, 3..4, 0).0;
+        let err_zero = ReSyntax::new(parse_err(), 0);
+        assert_eq!((span_zero.start.line, span_zero.end.line), (1, 2));
+        assert_eq!(err_zero.span.start.line, 1);
+        assert_eq!(err_zero.span_utf16.start.line, 1);
+        assert_eq!(err_zero.span_char.end.line, 1);
+        assert_eq!(span_zero.start.column, span.start.column);
+        assert_eq!(err_zero.span.start.offset, err.span.start.offset);
+
+        // The default is unchanged by the calls above
+        let err: ReSyntax = parse_err().into();
+        assert_eq!(err.span.start.line, 2);
+    }
+
+    #[test]
+    fn test_valid_prefix_len() {
+        let err: ReSyntax = regex_syntax::Parser::new()
+            .parse("ab(cd")
+            .unwrap_err()
+            .into();
+        assert_eq!(err.kind, "GroupUnclosed");
+        assert_eq!(err.valid_prefix_len, 2);
+    }
+
+    #[test]
+    fn test_unsupported_lookaround() {
+        for (pat, kind, end) in [
+            ("a(?=x)", "lookahead", 4),
+            ("a(?!x)", "lookahead", 4),
+            ("(?<=x)a", "lookbehind", 4),
+        ] {
+            let err: Error = regex_syntax::Parser::new().parse(pat).unwrap_err().into();
+            let Error::UnsupportedLookaround(err) = err else {
+                panic!("expected lookaround error for {pat}, got {err:?}");
+            };
+            assert_eq!(err.kind, kind);
+            assert_eq!(err.span.end.offset, end);
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let render = |pat| {
+            let err: Error = regex_syntax::Parser::new().parse(pat).unwrap_err().into();
+            err.render(pat)
+        };
+
+        assert_eq!(render("ab)c"), "ab)c\n  ^\nunopened group");
+        assert_eq!(
+            render("(?P<x>a)(?P<x>b)"),
+            "(?P<x>a)(?P<x>b)\n    -       ^\nduplicate capture group name"
+        );
+        // Columns count chars, and only lines with a span are shown
+        assert_eq!(
+            render("é\néa{2,1}"),
+            "éa{2,1}\n  ^^^^^\ninvalid repetition count range, the start must be <= the end"
+        );
+        // Errors at the end of the pattern still get a mark
+        assert_eq!(render("a(b"), "a(b\n ^\nunclosed group");
+    }
+
+    #[test]
+    fn test_unescape_source() {
+        let unescape = Unescape::from(("a\\qb", 1..3, EscapeError::InvalidEscape));
+        let err = Error::from((unescape, UnescapeSource::Replacement));
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["errorClass"], "unescape");
+        assert_eq!(json["error"]["source"], "replacement");
+
+        let json = serde_json::to_value(UnescapeSource::RegExp).unwrap();
+        assert_eq!(json, "regExp");
+    }
+
     fn make_span(offset: Range<usize>, line: Range<usize>, column: Range<usize>) -> Span {
         Span::new(
             Position::new(offset.start, line.start, column.start),
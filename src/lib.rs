@@ -1,24 +1,84 @@
 //! Simple regex utility available via WASM
 
+mod engine;
 mod error;
+mod lint;
+mod pattern;
 mod strops;
 mod util;
 
 use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::iter::Peekable;
+use std::ops::Range;
 use std::str;
 
-use error::Error;
-use regex::bytes::{Regex, RegexBuilder};
-use serde::Serialize;
-use strops::{str_from_utf8_rep, unescape, utf16_index_bytes_slice};
+use engine::{Captures, Regex, ShortestSearcher};
+use error::{Error, InvalidReplacement, UnescapeSource};
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use strops::{
+    bom_len, collapse_whitespace, decode_hex, diff_range_utf16, expand_utf16_radius,
+    line_starts_utf16, lookup_index, push_csv_row, str_from_utf8_rep, unescape, unescape_pattern,
+    utf16_index_bytes, utf16_index_bytes_slice, utf16_len_bytes, utf16_prefix_len,
+    utf32_index_bytes_slice, utf8_rep_offset_map, StrType, MAX_RAW_HASHES,
+};
+use unicode_segmentation::GraphemeCursor;
 use wasm_bindgen::prelude::*;
 
+/// Default for `BuildOptions::max_repetition`. This is generous; it only
+/// exists to give a better error than the compiled size limit.
+const DEFAULT_MAX_REPETITION: u32 = 100_000;
+/// Version of `regex-automata` this is built with, read from `Cargo.lock` by
+/// the build script. `None` if there was no lockfile to read.
+const REGEX_AUTOMATA_VERSION: Option<&str> = option_env!("REGEX_AUTOMATA_VERSION");
+
 /// Representation of all matches in some text
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Serialize, Default, PartialEq)]
 #[serde(rename_all(serialize = "camelCase"))]
 struct MatchSer<'a> {
-    /// List of all matches. The inner vector is a list of all groups.
+    /// List of all matches. The inner vector is a list of all groups, in the
+    /// order described on `CapSer`.
     matches: Vec<Vec<CapSer<'a>>>,
+    /// For each match, whether each group participated. This is the same
+    /// information as `CapSer::is_participating`, but easier to consume.
+    participation: Vec<Vec<bool>>,
+    /// True if we stopped collecting matches early because of a limit in
+    /// `FindOptions`
+    truncated: bool,
+    /// Timing information, if requested with `FindOptions::stats`
+    stats: Option<StatsSer>,
+    /// Length of the searched text in bytes
+    text_len_utf8: usize,
+    /// Length of the searched text in utf16 code units, the same units as
+    /// `CapSer::end_utf16`
+    text_len_utf16: usize,
+    /// True if there are no matches because the pattern is empty, rather than
+    /// because nothing matched
+    pattern_empty: bool,
+    /// True if the pattern can match the empty string, which gives empty
+    /// matches between characters in a global search
+    matches_empty: bool,
+    /// Names of the flags that were applied, see `State::flags_applied`
+    flags_applied: Vec<&'static str>,
+    /// Number of matches before `FindOptions::offset` and `limit` were
+    /// applied, only counted if one of them is set
+    total: Option<usize>,
+    /// For each group index, the number of returned matches that it
+    /// participated in
+    group_usage: Vec<usize>,
+}
+
+/// Profiling information for a search
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct StatsSer {
+    /// Time spent finding matches, in milliseconds
+    elapsed_ms: f64,
+    /// Number of matches found
+    match_count: usize,
 }
 
 impl<'a> MatchSer<'a> {
@@ -27,18 +87,68 @@ impl<'a> MatchSer<'a> {
         serde_wasm_bindgen::to_value(self).expect("failed to serialize result")
     }
 
+    /// Add a single match, as created by `match_to_ser`
+    fn push_match(&mut self, match_: Vec<CapSer<'a>>) {
+        self.participation
+            .push(match_.iter().map(|cap| cap.is_participating).collect());
+        self.matches.push(match_);
+    }
+
+    /// Add a preview of the surrounding text to every entire match
+    fn add_previews(&mut self, text: &'a str, radius: usize) {
+        for cap_ser in self
+            .matches
+            .iter_mut()
+            .filter_map(|match_| match_.first_mut())
+        {
+            let (Some(start), Some(end)) = (cap_ser.start, cap_ser.end) else {
+                continue;
+            };
+            let range = expand_utf16_radius(text, start, end, radius);
+            let before_len = text.floor_char_boundary(start) - range.start;
+            let preview = &text[range];
+            cap_ser.preview = Some(preview);
+            cap_ser.preview_offset_utf16 = Some(utf16_index_bytes(preview, before_len));
+        }
+    }
+
+    /// Add up to `count` chars on either side of every entire match. A match
+    /// that splits a char doesn't count that char as a neighbor.
+    fn add_neighbors(&mut self, text: &'a str, count: usize) {
+        for cap_ser in self
+            .matches
+            .iter_mut()
+            .filter_map(|match_| match_.first_mut())
+        {
+            let (Some(start), Some(end)) = (cap_ser.start, cap_ser.end) else {
+                continue;
+            };
+            let start = text.floor_char_boundary(start);
+            let end = text.ceil_char_boundary(end);
+            let before_start = text[..start]
+                .char_indices()
+                .rev()
+                .take(count)
+                .last()
+                .map_or(start, |(idx, _)| idx);
+            let after_end = text[end..]
+                .char_indices()
+                .nth(count)
+                .map_or(text.len(), |(idx, _)| end + idx);
+            cap_ser.before = Some(&text[before_start..start]);
+            cap_ser.after = Some(&text[end..after_end]);
+        }
+    }
+
     /// For all matches, set indices to utf16 for the given text
-    fn update_indices_utf16(&mut self, text: &str, indices: Vec<usize>) {
+    fn update_indices_utf16(&mut self, text: &str) {
+        // Collect all our utf8 indices to convert them at once
+        let indices = self.utf8_indices();
+
         // Get our indices from the text
         let matched_indices = utf16_index_bytes_slice(text, indices);
 
-        // convenience closure; find the correct element by binary search
-        let find_idx = |search| {
-            matched_indices[matched_indices
-                .binary_search_by_key(&search, |(idxu8, _)| *idxu8)
-                .unwrap()]
-            .1
-        };
+        let find_idx = |search| lookup_index(&matched_indices, search);
 
         for cap_ser in self.matches.iter_mut().flatten() {
             if let Some(start) = cap_ser.start {
@@ -48,15 +158,274 @@ impl<'a> MatchSer<'a> {
                 cap_ser.end_utf16 = Some(find_idx(end));
             }
         }
+
+        self.update_relative_indices();
+    }
+
+    /// All utf8 start and end indices of all groups, unsorted. Zero-width and
+    /// adjacent matches give the same index several times in a row, so those
+    /// are only kept once to save sorting work later.
+    fn utf8_indices(&self) -> Vec<usize> {
+        let mut indices = Vec::new();
+        let all = self
+            .matches
+            .iter()
+            .flatten()
+            .flat_map(|cap_ser| cap_ser.start.into_iter().chain(cap_ser.end));
+        for idx in all {
+            if indices.last() != Some(&idx) {
+                indices.push(idx);
+            }
+        }
+        indices
+    }
+
+    /// Set the utf16 position of each group relative to the start of its
+    /// entire match. Requires utf16 indices to be set.
+    fn update_relative_indices(&mut self) {
+        for match_ in &mut self.matches {
+            let Some((entire, groups)) = match_.split_first_mut() else {
+                continue;
+            };
+            let Some(match_start) = entire.start_utf16 else {
+                continue;
+            };
+            for cap_ser in groups {
+                // Groups lie within the entire match, unless it was trimmed
+                // with `FindOptions::trim`
+                cap_ser.rel_start_utf16 = cap_ser
+                    .start_utf16
+                    .and_then(|start| start.checked_sub(match_start));
+                cap_ser.rel_end_utf16 = cap_ser
+                    .end_utf16
+                    .and_then(|end| end.checked_sub(match_start));
+            }
+        }
+    }
+
+    /// Give every group that doesn't participate an empty span at the start
+    /// of the entire match, in every kind of offset that is set. Runs after
+    /// all offsets are converted.
+    fn fill_nonparticipating(&mut self) {
+        for match_ in &mut self.matches {
+            let Some((entire, groups)) = match_.split_first_mut() else {
+                continue;
+            };
+            for cap_ser in groups.iter_mut().filter(|cap| !cap.is_participating) {
+                (cap_ser.start, cap_ser.end) = (entire.start, entire.start);
+                (cap_ser.start_utf16, cap_ser.end_utf16) = (entire.start_utf16, entire.start_utf16);
+                (cap_ser.start_char, cap_ser.end_char) = (entire.start_char, entire.start_char);
+                let rel = entire.start_utf16.map(|_| 0);
+                (cap_ser.rel_start_utf16, cap_ser.rel_end_utf16) = (rel, rel);
+            }
+        }
+    }
+
+    /// Set `content_offsets` for every participating group
+    fn add_content_offsets(&mut self, text: &[u8]) {
+        for cap_ser in self.matches.iter_mut().flatten() {
+            if let (Some(start), Some(end)) = (cap_ser.start, cap_ser.end) {
+                cap_ser.content_offsets = Some(utf8_rep_offset_map(text, start, end));
+            }
+        }
+    }
+
+    /// Give every participating group other than the entire match its content
+    /// with invalid utf8 replaced by U+FFFD, rather than escaped. Groups that
+    /// had invalid utf8 lose their `content_offsets`, which only describe
+    /// escaped content.
+    fn lossy_groups(&mut self, text: &[u8]) {
+        for cap_ser in self.matches.iter_mut().flatten() {
+            if cap_ser.group_num == 0 {
+                continue;
+            }
+            if let (Some(start), Some(end)) = (cap_ser.start, cap_ser.end) {
+                let lossy = String::from_utf8_lossy(&text[start..end]);
+                if let Cow::Owned(content) = lossy {
+                    cap_ser.content = Some(Cow::Owned(content));
+                    cap_ser.content_offsets = None;
+                }
+            }
+        }
+    }
+
+    /// Set `display_content` for every participating group
+    fn add_display_content(&mut self) {
+        for cap_ser in self.matches.iter_mut().flatten() {
+            cap_ser.display_content = cap_ser.content.as_deref().map(collapse_whitespace);
+        }
+    }
+
+    /// Move the start and end of every entire match inward past whitespace,
+    /// updating its content. Only utf8 indices are changed, so this must run
+    /// before any conversions. A match that is all whitespace becomes empty
+    /// at its start.
+    fn trim_matches(&mut self, text: &'a [u8]) {
+        for cap_ser in self
+            .matches
+            .iter_mut()
+            .filter_map(|match_| match_.first_mut())
+        {
+            let (Some(start), Some(end)) = (cap_ser.start, cap_ser.end) else {
+                continue;
+            };
+            let bytes = &text[start..end];
+            // A match that splits a char can only have ASCII whitespace
+            // trimmed
+            let (lead, trail) = match str::from_utf8(bytes) {
+                Ok(s) => (s.len() - s.trim_start().len(), s.len() - s.trim_end().len()),
+                Err(_) => (
+                    bytes.iter().take_while(|b| b.is_ascii_whitespace()).count(),
+                    bytes
+                        .iter()
+                        .rev()
+                        .take_while(|b| b.is_ascii_whitespace())
+                        .count(),
+                ),
+            };
+            let new_end = (end - trail).max(start);
+            let new_start = (start + lead).min(new_end);
+            cap_ser.start = Some(new_start);
+            cap_ser.end = Some(new_end);
+            cap_ser.content = Some(str_from_utf8_rep(text, new_start, new_end));
+            cap_ser.line_span = Some(line_span(&text[new_start..new_end]));
+        }
+    }
+
+    /// Move the start and end of every entire match outward to the nearest
+    /// grapheme cluster boundaries, updating its content. Like `trim_matches`,
+    /// this only changes utf8 indices.
+    fn snap_graphemes(&mut self, text: &'a str) {
+        let snap = |offset: usize, forward: bool| {
+            let offset = if forward {
+                text.ceil_char_boundary(offset)
+            } else {
+                text.floor_char_boundary(offset)
+            };
+            let mut cursor = GraphemeCursor::new(offset, text.len(), true);
+            // The whole text is one chunk, so there is always enough context
+            if cursor.is_boundary(text, 0).unwrap() {
+                return offset;
+            }
+            let next = if forward {
+                cursor.next_boundary(text, 0)
+            } else {
+                cursor.prev_boundary(text, 0)
+            };
+            next.unwrap().expect("the ends of the text are boundaries")
+        };
+
+        for cap_ser in self
+            .matches
+            .iter_mut()
+            .filter_map(|match_| match_.first_mut())
+        {
+            let (Some(start), Some(end)) = (cap_ser.start, cap_ser.end) else {
+                continue;
+            };
+            let (new_start, new_end) = (snap(start, false), snap(end, true));
+            cap_ser.start = Some(new_start);
+            cap_ser.end = Some(new_end);
+            cap_ser.content = Some(Cow::Borrowed(&text[new_start..new_end]));
+            cap_ser.line_span = Some(line_span(&text.as_bytes()[new_start..new_end]));
+        }
+    }
+
+    /// Set `number_value` for every participating group whose content is a
+    /// finite integer or float
+    fn add_number_values(&mut self) {
+        for cap_ser in self.matches.iter_mut().flatten() {
+            cap_ser.number_value = cap_ser
+                .content
+                .as_deref()
+                .and_then(|content| content.parse::<f64>().ok())
+                .filter(|value| value.is_finite());
+        }
+    }
+
+    /// Cut the content of every participating group to at most `max` utf16
+    /// code units plus an ellipsis. This runs last so that other fields are
+    /// based on the full content; `content_offsets` is cut to match.
+    fn truncate_content(&mut self, max: usize) {
+        for cap_ser in self.matches.iter_mut().flatten() {
+            let Some(content) = &mut cap_ser.content else {
+                continue;
+            };
+            let cut = utf16_prefix_len(content, max);
+            if let Some(cut) = cut {
+                let kept_len = utf16_index_bytes(content, cut);
+                *content = Cow::Owned(format!("{}…", &content[..cut]));
+                if let Some(offsets) = &mut cap_ser.content_offsets {
+                    offsets.truncate(kept_len + 1);
+                }
+            }
+            cap_ser.truncated = Some(cut.is_some());
+        }
+    }
+
+    /// Move all utf8 indices forward by `by` bytes, for matches that were
+    /// found in a suffix of the text
+    fn shift_indices(&mut self, by: usize) {
+        if by == 0 {
+            return;
+        }
+        for cap_ser in self.matches.iter_mut().flatten() {
+            cap_ser.start = cap_ser.start.map(|start| start + by);
+            cap_ser.end = cap_ser.end.map(|end| end + by);
+        }
+    }
+
+    /// Set char indices for all matches, based on the utf8 indices
+    fn update_indices_char(&mut self, text: &str) {
+        let indices: Vec<usize> = self
+            .matches
+            .iter()
+            .flatten()
+            .flat_map(|cap_ser| cap_ser.start.into_iter().chain(cap_ser.end))
+            .collect();
+        let matched_indices = utf32_index_bytes_slice(text, indices);
+        let find_idx = |search| lookup_index(&matched_indices, search);
+
+        for cap_ser in self.matches.iter_mut().flatten() {
+            cap_ser.start_char = cap_ser.start.map(find_idx);
+            cap_ser.end_char = cap_ser.end.map(find_idx);
+        }
+    }
+
+    /// For matches on bytes decoded from a hex string, set utf16 indices to
+    /// point at the hex pairs. `pair_offsets` is the position of each pair in
+    /// the hex string, which is ASCII.
+    fn update_indices_hex(&mut self, hex_len: usize, pair_offsets: &[usize]) {
+        let start_idx = |start: usize| pair_offsets.get(start).copied().unwrap_or(hex_len);
+
+        for cap_ser in self.matches.iter_mut().flatten() {
+            let (Some(start), Some(end)) = (cap_ser.start, cap_ser.end) else {
+                continue;
+            };
+            cap_ser.start_utf16 = Some(start_idx(start));
+            // End after the last matched pair, rather than before the next one
+            cap_ser.end_utf16 = Some(if end > start {
+                pair_offsets[end - 1] + 2
+            } else {
+                start_idx(start)
+            });
+        }
+
+        self.update_relative_indices();
     }
 }
 
 /// Result of a replacement. The purpose of this struct is just to wrap the
 /// string within a "result" key for the JS result.
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Serialize, Default, PartialEq)]
 #[serde(rename_all(serialize = "camelCase"))]
 struct ReplacdSer<'a> {
-    result: &'a str,
+    result: Cow<'a, str>,
+    /// Names of the flags that were applied, see `State::flags_applied`
+    flags_applied: Vec<&'static str>,
+    /// Whether anything was replaced. This tells a result that is empty
+    /// because every match was deleted apart from an empty pattern.
+    had_matches: bool,
 }
 
 impl<'a> ReplacdSer<'a> {
@@ -66,12 +435,293 @@ impl<'a> ReplacdSer<'a> {
     }
 }
 
+/// Result of `re_run_impl`, which is the result of whichever operation ran
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(untagged)]
+enum RunSer<'a> {
+    Find(MatchSer<'a>),
+    Replace(ReplacdSer<'a>),
+    Split(Vec<Cow<'a, str>>),
+    IsMatch(bool),
+}
+
+/// Result of `re_replace_bounds_impl`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct ReplaceBoundsSer<'a> {
+    result: Cow<'a, str>,
+    /// Utf16 offset of the first difference between the text and `result`,
+    /// `None` if they are the same
+    first_diff_utf16: Option<usize>,
+    /// Utf16 offset in the text where the unchanged end starts
+    last_diff_utf16: Option<usize>,
+}
+
+/// Whether a pattern compiles with one set of flags, see
+/// `re_validate_all_flags_impl`
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct FlagValiditySer {
+    flags: String,
+    valid: bool,
+    error: Option<Error>,
+}
+
+/// Result of a single replacement step
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct ReplaceStepSer {
+    /// Text with at most one replacement applied
+    result: String,
+    /// Offset in `result` to continue from, or `None` if nothing was replaced
+    next_offset: Option<usize>,
+}
+
+/// A participating capture group in `re_find_tree`, with the groups that lie
+/// within it as children
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct GroupNodeSer<'a> {
+    group_name: Option<String>,
+    group_num: usize,
+    content: Cow<'a, str>,
+    start: usize,
+    end: usize,
+    start_utf16: usize,
+    end_utf16: usize,
+    /// Groups contained in this one, ordered by start position
+    children: Vec<GroupNodeSer<'a>>,
+}
+
+impl<'a> GroupNodeSer<'a> {
+    /// Build the tree for a single match, dropping groups that don't
+    /// participate. A group is a child of the smallest group whose span
+    /// contains its own; if two spans are the same, the lower group number is
+    /// the parent.
+    fn from_match(match_: Vec<CapSer<'a>>) -> Self {
+        let mut nodes: Vec<Self> = match_
+            .into_iter()
+            .filter_map(|cap| {
+                Some(Self {
+                    group_name: cap.group_name,
+                    group_num: cap.group_num,
+                    content: cap.content?,
+                    start: cap.start?,
+                    end: cap.end?,
+                    start_utf16: cap.start_utf16?,
+                    end_utf16: cap.end_utf16?,
+                    children: Vec::new(),
+                })
+            })
+            .collect();
+        // Parents sort before anything they contain, so the entire match is
+        // first
+        nodes.sort_by_key(|node| (node.start, Reverse(node.end), node.group_num));
+
+        let mut nodes = nodes.into_iter().peekable();
+        let mut root = nodes.next().expect("the entire match always participates");
+        root.add_children(&mut nodes);
+        root
+    }
+
+    /// Take nodes from `nodes` while they are contained in this one. Nodes
+    /// are sorted so everything left starts at or after this one.
+    fn add_children(&mut self, nodes: &mut Peekable<impl Iterator<Item = Self>>) {
+        while let Some(mut child) = nodes.next_if(|node| node.end <= self.end) {
+            child.add_children(nodes);
+            self.children.push(child);
+        }
+    }
+}
+
+/// A match in the shape of a JS `RegExpMatchArray`, see `re_find_jsmatch_impl`
+#[derive(Debug, PartialEq)]
+struct JsMatchSer<'a> {
+    /// Content of each group by index, `None` if it didn't participate
+    indexed: Vec<Option<Cow<'a, str>>>,
+    /// Utf16 offset of the match
+    index: usize,
+    /// Content of each named group, `None` if there are no named groups
+    groups: Option<Vec<(String, Option<Cow<'a, str>>)>>,
+}
+
+impl<'a> JsMatchSer<'a> {
+    fn from_match(match_: Vec<CapSer<'a>>) -> Self {
+        let index = match_[0]
+            .start_utf16
+            .expect("the entire match always participates");
+        let named: Vec<_> = match_
+            .iter()
+            .filter_map(|cap| Some((cap.group_name.clone()?, cap.content.clone())))
+            .collect();
+        Self {
+            indexed: match_.into_iter().map(|cap| cap.content).collect(),
+            index,
+            groups: (!named.is_empty()).then_some(named),
+        }
+    }
+}
+
+impl Serialize for JsMatchSer<'_> {
+    /// Serialize as an object with a key for each index, plus `length`,
+    /// `index`, and `groups`, like the properties of a JS match
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        /// Named groups as an object rather than a list of pairs
+        struct Groups<'b, 'a>(&'b [(String, Option<Cow<'a, str>>)]);
+
+        impl Serialize for Groups<'_, '_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().map(|(name, content)| (name, content)))
+            }
+        }
+
+        let mut map = serializer.serialize_map(Some(self.indexed.len() + 3))?;
+        for (i, content) in self.indexed.iter().enumerate() {
+            map.serialize_entry(&i.to_string(), content)?;
+        }
+        map.serialize_entry("length", &self.indexed.len())?;
+        map.serialize_entry("index", &self.index)?;
+        map.serialize_entry("groups", &self.groups.as_deref().map(Groups))?;
+        map.end()
+    }
+}
+
+/// One range of a character class, see `re_class_ranges_impl`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct ClassRangeSer {
+    /// First code point in the range (or byte, for a class without `u`)
+    start_codepoint: u32,
+    /// Last code point in the range, inclusive
+    end_codepoint: u32,
+}
+
+/// Bounds on the length of a match, see `re_fixed_len_impl`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct FixedLenSer {
+    /// Shortest possible match in bytes, `None` if the pattern can't match
+    min: Option<usize>,
+    /// Longest possible match in bytes, `None` if unbounded
+    max: Option<usize>,
+}
+
+/// Matches within a single line, see `re_find_in_lines_impl`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct LineFindSer<'a> {
+    /// Index of the line in the list, starting at 0
+    line: usize,
+    /// Matches in the same format as `MatchSer::matches`, with offsets
+    /// relative to the start of the line
+    matches: Vec<Vec<CapSer<'a>>>,
+}
+
+/// Result of `SegmentedText::find_impl`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct SegmentsFindSer<'a> {
+    /// Matches as if the segments were one text, in the format of `re_find`
+    result: MatchSer<'a>,
+    /// Where each entire match starts and ends within the segments, in the
+    /// same order as `result.matches`
+    spans: Vec<SegmentSpanSer>,
+}
+
+/// The location of a match within the segments it covers. A match that
+/// crosses a boundary covers every segment from `start_segment` to
+/// `end_segment`.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct SegmentSpanSer {
+    /// Index of the segment the match starts in
+    start_segment: usize,
+    /// Start of the match within that segment, in utf16
+    start_offset_utf16: usize,
+    /// Index of the segment with the last char of the match. An empty match
+    /// ends in the segment it starts in.
+    end_segment: usize,
+    /// End of the match within that segment, in utf16
+    end_offset_utf16: usize,
+}
+
+/// A line of text that the pattern matches in full
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct LineMatchSer<'a> {
+    /// Line number, starting at `BuildOptions::line_base`
+    line: usize,
+    /// Content of the line, without its `\n`
+    content: &'a str,
+}
+
+/// Result of `re_test_cases_impl`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct TestCasesSer {
+    /// One result for each case, in the same order
+    cases: Vec<TestCaseSer>,
+    /// True if every case passed
+    passed: bool,
+}
+
+/// Result of a single case in `TestCasesSer`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct TestCaseSer {
+    /// Whether the pattern matches anywhere in the case's text
+    matched: bool,
+    /// Whether `matched` is what the case expected
+    passed: bool,
+}
+
+/// How a single replacement changes the text, see `re_replace_offsets_impl`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct ReplaceOffsetSer {
+    /// Start of the replaced match in the original text, in utf16
+    start_utf16: usize,
+    /// End of the replaced match in the original text, in utf16
+    end_utf16: usize,
+    /// Length of the replacement minus the length of the match, in utf16.
+    /// Offsets after the match move by this much.
+    delta: isize,
+}
+
+/// A piece of text that either is or isn't part of a match
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct SegmentSer<'a> {
+    text: Cow<'a, str>,
+    is_match: bool,
+}
+
+/// The expanded replacement for one match, see `re_replace_list_tagged_impl`
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct TaggedOutputSer {
+    /// Index of the match that was replaced
+    source_match: usize,
+    /// Range of the match in the original text, in utf16
+    source_span_utf16: Range<usize>,
+    /// The expanded replacement
+    output: String,
+}
+
 /// Representation of a single capture group
-#[derive(Debug, Serialize, Default)]
+///
+/// Within a match, groups are always in capture index order: the entire match
+/// is first with `group_num` 0, then each group in the order of its opening
+/// parenthesis in the pattern, so `group_num` equals the position in the list.
+/// Groups that don't participate are still included. The only exception is
+/// `FindOptions::innermost_only`, which leaves groups out but keeps the order.
+#[derive(Debug, Serialize, Default, PartialEq)]
 #[serde(rename_all(serialize = "camelCase"))]
 struct CapSer<'a> {
     /// Optional name of the capture group
-    group_name: Option<&'a str>,
+    group_name: Option<String>,
     /// Index of the match within all matches
     #[serde(rename = "match")]
     match_num: usize,
@@ -80,7 +730,8 @@ struct CapSer<'a> {
     /// Whether or not an optional group is found within the match
     is_participating: bool,
     /// Whether or not this capture group represents the entire match (this will
-    /// be the first capture group within its list)
+    /// be the first capture group within its list, unless
+    /// `FindOptions::primary_group` picks another)
     entire_match: bool,
 
     /* below fields only exist if is_participating */
@@ -94,6 +745,469 @@ struct CapSer<'a> {
     end_utf16: Option<usize>,
     /// End index as a utf8 array
     end: Option<usize>,
+    /// Start index in chars, see `FindOptions::char_offsets`
+    start_char: Option<usize>,
+    /// End index in chars
+    end_char: Option<usize>,
+    /// Start index in utf16, relative to the start of the entire match. Not set
+    /// for the entire match itself.
+    rel_start_utf16: Option<usize>,
+    /// End index in utf16, relative to the start of the entire match
+    rel_end_utf16: Option<usize>,
+
+    /// For each utf16 code unit of `content`, the byte offset in the text that
+    /// it came from, plus the end offset. See `FindOptions::content_offsets`.
+    content_offsets: Option<Vec<usize>>,
+    /// `content` with runs of whitespace collapsed to a single space, see
+    /// `FindOptions::normalize_whitespace`
+    display_content: Option<String>,
+    /// `content` as a number, if it is one, see `FindOptions::coerce_numbers`
+    number_value: Option<f64>,
+    /// Whether `content` was cut short, see `FindOptions::max_content_len`
+    truncated: Option<bool>,
+
+    /* below fields only exist for the entire match, if requested */
+    /// Text surrounding the match, see `FindOptions::preview_radius`
+    preview: Option<&'a str>,
+    /// Start of the match within `preview`, in utf16
+    preview_offset_utf16: Option<usize>,
+    /// Chars just before the match, see `FindOptions::neighbors`
+    before: Option<&'a str>,
+    /// Chars just after the match
+    after: Option<&'a str>,
+    /// Number of lines the match covers: one more than the number of `\n`
+    /// in it
+    line_span: Option<usize>,
+}
+
+/// Result of `re_find_utf16`. This is `MatchSer` with only utf16 offsets, for
+/// callers that never deal with utf8.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct MatchUtf16Ser<'a> {
+    matches: Vec<Vec<CapUtf16Ser<'a>>>,
+    participation: Vec<Vec<bool>>,
+    truncated: bool,
+    stats: Option<StatsSer>,
+    /// Length of the searched text in utf16 code units
+    text_len: usize,
+    pattern_empty: bool,
+    matches_empty: bool,
+    flags_applied: Vec<&'static str>,
+    total: Option<usize>,
+    group_usage: Vec<usize>,
+}
+
+/// A capture group within `MatchUtf16Ser`. `start` and `end` are the utf16
+/// offsets that are `start_utf16` and `end_utf16` in `CapSer`.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct CapUtf16Ser<'a> {
+    group_name: Option<String>,
+    #[serde(rename = "match")]
+    match_num: usize,
+    group_num: usize,
+    is_participating: bool,
+    entire_match: bool,
+    content: Option<Cow<'a, str>>,
+    display_content: Option<String>,
+    number_value: Option<f64>,
+    truncated: Option<bool>,
+    start: Option<usize>,
+    end: Option<usize>,
+    rel_start: Option<usize>,
+    rel_end: Option<usize>,
+    preview: Option<&'a str>,
+    preview_offset: Option<usize>,
+    before: Option<&'a str>,
+    after: Option<&'a str>,
+    line_span: Option<usize>,
+}
+
+impl<'a> From<MatchSer<'a>> for MatchUtf16Ser<'a> {
+    fn from(res: MatchSer<'a>) -> Self {
+        Self {
+            matches: res
+                .matches
+                .into_iter()
+                .map(|match_| match_.into_iter().map(CapUtf16Ser::from).collect())
+                .collect(),
+            participation: res.participation,
+            truncated: res.truncated,
+            stats: res.stats,
+            text_len: res.text_len_utf16,
+            pattern_empty: res.pattern_empty,
+            matches_empty: res.matches_empty,
+            flags_applied: res.flags_applied,
+            total: res.total,
+            group_usage: res.group_usage,
+        }
+    }
+}
+
+impl<'a> From<CapSer<'a>> for CapUtf16Ser<'a> {
+    fn from(cap: CapSer<'a>) -> Self {
+        Self {
+            group_name: cap.group_name,
+            match_num: cap.match_num,
+            group_num: cap.group_num,
+            is_participating: cap.is_participating,
+            entire_match: cap.entire_match,
+            content: cap.content,
+            display_content: cap.display_content,
+            number_value: cap.number_value,
+            truncated: cap.truncated,
+            start: cap.start_utf16,
+            end: cap.end_utf16,
+            rel_start: cap.rel_start_utf16,
+            rel_end: cap.rel_end_utf16,
+            preview: cap.preview,
+            preview_offset: cap.preview_offset_utf16,
+            before: cap.before,
+            after: cap.after,
+            line_span: cap.line_span,
+        }
+    }
+}
+
+/// Optional settings for `re_find`. These are passed from js as an object with
+/// camelCase keys; any keys that are left out take their default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"), default)]
+struct FindOptions {
+    /// Settings that affect how the pattern is compiled
+    #[serde(flatten)]
+    build: BuildOptions,
+    /// Stop collecting matches before the total byte length of all `content`
+    /// fields would exceed this value
+    max_content_bytes: Option<usize>,
+    /// If set, include a preview of the text around each match with up to this
+    /// many utf16 code units on either side. An error with hex text.
+    preview_radius: Option<usize>,
+    /// Time how long matching takes and include it in the result
+    stats: bool,
+    /// Drop matches whose entire match has the same content as an earlier
+    /// one. `match` numbers count only the matches that are kept.
+    dedup: bool,
+    /// Also give match positions counted in chars (unicode scalar values). An
+    /// error with hex text.
+    char_offsets: bool,
+    /// Include `content_offsets` for every group, to map positions in content
+    /// that has escaped invalid utf8 back to the text. With hex text, these
+    /// are offsets in the decoded bytes.
+    content_offsets: bool,
+    /// Report the shortest match at each position, rather than the one chosen
+    /// by the usual leftmost-first rules
+    shortest: bool,
+    /// Only accept a match that starts at the beginning of the text (or at
+    /// `range_start`), without
+    /// requiring it to extend to the end. Unlike `^`, this isn't affected by
+    /// the `m` flag.
+    anchored_start: bool,
+    /// Keep only the entire match and the participating group with the
+    /// highest index, which is the innermost one for nested groups
+    innermost_only: bool,
+    /// Keep only the first match that starts on each line
+    one_per_line: bool,
+    /// Include `display_content` for every group, with whitespace collapsed
+    normalize_whitespace: bool,
+    /// Group (by name or number) to mark with `entire_match` instead of group 0
+    primary_group: Option<String>,
+    /// Only search from this utf8 offset. Text before it is still visible to
+    /// assertions like `^` and `\b`. With hex text, this is an offset in the
+    /// decoded bytes.
+    range_start: Option<usize>,
+    /// Only search up to this utf8 offset. Matches can't extend past it, and
+    /// it acts like the end of the text for assertions. With hex text, this is
+    /// an offset in the decoded bytes.
+    range_end: Option<usize>,
+    /// Ignore a leading byte order mark when matching. Offsets still refer to
+    /// the text including the BOM. An error with hex text.
+    strip_bom: bool,
+    /// Move the start and end of each entire match inward past any whitespace
+    /// it starts or ends with. Other groups are unchanged.
+    trim: bool,
+    /// Widen each entire match to the nearest grapheme cluster boundaries, so
+    /// highlighting never splits a cluster. Applied after `trim`. An error
+    /// with hex text.
+    snap_graphemes: bool,
+    /// Add the value of each group whose content is a number, such as `42`,
+    /// `-1.5`, or `1e3`. Infinities and NaN are not counted as numbers.
+    coerce_numbers: bool,
+    /// Add the text of up to this many chars just before and after each
+    /// entire match, stopping at the ends of the text. An error with hex text.
+    neighbors: usize,
+    /// Skip this many matches, for paging through results
+    offset: usize,
+    /// Return at most this many matches after `offset`. Setting this or
+    /// `offset` adds `MatchSer::total`, which means finding every match.
+    limit: Option<usize>,
+    /// Return `Error::TextTooLong` for text longer than this many utf8 bytes
+    max_text_len: Option<usize>,
+    /// Cut the `content` of every group to at most this many utf16 code units,
+    /// ending it with `…` and setting `truncated`. Offsets still cover the
+    /// whole group.
+    max_content_len: Option<usize>,
+    /// Skip any match that overlaps one of these `[start, end)` utf16 ranges,
+    /// e.g. parts of the text that are already highlighted. An empty match
+    /// overlaps a range if it is at the range's start or inside it. An error
+    /// with hex text.
+    exclude_ranges: Vec<(usize, usize)>,
+    /// Give groups that don't participate an empty span at the start of their
+    /// match, rather than no offsets. They still have `is_participating` set
+    /// to false and no content, which tells them apart from real empty groups.
+    sentinel_nonparticipating: bool,
+    /// Whether groups other than the entire match escape invalid utf8 in their
+    /// content like `\xff`, the same as the entire match. If false, invalid
+    /// utf8 in those groups becomes U+FFFD. Defaults to true.
+    escape_groups: Option<bool>,
+}
+
+/// Options for `re_replace`, passed from js as an object
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"), default)]
+struct ReplaceOptions {
+    #[serde(flatten)]
+    build: BuildOptions,
+    /// Ignore the replacement template and instead overwrite each match with
+    /// this char, repeated so the result has the same UTF-16 length
+    redact: Option<char>,
+    /// With `redact`, only overwrite this group (by name or number) rather
+    /// than the entire match
+    redact_group: Option<String>,
+    /// Ignore a leading byte order mark when matching. It is kept in the
+    /// result.
+    strip_bom: bool,
+    /// How to handle a result that isn't valid utf8
+    on_invalid_utf8: OnInvalidUtf8,
+    /// Return an error for a `$` in the replacement that isn't `$$` or a
+    /// group reference, rather than leaving it as a literal `$`
+    strict: bool,
+    /// Names that can be used in the replacement template in place of a group
+    /// name or number. Every alias must refer to a group in the pattern.
+    alias: HashMap<String, String>,
+    /// Return `Error::TextTooLong` for text longer than this many utf8 bytes
+    max_text_len: Option<usize>,
+    /// Open and close markers to wrap around the expanded output of every
+    /// replacement (or each redaction), so edits can be found in the result
+    mark: Option<(String, String)>,
+}
+
+/// What to do if a replacement result isn't valid utf8, which can happen if a
+/// match without the `u` flag splits a multibyte char
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+enum OnInvalidUtf8 {
+    /// Use the replacement character U+FFFD
+    #[default]
+    Lossy,
+    /// Return `Error::InvalidUtf8Result`
+    Error,
+    /// Escape invalid bytes like `\xff`, the same as match content
+    Escape,
+}
+
+impl OnInvalidUtf8 {
+    /// Turn the bytes of a result into a string
+    fn convert(self, bytes: Vec<u8>) -> Result<String, Error> {
+        let err = match String::from_utf8(bytes) {
+            Ok(s) => return Ok(s),
+            Err(e) => e,
+        };
+        match self {
+            Self::Lossy => Ok(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+            Self::Error => Err(Error::InvalidUtf8Result {
+                offset: err.utf8_error().valid_up_to(),
+            }),
+            Self::Escape => {
+                let bytes = err.as_bytes();
+                Ok(str_from_utf8_rep(bytes, 0, bytes.len()).into_owned())
+            }
+        }
+    }
+}
+
+/// Options for `re_replace_list`, passed from js as an object
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"), default)]
+struct ReplaceListOptions {
+    #[serde(flatten)]
+    build: BuildOptions,
+    /// Return segments that include the text between matches, rather than a
+    /// single string of replacements
+    include_gaps: bool,
+    /// Return each replacement separately, tagged with the match it came
+    /// from. Can't be combined with `include_gaps`.
+    tagged: bool,
+    /// How to handle a result that isn't valid utf8
+    on_invalid_utf8: OnInvalidUtf8,
+}
+
+/// Settings that affect how a pattern gets compiled, beyond what is available
+/// with flags. These are flattened into the options of each function.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"), default)]
+struct BuildOptions {
+    /// Byte to use in place of `\n` as the line terminator for `^`, `$`, and
+    /// `.`. Must be ASCII.
+    line_terminator: Option<u8>,
+    /// Match the pattern as plain text, as if every metacharacter were escaped
+    literal: bool,
+    /// Largest bound allowed in a counted repetition like `a{n}`. Defaults to
+    /// `DEFAULT_MAX_REPETITION`.
+    max_repetition: Option<u32>,
+    /// Approximate limit in bytes for the compiled program. Uses the regex
+    /// crate's default if unset.
+    size_limit: Option<usize>,
+    /// Number of the first line, for line numbers in errors from the pattern
+    /// and in results. Defaults to `error::DEFAULT_LINE_BASE`, which is 1;
+    /// editors that count lines from 0 can set 0.
+    line_base: Option<usize>,
+}
+
+impl BuildOptions {
+    /// The number of the first line, see `line_base`
+    fn line_base(&self) -> usize {
+        self.line_base.unwrap_or(error::DEFAULT_LINE_BASE)
+    }
+}
+
+/// Every flag letter in canonical order, with its stable name. The names are
+/// used in `State::flags_applied` and as the keys of `RegexFlags`.
+const FLAGS: [(char, &str); 9] = [
+    ('g', "global"),
+    ('A', "asciiCaseInsensitive"),
+    ('i', "caseInsensitive"),
+    ('m', "multiLine"),
+    ('o', "octal"),
+    ('s', "dotMatchesNewLine"),
+    ('U', "swapGreed"),
+    ('u', "unicode"),
+    ('x', "ignoreWhitespace"),
+];
+
+/// Look up the stable name of a flag letter
+fn flag_name(flag: char) -> Result<&'static str, Error> {
+    FLAGS
+        .iter()
+        .find(|(letter, _)| *letter == flag)
+        .map(|(_, name)| *name)
+        .ok_or_else(|| unknown_flag(flag))
+}
+
+/// Error for a letter that isn't in `FLAGS`
+fn unknown_flag(flag: char) -> Error {
+    Error::InvalidOptions(format!("unknown flag '{flag}'"))
+}
+
+/// What this module supports, from `re_capabilities_impl`. Fields are only
+/// added, never removed, so clients can use this for feature detection.
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+struct CapabilitiesSer {
+    /// Every accepted flag, in the order of `FLAGS`
+    flags: Vec<FlagCapabilitySer>,
+    /// Always false, the regex crate has no lookahead or lookbehind
+    lookaround: bool,
+    /// Always false, the regex crate has no backreferences
+    backreferences: bool,
+    /// Version of `regex-automata`, the regex crate's engine, which we use
+    /// for matching, if it is known
+    regex_automata_version: Option<&'static str>,
+    /// Most hashes `rawauto` will use, i.e. the largest `rawhashN`
+    max_raw_hashes: usize,
+}
+
+/// One accepted flag, see `FLAGS`
+#[derive(Debug, Serialize)]
+struct FlagCapabilitySer {
+    flag: char,
+    name: &'static str,
+}
+
+/// Describe the engine and the features that are supported
+fn re_capabilities_impl() -> CapabilitiesSer {
+    CapabilitiesSer {
+        flags: FLAGS
+            .iter()
+            .map(|&(flag, name)| FlagCapabilitySer { flag, name })
+            .collect(),
+        lookaround: false,
+        backreferences: false,
+        regex_automata_version: REGEX_AUTOMATA_VERSION,
+        max_raw_hashes: MAX_RAW_HASHES,
+    }
+}
+
+/// A flags string as one field per flag, e.g. for checkboxes in a UI. Reading
+/// it from js rejects unknown keys.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+struct RegexFlags {
+    /// `g`
+    global: bool,
+    /// `A`
+    ascii_case_insensitive: bool,
+    /// `i`
+    case_insensitive: bool,
+    /// `m`
+    multi_line: bool,
+    /// `o`
+    octal: bool,
+    /// `s`
+    dot_matches_new_line: bool,
+    /// `U`
+    swap_greed: bool,
+    /// `u`
+    unicode: bool,
+    /// `x`
+    ignore_whitespace: bool,
+}
+
+impl RegexFlags {
+    /// Read a flags string. Repeated flags are allowed; unknown flags give
+    /// `Error::InvalidOptions`.
+    fn parse(flags: &str) -> Result<Self, Error> {
+        let mut ret = Self::default();
+        for flag in flags.chars() {
+            let field = match flag {
+                'g' => &mut ret.global,
+                'A' => &mut ret.ascii_case_insensitive,
+                'i' => &mut ret.case_insensitive,
+                'm' => &mut ret.multi_line,
+                'o' => &mut ret.octal,
+                's' => &mut ret.dot_matches_new_line,
+                'U' => &mut ret.swap_greed,
+                'u' => &mut ret.unicode,
+                'x' => &mut ret.ignore_whitespace,
+                _ => return Err(unknown_flag(flag)),
+            };
+            *field = true;
+        }
+        Ok(ret)
+    }
+
+    /// The canonical flags string, with each set flag once in the order of
+    /// `FLAGS`
+    fn to_flag_string(&self) -> String {
+        let set = [
+            self.global,
+            self.ascii_case_insensitive,
+            self.case_insensitive,
+            self.multi_line,
+            self.octal,
+            self.dot_matches_new_line,
+            self.swap_greed,
+            self.unicode,
+            self.ignore_whitespace,
+        ];
+        FLAGS
+            .iter()
+            .zip(set)
+            .filter(|(_, set)| *set)
+            .map(|((letter, _), _)| letter)
+            .collect()
+    }
 }
 
 /// Our regex state with compiled regex and global flag
@@ -101,263 +1215,2941 @@ struct CapSer<'a> {
 struct State {
     re: Regex,
     global: bool,
+    /// The parsed pattern, for anything that needs to inspect it
+    hir: Hir,
+    /// Stable names of the flags that took effect, in the order they were
+    /// first given. Repeated flags are listed once, and `x` is left out for
+    /// literal patterns since it has no effect there.
+    flags_applied: Vec<&'static str>,
 }
 
 /// Process specified flags to create a regex query. Acceptable flags characters
-/// are `gimsUux`. Also validates the regex string.
+/// are `gAimosUux`, see `FLAGS`; any other gives `Error::InvalidOptions`. Also
+/// validates the regex string.
+///
+/// Octal escapes such as `\141` are rejected by default, as they are easy to
+/// confuse with backreferences; the `o` flag enables them.
 ///
 /// If the regex expression is empty, returns `None` for the state, allowing for
 /// short circuiting
 fn re_build(reg_exp: &str, flags: &str) -> Result<Option<State>, Error> {
+    re_build_with(reg_exp, flags, &BuildOptions::default())
+}
+
+/// Same as `re_build` but with additional settings
+fn re_build_with(reg_exp: &str, flags: &str, opts: &BuildOptions) -> Result<Option<State>, Error> {
+    let Some(Parsed {
+        hir,
+        global,
+        flags_applied,
+    }) = re_parse_with(reg_exp, flags, opts)?
+    else {
+        return Ok(None);
+    };
+
+    let re = Regex::from_hir(&hir, opts.size_limit, opts.line_terminator)?;
+    Ok(Some(State {
+        re,
+        global,
+        hir,
+        flags_applied,
+    }))
+}
+
+/// A pattern that has been parsed and checked but not compiled, see `State`
+struct Parsed {
+    hir: Hir,
+    global: bool,
+    flags_applied: Vec<&'static str>,
+}
+
+/// Everything `re_build_with` does except compiling the pattern, for when only
+/// the parsed pattern is needed
+fn re_parse_with(reg_exp: &str, flags: &str, opts: &BuildOptions) -> Result<Option<Parsed>, Error> {
     if reg_exp.is_empty() {
         return Ok(None);
     }
 
-    // We keep a parser and builder separate; parser gives us nice errors,
-    // builder creates the regex we need.
+    let escaped;
+    let reg_exp = if opts.literal {
+        escaped = regex_syntax::escape(reg_exp);
+        escaped.as_str()
+    } else {
+        reg_exp
+    };
+
+    // We parse the pattern ourselves, which gives us nice errors, and compile
+    // the result
     let mut parser = regex_syntax::ParserBuilder::new();
-    let mut builder = RegexBuilder::new(reg_exp);
 
     // Default to non-unicode, non-global
-    let mut global = false;
-    parser.allow_invalid_utf8(true);
-    parser.unicode(false);
-    builder.unicode(false);
+    let flag_set = RegexFlags::parse(flags)?;
+    // Whitespace in a literal pattern is never ignored
+    let ignore_whitespace = flag_set.ignore_whitespace && !opts.literal;
+    parser
+        .utf8(false)
+        .unicode(flag_set.unicode)
+        .case_insensitive(flag_set.case_insensitive)
+        .multi_line(flag_set.multi_line)
+        .octal(flag_set.octal)
+        .dot_matches_new_line(flag_set.dot_matches_new_line)
+        .swap_greed(flag_set.swap_greed)
+        .ignore_whitespace(ignore_whitespace);
 
+    let mut flags_applied = Vec::new();
     for flag in flags.chars() {
-        // We need to apply all flags to both our builder and our parser
-        match flag {
-            'g' => global = true,
-            'i' => {
-                builder.case_insensitive(true);
-                parser.case_insensitive(true);
-            }
-            'm' => {
-                builder.multi_line(true);
-                parser.multi_line(true);
-            }
-            's' => {
-                builder.dot_matches_new_line(true);
-                parser.dot_matches_new_line(true);
-            }
-            'U' => {
-                builder.swap_greed(true);
-                parser.swap_greed(true);
-            }
-            'u' => {
-                builder.unicode(true);
-                parser.unicode(true);
-            }
-            'x' => {
-                builder.ignore_whitespace(true);
-                parser.ignore_whitespace(true);
-            }
-            // We can panic here because the UI should only ever give us valid
-            // flags
-            _ => panic!("unrecognized flag"),
+        if flag == 'x' && !ignore_whitespace {
+            continue;
+        }
+        let name = flag_name(flag)?;
+        if !flags_applied.contains(&name) {
+            flags_applied.push(name);
+        }
+    }
+
+    if let Some(term) = opts.line_terminator {
+        if !term.is_ascii() {
+            return Err(Error::InvalidOptions(format!(
+                "line terminator must be an ASCII byte, got {term:#04x}"
+            )));
         }
+        parser.line_terminator(term);
     }
 
     // Create nice errors
-    let _ = parser.build().parse(reg_exp)?;
+    let mut hir = parser
+        .build()
+        .parse(reg_exp)
+        .map_err(|e| Error::from_syntax(e, opts.line_base()))?;
+
+    // An escaped pattern has no repetitions to check
+    if !opts.literal {
+        let limit = opts.max_repetition.unwrap_or(DEFAULT_MAX_REPETITION);
+        pattern::check_repetitions(
+            reg_exp,
+            flags.contains('x'),
+            flags.contains('o'),
+            limit,
+            opts.line_base(),
+        )?;
+    }
 
-    // Build our pattern
-    match builder.build() {
-        Ok(re) => Ok(Some(State { re, global })),
-        Err(e) => Err(e.into()),
+    // The regex crate only has unicode-aware case folding when unicode is
+    // enabled, so for ASCII-only folding we add the other case to the parsed
+    // pattern ourselves. This happens after `i` has been applied by the
+    // parser, so `Ai` still folds everything that `i` alone does.
+    if flag_set.ascii_case_insensitive {
+        hir = pattern::fold_ascii_case(&hir);
     }
+
+    Ok(Some(Parsed {
+        hir,
+        global: flag_set.global,
+        flags_applied,
+    }))
+}
+
+/// Parse a pattern with the given flags. An empty pattern gives an empty HIR.
+fn re_hir(reg_exp: &str, flags: &str) -> Result<Hir, Error> {
+    Ok(re_build(reg_exp, flags)?.map_or_else(Hir::empty, |state| state.hir))
 }
 
 /// Run a regular expression on a block of text, returning a JSON string
 ///
 /// # Arguments
 ///
-/// - `flags`: apply global flags, options `gimsUux`
+/// - `flags`: apply global flags, options `gimosUux`
 /// - `text`: haystack to search in
 /// - `reg_exp`: regular expression to match against
+/// - `opts`: additional settings, see `FindOptions`
 ///
-/// Returns a string JSON representation of `CapSer`
-fn re_find_impl(text: &str, reg_exp: &str, flags: &str) -> Result<JsValue, Error> {
-    const MATCH_ESTIMATE: usize = 16; // estimate for vec size initialization
+/// Returns a `MatchSer` representing all matches
+fn re_find_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+    opts: &FindOptions,
+) -> Result<MatchSer<'a>, Error> {
+    check_text_len(text.len(), opts.max_text_len)?;
+    let text_len_utf8 = text.len();
+    let text_len_utf16 = utf16_index_bytes(text, text.len());
 
-    let Some(State {
-        re,
-        global,
-    }) = re_build(reg_exp, flags)? else {
-        return Ok(MatchSer::default().to_js_value());
+    let Some(state) = re_build_with(reg_exp, flags, &opts.build)? else {
+        return Ok(MatchSer {
+            text_len_utf8,
+            text_len_utf16,
+            pattern_empty: true,
+            ..MatchSer::default()
+        });
+    };
+
+    let Range {
+        start: range_start,
+        end: range_end,
+    } = search_range(text.len(), opts, |offset| {
+        if text.is_char_boundary(offset) {
+            return Ok(());
+        }
+        Err(Error::InvalidOffset(format!(
+            "offset {offset} is not a char boundary in a string of length {}",
+            text.len()
+        )))
+    })?;
+
+    // Match without the BOM, then shift offsets so they refer to `text`
+    let bom_len = if opts.strip_bom { bom_len(text) } else { 0 };
+    let haystack = &text.as_bytes()[bom_len..range_end.max(bom_len)];
+    let exclude = opts
+        .exclude_ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start > end {
+                return Err(Error::InvalidOffset(format!(
+                    "excluded range start {start} is after its end {end}"
+                )));
+            }
+            let [start, end] = [start, end].map(|idx| {
+                utf16_prefix_len(text, idx)
+                    .unwrap_or(text.len())
+                    .saturating_sub(bom_len)
+            });
+            Ok(start..end)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut res = collect_matches(
+        &state,
+        haystack,
+        range_start.saturating_sub(bom_len),
+        &exclude,
+        opts,
+    );
+    res.shift_indices(bom_len);
+    res.text_len_utf8 = text_len_utf8;
+    res.text_len_utf16 = text_len_utf16;
+
+    if opts.trim {
+        res.trim_matches(text.as_bytes());
+    }
+
+    if opts.snap_graphemes {
+        res.snap_graphemes(text);
+    }
+
+    if let Some(radius) = opts.preview_radius {
+        res.add_previews(text, radius);
+    }
+
+    if opts.neighbors > 0 {
+        res.add_neighbors(text, opts.neighbors);
+    }
+
+    // We need to add valid utf16 indices, for js highlighting
+    res.update_indices_utf16(text);
+
+    if opts.char_offsets {
+        res.update_indices_char(text);
+    }
+
+    if opts.sentinel_nonparticipating {
+        res.fill_nonparticipating();
+    }
+
+    if opts.content_offsets {
+        res.add_content_offsets(text.as_bytes());
+    }
+
+    if opts.escape_groups == Some(false) {
+        res.lossy_groups(text.as_bytes());
+    }
+
+    if opts.normalize_whitespace {
+        res.add_display_content();
+    }
+
+    if opts.coerce_numbers {
+        res.add_number_values();
+    }
+
+    if let Some(max) = opts.max_content_len {
+        res.truncate_content(max);
+    }
+
+    Ok(res)
+}
+
+/// Number of lines that some matched text covers
+fn line_span(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+/// Check the length of the text against a limit from the options, so huge
+/// inputs fail fast rather than being searched
+fn check_text_len(len: usize, max: Option<usize>) -> Result<(), Error> {
+    match max {
+        Some(max) if len > max => Err(Error::TextTooLong { len, max }),
+        _ => Ok(()),
+    }
+}
+
+/// The part of the text to search, from `FindOptions::range_start` and
+/// `range_end`. `check_offset` gives an error for an offset that can't be used
+/// in a text of length `len`.
+fn search_range(
+    len: usize,
+    opts: &FindOptions,
+    check_offset: impl Fn(usize) -> Result<(), Error>,
+) -> Result<Range<usize>, Error> {
+    let range_start = opts.range_start.unwrap_or(0);
+    let range_end = opts.range_end.unwrap_or(len);
+    check_offset(range_start)?;
+    check_offset(range_end)?;
+    if range_start > range_end {
+        return Err(Error::InvalidOffset(format!(
+            "range start {range_start} is after range end {range_end}"
+        )));
+    }
+    Ok(range_start..range_end)
+}
+
+/// Run a regular expression on bytes that were decoded from the hex string
+/// `hex`. Utf16 indices in the result refer to positions in `hex`, so clients
+/// can highlight the matching pairs. Options that only make sense for a
+/// string, like previews, give an error rather than being ignored.
+fn re_find_hex_impl<'a>(
+    hex: &str,
+    bytes: &'a [u8],
+    pair_offsets: &[usize],
+    reg_exp: &str,
+    flags: &str,
+    opts: &FindOptions,
+) -> Result<MatchSer<'a>, Error> {
+    check_text_len(bytes.len(), opts.max_text_len)?;
+    let string_only = [
+        ("previewRadius", opts.preview_radius.is_some()),
+        ("charOffsets", opts.char_offsets),
+        ("stripBom", opts.strip_bom),
+        ("snapGraphemes", opts.snap_graphemes),
+        ("neighbors", opts.neighbors > 0),
+        ("excludeRanges", !opts.exclude_ranges.is_empty()),
+    ];
+    if let Some((name, _)) = string_only.iter().find(|(_, set)| *set) {
+        return Err(Error::InvalidOptions(format!(
+            "{name} can't be used with hex text"
+        )));
+    }
+
+    // Lengths are in the same units as the offsets: decoded bytes and
+    // positions in the hex text
+    let Some(state) = re_build_with(reg_exp, flags, &opts.build)? else {
+        return Ok(MatchSer {
+            text_len_utf8: bytes.len(),
+            text_len_utf16: hex.len(),
+            pattern_empty: true,
+            ..MatchSer::default()
+        });
     };
 
+    // Any offset in the decoded bytes can be used, since they don't need to be
+    // valid utf8
+    let range = search_range(bytes.len(), opts, |offset| {
+        if offset <= bytes.len() {
+            return Ok(());
+        }
+        Err(Error::InvalidOffset(format!(
+            "offset {offset} is past the end of {} decoded bytes",
+            bytes.len()
+        )))
+    })?;
+    let mut res = collect_matches(&state, &bytes[..range.end], range.start, &[], opts);
+    res.text_len_utf8 = bytes.len();
+    res.text_len_utf16 = hex.len();
+    if opts.trim {
+        res.trim_matches(bytes);
+    }
+    res.update_indices_hex(hex.len(), pair_offsets);
+
+    if opts.sentinel_nonparticipating {
+        res.fill_nonparticipating();
+    }
+
+    if opts.content_offsets {
+        res.add_content_offsets(bytes);
+    }
+
+    if opts.escape_groups == Some(false) {
+        res.lossy_groups(bytes);
+    }
+
+    if opts.normalize_whitespace {
+        res.add_display_content();
+    }
+
+    if opts.coerce_numbers {
+        res.add_number_values();
+    }
+
+    if let Some(max) = opts.max_content_len {
+        res.truncate_content(max);
+    }
+
+    Ok(res)
+}
+
+/// Collect all matches in the text that start at or after `start`, respecting
+/// the global flag and limits from `opts`. Matches that overlap a range in
+/// `exclude` (utf8 offsets into `text`) are skipped. Only utf8 indices are
+/// set.
+fn collect_matches<'a>(
+    state: &State,
+    text: &'a [u8],
+    start: usize,
+    exclude: &[Range<usize>],
+    opts: &FindOptions,
+) -> MatchSer<'a> {
+    const MATCH_ESTIMATE: usize = 16; // estimate for vec size initialization
+
+    let State { re, global, .. } = state;
+
+    let start_ms = opts.stats.then(util::now_ms);
+
     // If we aren't global, limit to the first match
-    let limit = if global { usize::MAX } else { 1 };
-    let mut matches: Vec<Vec<CapSer>> = Vec::with_capacity(MATCH_ESTIMATE);
-    // We'll use this to convert our utf8 indices to utf16 all at once
-    let mut all_indices: Vec<usize> = Vec::with_capacity(MATCH_ESTIMATE * 2);
+    let limit = if *global { usize::MAX } else { 1 };
+    let mut res = MatchSer {
+        matches: Vec::with_capacity(MATCH_ESTIMATE),
+        participation: Vec::with_capacity(MATCH_ESTIMATE),
+        matches_empty: state.hir.properties().minimum_len() == Some(0),
+        flags_applied: state.flags_applied.clone(),
+        group_usage: vec![0; re.captures_len()],
+        ..MatchSer::default()
+    };
+    let mut content_bytes = 0usize;
+    // With paging we keep searching past the page to count the total
+    let paging = opts.offset > 0 || opts.limit.is_some();
+    let page_end = opts
+        .limit
+        .map_or(usize::MAX, |limit| opts.offset.saturating_add(limit));
+    let mut kept = 0;
+    let mut seen: HashSet<&[u8]> = HashSet::new();
+    let mut next_line_start = 0;
+    let primary_idx = opts
+        .primary_group
+        .as_deref()
+        .map(|group| match group.parse::<usize>() {
+            Ok(idx) => Some(idx),
+            Err(_) => re.capture_names().position(|name| name == Some(group)),
+        });
+
+    let matches: Box<dyn Iterator<Item = Captures<'a>> + '_> = if opts.shortest {
+        // The same pattern was just compiled with a size limit, so this can't
+        // fail
+        let shortest = ShortestSearcher::new(&state.hir, opts.build.line_terminator)
+            .expect("pattern already compiled");
+        Box::new(shortest_captures_iter(re, shortest, text, start))
+    } else if start > 0 {
+        Box::new(captures_iter_at(re, text, start))
+    } else {
+        Box::new(re.captures_iter(text))
+    };
+
+    // With leftmost-first semantics, if any match starts at `start` then the
+    // first match does. Later matches can't start there, so this keeps at most
+    // one.
+    let matches = matches
+        .take(limit)
+        .take_while(|cap| !opts.anchored_start || cap.get(0).unwrap().start() == start);
 
     // Each item in this loop is a query match. Limit to `limit`.
-    for (match_idx, cap_match) in re.captures_iter(text.as_bytes()).take(limit).enumerate() {
-        // For each capture name, get the correct capture and turn it into a
-        // serializable representation (CapSer). Collect it into a vector.
-        let mut match_: Vec<CapSer> = Vec::with_capacity(re.captures_len());
-
-        for (i, opt_cap_name) in re.capture_names().enumerate() {
-            // Start with a default capture representation
-            let mut to_push = CapSer {
-                group_name: opt_cap_name,
-                group_num: i,
-                match_num: match_idx,
-                ..CapSer::default()
-            };
+    for cap_match in matches {
+        // Excluded matches are skipped as if they weren't found. An empty
+        // match is treated as covering the position it is at.
+        let entire = cap_match.get(0).unwrap();
+        let covered = entire.start()..entire.end().max(entire.start() + 1);
+        if exclude
+            .iter()
+            .any(|range| covered.start < range.end && range.start < covered.end)
+        {
+            continue;
+        }
+
+        // With `one_per_line`, skip anything that starts before the line after
+        // the last kept match
+        let start = entire.start();
+        if opts.one_per_line {
+            if start < next_line_start {
+                continue;
+            }
+            next_line_start = text[start..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(usize::MAX, |pos| start + pos + 1);
+        }
+
+        // Duplicates are skipped before doing any work to serialize them
+        if opts.dedup && !seen.insert(cap_match.get(0).unwrap().as_bytes()) {
+            continue;
+        }
 
-            // If our capture exists, update info for it
-            if let Some(m) = cap_match.get(i) {
-                let content = str_from_utf8_rep(text, m.start(), m.end());
+        // Matches outside of the page are only counted
+        kept += 1;
+        if kept <= opts.offset || kept > page_end || res.truncated {
+            continue;
+        }
 
-                all_indices.push(m.start());
-                all_indices.push(m.end());
+        let mut match_ = match_to_ser(re, text, kept - 1, &cap_match);
+        if let Some(primary_idx) = primary_idx {
+            for cap in &mut match_ {
+                cap.entire_match = cap.is_participating && Some(cap.group_num) == primary_idx;
+            }
+        }
+        if opts.innermost_only {
+            let innermost = match_.iter().rposition(|cap| cap.is_participating);
+            match_ = match_
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _)| *idx == 0 || Some(*idx) == innermost)
+                .map(|(_, cap)| cap)
+                .collect();
+        }
 
-                to_push.is_participating = true;
-                to_push.entire_match = i == 0;
-                to_push.content = Some(content);
-                to_push.start = Some(m.start());
-                to_push.end = Some(m.end());
+        // Stop before this match would put us over our content budget
+        if let Some(max_bytes) = opts.max_content_bytes {
+            content_bytes += match_
+                .iter()
+                .filter_map(|cap| cap.content.as_ref())
+                .map(|content| content.len())
+                .sum::<usize>();
+            if content_bytes > max_bytes {
+                res.truncated = true;
+                if paging {
+                    continue;
+                }
+                break;
             }
+        }
 
-            match_.push(to_push);
+        // Count from the captures since `innermost_only` may drop groups
+        for (idx, usage) in res.group_usage.iter_mut().enumerate() {
+            if cap_match.get(idx).is_some() {
+                *usage += 1;
+            }
         }
+        res.push_match(match_);
+    }
 
-        matches.push(match_);
+    if let Some(start_ms) = start_ms {
+        res.stats = Some(StatsSer {
+            elapsed_ms: util::now_ms() - start_ms,
+            match_count: res.matches.len(),
+        });
     }
 
-    let mut res = MatchSer { matches };
+    res.total = paging.then_some(kept);
+    res
+}
 
-    // We need to add valid utf16 indices, for js highlighting
-    res.update_indices_utf16(text, all_indices);
+/// Iterate over matches like `captures_iter`, but each match is the shortest
+/// one that starts where the usual leftmost-first match starts. That start is
+/// found with a normal search, then an anchored search from it stops at the
+/// first place a match can end.
+fn shortest_captures_iter<'r, 'h>(
+    re: &'r Regex,
+    mut shortest: ShortestSearcher,
+    text: &'h [u8],
+    start: usize,
+) -> impl Iterator<Item = Captures<'h>> + 'r
+where
+    'h: 'r,
+{
+    let mut pos = Some(start);
+
+    std::iter::from_fn(move || {
+        let start_pos = pos?;
+        let Some(longest) = re.captures_at(text, start_pos) else {
+            pos = None;
+            return None;
+        };
+        let entire = longest.get(0).unwrap();
 
-    Ok(res.to_js_value())
+        // The usual match starts here, so there is always a shortest one
+        let cap_match = shortest
+            .captures_at(text, entire.start())
+            .unwrap_or(longest);
+
+        // Move past empty matches by one char
+        let end = cap_match.get(0).unwrap().end();
+        pos = if end > entire.start() {
+            Some(end)
+        } else if end < text.len() {
+            let step = text[end..]
+                .utf8_chunks()
+                .next()
+                .and_then(|chunk| chunk.valid().chars().next())
+                .map_or(1, char::len_utf8);
+            Some(end + step)
+        } else {
+            None
+        };
+
+        Some(cap_match)
+    })
 }
 
-/// Perform a regex replacement on a provided string
-fn re_replace_impl(text: &str, reg_exp: &str, rep: &str, flags: &str) -> Result<JsValue, Error> {
+/// Iterate over matches like `captures_iter`, but only starting the search at
+/// `start`. Text before `start` is still used for assertions like `\b`.
+fn captures_iter_at<'r, 'h>(
+    re: &'r Regex,
+    text: &'h [u8],
+    start: usize,
+) -> impl Iterator<Item = Captures<'h>> + 'r
+where
+    'h: 'r,
+{
+    let mut pos = Some(start);
+    let mut last_end = None;
+
+    std::iter::from_fn(move || loop {
+        let Some(cap_match) = re.captures_at(text, pos?) else {
+            pos = None;
+            return None;
+        };
+        let entire = cap_match.get(0).unwrap();
+
+        // Like `captures_iter`, skip an empty match where the last match ended
+        // and step forward a byte
+        if entire.is_empty() && Some(entire.end()) == last_end {
+            pos = (entire.end() < text.len()).then_some(entire.end() + 1);
+            continue;
+        }
+
+        last_end = Some(entire.end());
+        pos = Some(entire.end());
+        return Some(cap_match);
+    })
+}
+
+/// Find only the last match in the text. This ignores the global flag, and
+/// `match` is the index among all matches.
+fn re_find_last_impl<'a>(text: &'a str, reg_exp: &str, flags: &str) -> Result<MatchSer<'a>, Error> {
+    let mut res = MatchSer {
+        text_len_utf8: text.len(),
+        text_len_utf16: utf16_index_bytes(text, text.len()),
+        ..MatchSer::default()
+    };
     let Some(State {
         re,
-        global,
-    }) = re_build(reg_exp, flags)?  else {
-        return Ok(text.into());
+        hir,
+        flags_applied,
+        ..
+    }) = re_build(reg_exp, flags)?
+    else {
+        res.pattern_empty = true;
+        return Ok(res);
     };
+    res.matches_empty = hir.properties().minimum_len() == Some(0);
+    res.flags_applied = flags_applied;
 
-    let text_bytes = text.as_bytes();
-    let rep_bytes = rep.as_bytes();
+    let last = re.captures_iter(text.as_bytes()).enumerate().last();
+    if let Some((match_idx, cap_match)) = last {
+        res.push_match(match_to_ser(&re, text.as_bytes(), match_idx, &cap_match));
+        res.update_indices_utf16(text);
+    }
 
-    let res_cow = if global {
-        re.replace_all(text_bytes, rep_bytes)
-    } else {
-        re.replace(text_bytes, rep_bytes)
+    Ok(res)
+}
+
+/// Call `f` with the groups of each match as it is found, stopping early if it
+/// returns `false`. Returns the number of matches passed to `f`.
+///
+/// Utf16 offsets are counted from the start of the previous match rather than
+/// converting all offsets at the end, so each match is ready when it is found.
+/// Offsets inside a char count up to the end of it, the same as in `re_find`.
+fn re_find_each_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+    mut f: impl FnMut(Vec<CapSer<'a>>) -> bool,
+) -> Result<usize, Error> {
+    let Some(State { re, global, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(0);
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+    // Utf8 and utf16 offsets of the start of the last match
+    let (mut last_start, mut last_start_utf16) = (0, 0);
+    let mut count = 0;
+
+    for (match_idx, cap_match) in re.captures_iter(text.as_bytes()).take(limit).enumerate() {
+        // Non-utf8 patterns can match part of a char, so only slice the text
+        // at char boundaries
+        let start = text.ceil_char_boundary(cap_match.get(0).unwrap().start());
+        last_start_utf16 += utf16_index_bytes(&text[last_start..], start - last_start);
+        last_start = start;
+
+        let mut match_ = match_to_ser(&re, text.as_bytes(), match_idx, &cap_match);
+        // Groups always lie within the match, so count from its start
+        let to_utf16 = |offset: usize| {
+            utf16_index_bytes(&text[start..], text.ceil_char_boundary(offset) - start)
+        };
+        for cap_ser in &mut match_ {
+            let rel_start = cap_ser.start.map(to_utf16);
+            let rel_end = cap_ser.end.map(to_utf16);
+            cap_ser.start_utf16 = rel_start.map(|rel| rel + last_start_utf16);
+            cap_ser.end_utf16 = rel_end.map(|rel| rel + last_start_utf16);
+            if cap_ser.group_num > 0 {
+                cap_ser.rel_start_utf16 = rel_start;
+                cap_ser.rel_end_utf16 = rel_end;
+            }
+        }
+
+        count += 1;
+        if !f(match_) {
+            break;
+        }
+    }
+
+    Ok(count)
+}
+
+/// For each capture name, get the correct capture and turn it into a
+/// serializable representation (CapSer). Collect it into a vector.
+fn match_to_ser<'a>(
+    re: &Regex,
+    text: &'a [u8],
+    match_idx: usize,
+    cap_match: &Captures,
+) -> Vec<CapSer<'a>> {
+    let mut match_: Vec<CapSer> = Vec::with_capacity(re.captures_len());
+
+    for (i, opt_cap_name) in re.capture_names().enumerate() {
+        // Start with a default capture representation
+        let mut to_push = CapSer {
+            group_name: opt_cap_name.map(str::to_owned),
+            group_num: i,
+            match_num: match_idx,
+            ..CapSer::default()
+        };
+
+        // If our capture exists, update info for it
+        if let Some(m) = cap_match.get(i) {
+            let content = str_from_utf8_rep(text, m.start(), m.end());
+
+            to_push.is_participating = true;
+            to_push.entire_match = i == 0;
+            to_push.content = Some(content);
+            to_push.start = Some(m.start());
+            to_push.end = Some(m.end());
+            if i == 0 {
+                to_push.line_span = Some(line_span(m.as_bytes()));
+            }
+        }
+
+        match_.push(to_push);
+    }
+
+    match_
+}
+
+/// Perform a regex replacement on a provided string
+fn re_replace_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    opts: &ReplaceOptions,
+) -> Result<ReplacdSer<'a>, Error> {
+    check_text_len(text.len(), opts.max_text_len)?;
+    let Some(state) = re_build_with(reg_exp, flags, &opts.build)? else {
+        return Ok(ReplacdSer {
+            result: Cow::Borrowed(text),
+            ..ReplacdSer::default()
+        });
+    };
+
+    // The BOM is left out of matching but kept in the result
+    let (bom, body) = text.split_at(if opts.strip_bom { bom_len(text) } else { 0 });
+
+    let res_cow = match opts.redact {
+        Some(redact) => redact_bytes(&state, body.as_bytes(), redact, opts),
+        None => {
+            if opts.strict {
+                check_template(rep)?;
+            }
+            let mut rep = apply_aliases(rep, &opts.alias, &state.re)?;
+            // Markers are escaped so they are inserted literally
+            if let Some((open, close)) = &opts.mark {
+                rep = Cow::Owned(format!(
+                    "{}{rep}{}",
+                    open.replace('$', "$$"),
+                    close.replace('$', "$$")
+                ));
+            }
+            replace_bytes(&state, body.as_bytes(), rep.as_bytes())
+        }
     };
 
-    // Replace returns a Cow, get it as &str and turn into a js string
-    // Invalid unicode is replaced with the invalid unicode character
-    let rep_ser = ReplacdSer {
-        result: &String::from_utf8_lossy(res_cow.as_ref()),
+    // A borrowed result means nothing was replaced. Otherwise, invalid unicode
+    // is handled as requested, with error offsets relative to the whole result.
+    let had_matches = matches!(res_cow, Cow::Owned(_));
+    let result = match res_cow {
+        Cow::Borrowed(_) => Cow::Borrowed(text),
+        Cow::Owned(v) => {
+            let body = opts.on_invalid_utf8.convert(v).map_err(|e| match e {
+                Error::InvalidUtf8Result { offset } => Error::InvalidUtf8Result {
+                    offset: offset + bom.len(),
+                },
+                e => e,
+            })?;
+            Cow::Owned(format!("{bom}{body}"))
+        }
     };
-    Ok(rep_ser.to_js_value())
+    Ok(ReplacdSer {
+        result,
+        flags_applied: state.flags_applied,
+        had_matches,
+    })
 }
 
-/// Perform replacements and only return the matched string
-fn re_replace_list_impl(
+/// Perform a replacement and split the result on `\n`. This is `str::split`,
+/// so there is always at least one line and a trailing newline gives a
+/// trailing empty line. A `\r` before a `\n` is left on the line.
+fn re_replace_lines_impl(
     text: &str,
     reg_exp: &str,
     rep: &str,
     flags: &str,
-) -> Result<JsValue, Error> {
-    let Some(State {
-        re,
-        global,
-    }) = re_build(reg_exp, flags)?  else {
-        return Ok("".into());
-    };
+    opts: &ReplaceOptions,
+) -> Result<Vec<String>, Error> {
+    let res = re_replace_impl(text, reg_exp, rep, flags, opts)?;
+    Ok(res.result.split('\n').map(str::to_owned).collect())
+}
 
-    let limit = if global { usize::MAX } else { 1 };
-    let mut dest: Vec<u8> = Vec::with_capacity(text.len());
+/// Overwrite each match (or the group named by `redact_group`) with `redact`,
+/// repeated enough to keep the UTF-16 length the same. If `redact` is itself
+/// two code units, an odd length is rounded down. The text is borrowed if
+/// nothing was redacted.
+fn redact_bytes<'a>(
+    state: &State,
+    text: &'a [u8],
+    redact: char,
+    opts: &ReplaceOptions,
+) -> Cow<'a, [u8]> {
+    let limit = if state.global { usize::MAX } else { 1 };
+    let mut redact_buf = [0u8; 4];
+    let fill = redact.encode_utf8(&mut redact_buf).as_bytes();
+    let mut dest = Vec::with_capacity(text.len());
+    let mut last_end = 0;
+    let mut redacted = false;
 
-    // For each match, expand the replacement string and append it to our vector
-    for cap_match in re.captures_iter(text.as_bytes()).take(limit) {
-        cap_match.expand(rep.as_bytes(), &mut dest);
+    for cap_match in state.re.captures_iter(text).take(limit) {
+        let target = match &opts.redact_group {
+            Some(group) => match group.parse::<usize>() {
+                Ok(idx) => cap_match.get(idx),
+                Err(_) => cap_match.name(group),
+            },
+            None => cap_match.get(0),
+        };
+        // Groups that don't participate are left alone
+        let Some(target) = target else {
+            continue;
+        };
+
+        let count = utf16_len_bytes(target.as_bytes()) / redact.len_utf16();
+        dest.extend_from_slice(&text[last_end..target.start()]);
+        if let Some((open, _)) = &opts.mark {
+            dest.extend_from_slice(open.as_bytes());
+        }
+        for _ in 0..count {
+            dest.extend_from_slice(fill);
+        }
+        if let Some((_, close)) = &opts.mark {
+            dest.extend_from_slice(close.as_bytes());
+        }
+        last_end = target.end();
+        redacted = true;
     }
 
-    // Return a valid utf8 string that uses the replacement character where needed
-    let rep_ser = ReplacdSer {
-        result: &String::from_utf8_lossy(&dest),
+    if !redacted {
+        return Cow::Borrowed(text);
+    }
+    dest.extend_from_slice(&text[last_end..]);
+    Cow::Owned(dest)
+}
+
+/// Perform a regex replacement on raw bytes, without any lossy conversion of
+/// the result
+fn re_replace_bytes_impl<'a>(
+    text: &'a [u8],
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+) -> Result<Cow<'a, [u8]>, Error> {
+    let Some(state) = re_build(reg_exp, flags)? else {
+        return Ok(Cow::Borrowed(text));
     };
 
-    Ok(rep_ser.to_js_value())
+    Ok(replace_bytes(&state, text, rep.as_bytes()))
 }
 
-/// Wrapper for `re_find_impl`
-#[wasm_bindgen]
-pub fn re_find(
+/// Replace only the first match that starts at or after `from_offset` (a utf8
+/// offset in `text`). The global flag is ignored. The returned offset points
+/// just past the replacement, so calling this again with the result and that
+/// offset steps through matches one at a time. Gives
+/// `Error::InvalidUtf8Result` if replacing a match that splits a char would
+/// leave invalid utf8.
+fn re_replace_step_impl(
     text: &str,
     reg_exp: &str,
+    rep: &str,
     flags: &str,
-    text_sep: Option<String>,
-    reg_exp_sep: Option<String>,
-) -> JsValue {
-    wrap_erroring_fn(|| {
-        let text_esc = unescape(text, &text_sep).map_err(|e| (e, "text"))?;
-        let reg_exp_esc = unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, "reg_exp"))?;
-        re_find_impl(&text_esc, &reg_exp_esc, flags)
+    from_offset: usize,
+) -> Result<ReplaceStepSer, Error> {
+    if !text.is_char_boundary(from_offset) {
+        return Err(Error::InvalidOffset(format!(
+            "offset {from_offset} is not a char boundary in a string of length {}",
+            text.len()
+        )));
+    }
+
+    let no_match = || ReplaceStepSer {
+        result: text.to_owned(),
+        next_offset: None,
+    };
+    let Some(State { re, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(no_match());
+    };
+    let Some(cap_match) = re.captures_at(text.as_bytes(), from_offset) else {
+        return Ok(no_match());
+    };
+
+    let entire = cap_match.get(0).unwrap();
+    let mut bytes = text.as_bytes()[..entire.start()].to_vec();
+    cap_match.expand(rep.as_bytes(), &mut bytes);
+    let mut next_offset = bytes.len();
+    bytes.extend_from_slice(&text.as_bytes()[entire.end()..]);
+
+    // Without the `u` flag a match can split a char, which would leave half of
+    // it on either side of the replacement
+    let result = OnInvalidUtf8::Error.convert(bytes)?;
+
+    // Step over a char after an empty match so we don't find it again, and
+    // never stop inside a char
+    if entire.is_empty() && next_offset < result.len() {
+        next_offset += 1;
+    }
+    while !result.is_char_boundary(next_offset) {
+        next_offset += 1;
+    }
+
+    Ok(ReplaceStepSer {
+        result,
+        next_offset: Some(next_offset),
     })
 }
 
-/// Wrapper for `re_replace_impl`
-#[wasm_bindgen]
-pub fn re_replace(
-    text: &str,
+/// Perform a replacement and give the range of the text that changed, found by
+/// comparing the text with the result
+fn re_replace_bounds_impl<'a>(
+    text: &'a str,
     reg_exp: &str,
     rep: &str,
     flags: &str,
-    text_sep: Option<String>,
-    reg_exp_sep: Option<String>,
-    rep_sep: Option<String>,
-) -> JsValue {
-    wrap_erroring_fn(|| {
-        let text_esc = unescape(text, &text_sep).map_err(|e| (e, "text"))?;
-        let reg_exp_esc = unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, "reg_exp"))?;
-        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, "rep"))?;
-        re_replace_impl(&text_esc, &reg_exp_esc, &rep_esc, flags)
+) -> Result<ReplaceBoundsSer<'a>, Error> {
+    let ReplacdSer { result, .. } =
+        re_replace_impl(text, reg_exp, rep, flags, &ReplaceOptions::default())?;
+    let range = diff_range_utf16(text, &result);
+    Ok(ReplaceBoundsSer {
+        first_diff_utf16: range.as_ref().map(|range| range.start),
+        last_diff_utf16: range.map(|range| range.end),
+        result,
     })
 }
 
-/// Wrapper for `re_replace_list_impl`
-#[wasm_bindgen]
-pub fn re_replace_list(
-    text: &str,
+/// Replace only the span of `group` (a name or number) in each match with the
+/// expanded replacement, keeping the rest of the match. Matches where the
+/// group doesn't participate are left alone.
+fn re_replace_group_impl<'a>(
+    text: &'a str,
     reg_exp: &str,
+    group: &str,
     rep: &str,
     flags: &str,
-    text_sep: Option<String>,
-    reg_exp_sep: Option<String>,
-    rep_sep: Option<String>,
-) -> JsValue {
-    wrap_erroring_fn(|| {
-        let text_esc = unescape(text, &text_sep).map_err(|e| (e, "text"))?;
-        let reg_exp_esc = unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, "reg_exp"))?;
-        let rep_exc = unescape(rep, &rep_sep).map_err(|e| (e, "rep"))?;
-        re_replace_list_impl(&text_esc, &reg_exp_esc, &rep_exc, flags)
+) -> Result<ReplacdSer<'a>, Error> {
+    let Some(State {
+        re,
+        global,
+        flags_applied,
+        ..
+    }) = re_build(reg_exp, flags)?
+    else {
+        return Ok(ReplacdSer {
+            result: Cow::Borrowed(text),
+            ..ReplacdSer::default()
+        });
+    };
+
+    let group_idx = group_index(&re, group)?;
+    let limit = if global { usize::MAX } else { 1 };
+    let mut dest = Vec::new();
+    let mut last_end = 0;
+    let mut replaced = false;
+    for cap_match in re.captures_iter(text.as_bytes()).take(limit) {
+        let Some(target) = cap_match.get(group_idx) else {
+            continue;
+        };
+        dest.extend_from_slice(&text.as_bytes()[last_end..target.start()]);
+        cap_match.expand(rep.as_bytes(), &mut dest);
+        last_end = target.end();
+        replaced = true;
+    }
+
+    if !replaced {
+        return Ok(ReplacdSer {
+            result: Cow::Borrowed(text),
+            flags_applied,
+            had_matches: false,
+        });
+    }
+
+    dest.extend_from_slice(&text.as_bytes()[last_end..]);
+    Ok(ReplacdSer {
+        result: Cow::Owned(OnInvalidUtf8::Lossy.convert(dest)?),
+        flags_applied,
+        had_matches: true,
     })
 }
 
-/* helper functions */
-
-/// Helper method that lets us use `?` to propegate errors, and serializes
-/// everything to a `JsValue`
-fn wrap_erroring_fn<F>(f: F) -> JsValue
-where
-    F: FnOnce() -> Result<JsValue, Error>,
-{
-    match f() {
-        Ok(v) => v,
-        Err(e) => serde_wasm_bindgen::to_value(&e).expect("failed to serialize result"),
+/// Look up a group given by name or number, which must be in the pattern
+fn group_index(re: &Regex, group: &str) -> Result<usize, Error> {
+    match group.parse::<usize>() {
+        Ok(idx) => (idx < re.captures_len()).then_some(idx),
+        Err(_) => re.capture_names().position(|name| name == Some(group)),
     }
+    .ok_or_else(|| Error::InvalidOptions(format!("unknown group '{group}'")))
+}
+
+/// Add `prefix` to the start of every line, with a replacement of `(?m)^`.
+/// A newline at the very end of the text ends the last line rather than
+/// starting an empty one, so that empty line doesn't get a prefix. Every other
+/// line does, including empty ones. `flags` is used with `g` added.
+fn line_prefix_impl(text: &str, prefix: &str, flags: &str) -> Result<String, Error> {
+    // Without this, `^` would match once more at the very end
+    let (body, trailing) = match text.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (text, ""),
+    };
+    let flags = if flags.contains('g') {
+        Cow::Borrowed(flags)
+    } else {
+        Cow::Owned(format!("{flags}g"))
+    };
+    let rep = prefix.replace('$', "$$");
+    let res = re_replace_impl(body, "(?m)^", &rep, &flags, &ReplaceOptions::default())?;
+    Ok(format!("{}{trailing}", res.result))
+}
+
+/// Check whether `re_replace_impl` would change the text, without building the
+/// result. Each replacement is expanded on its own and compared to the text it
+/// replaces, stopping at the first one that differs.
+fn re_would_change_impl(text: &str, reg_exp: &str, rep: &str, flags: &str) -> Result<bool, Error> {
+    let Some(State { re, global, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(false);
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+    let changed = re
+        .replacements(text.as_bytes(), rep.as_bytes(), limit)
+        .any(|(range, expanded)| expanded != text.as_bytes()[range]);
+    Ok(changed)
+}
+
+/// Describe the edits that `re_replace_impl` makes without building the
+/// result: the original range of each match and how much the replacement
+/// changes the length, both in utf16.
+fn re_replace_offsets_impl(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+) -> Result<Vec<ReplaceOffsetSer>, Error> {
+    let Some(State { re, global, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(Vec::new());
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+    let (ranges, rep_lens): (Vec<_>, Vec<_>) = re
+        .replacements(text.as_bytes(), rep.as_bytes(), limit)
+        .map(|(range, expanded)| ((range.start, range.end), utf16_len_bytes(&expanded)))
+        .unzip();
+
+    let indices = ranges
+        .iter()
+        .flat_map(|&(start, end)| [start, end])
+        .collect();
+    let matched_indices = utf16_index_bytes_slice(text, indices);
+    let find_idx = |search| lookup_index(&matched_indices, search);
+
+    Ok(ranges
+        .into_iter()
+        .zip(rep_lens)
+        .map(|((start, end), rep_len)| {
+            let (start_utf16, end_utf16) = (find_idx(start), find_idx(end));
+            ReplaceOffsetSer {
+                start_utf16,
+                end_utf16,
+                delta: rep_len as isize - (end_utf16 - start_utf16) as isize,
+            }
+        })
+        .collect())
+}
+
+/// Perform replacements and only return the matched string
+fn re_replace_list_impl<'a>(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    opts: &ReplaceListOptions,
+) -> Result<ReplacdSer<'a>, Error> {
+    let Some(State {
+        re,
+        global,
+        flags_applied,
+        ..
+    }) = re_build_with(reg_exp, flags, &opts.build)?
+    else {
+        return Ok(ReplacdSer::default());
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+    let mut dest: Vec<u8> = Vec::with_capacity(text.len());
+
+    // For each match, expand the replacement string and append it to our vector
+    let mut had_matches = false;
+    for (_, expanded) in re.replacements(text.as_bytes(), rep.as_bytes(), limit) {
+        dest.extend_from_slice(&expanded);
+        had_matches = true;
+    }
+
+    Ok(ReplacdSer {
+        result: Cow::Owned(opts.on_invalid_utf8.convert(dest)?),
+        flags_applied,
+        had_matches,
+    })
+}
+
+/// Like `re_replace_list_impl`, but keep the text between matches. The result
+/// is a list of segments, where match segments hold their expanded
+/// replacement. Empty gaps are left out, but every match gets a segment.
+fn re_replace_list_gaps_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    opts: &ReplaceListOptions,
+) -> Result<Vec<SegmentSer<'a>>, Error> {
+    let text_bytes = text.as_bytes();
+    let mut segments = Vec::new();
+    let push_gap = |segments: &mut Vec<SegmentSer<'a>>, start: usize, end: usize| {
+        if start < end {
+            segments.push(SegmentSer {
+                text: str_from_utf8_rep(text_bytes, start, end),
+                is_match: false,
+            });
+        }
+    };
+
+    let mut last_end = 0;
+    if let Some(State { re, global, .. }) = re_build_with(reg_exp, flags, &opts.build)? {
+        let limit = if global { usize::MAX } else { 1 };
+        for (range, expanded) in re.replacements(text_bytes, rep.as_bytes(), limit) {
+            push_gap(&mut segments, last_end, range.start);
+            segments.push(SegmentSer {
+                text: Cow::Owned(opts.on_invalid_utf8.convert(expanded)?),
+                is_match: true,
+            });
+            last_end = range.end;
+        }
+    }
+    push_gap(&mut segments, last_end, text.len());
+
+    Ok(segments)
+}
+
+/// Like `re_replace_list_impl`, but keep each expanded replacement separate
+/// along with the index and utf16 range of the match it replaced
+fn re_replace_list_tagged_impl(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    opts: &ReplaceListOptions,
+) -> Result<Vec<TaggedOutputSer>, Error> {
+    let Some(State { re, global, .. }) = re_build_with(reg_exp, flags, &opts.build)? else {
+        return Ok(Vec::new());
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+    let mut ranges = Vec::new();
+    let mut outputs = Vec::new();
+    for (range, expanded) in re.replacements(text.as_bytes(), rep.as_bytes(), limit) {
+        ranges.push(range);
+        outputs.push(opts.on_invalid_utf8.convert(expanded)?);
+    }
+
+    let indices = ranges
+        .iter()
+        .flat_map(|range| [range.start, range.end])
+        .collect();
+    let matched_indices = utf16_index_bytes_slice(text, indices);
+    let find_idx = |search| lookup_index(&matched_indices, search);
+
+    Ok(ranges
+        .into_iter()
+        .zip(outputs)
+        .enumerate()
+        .map(|(source_match, (range, output))| TaggedOutputSer {
+            source_match,
+            source_span_utf16: find_idx(range.start)..find_idx(range.end),
+            output,
+        })
+        .collect())
+}
+
+/// Split the text into segments that alternate between non-matching and
+/// matching text, covering the entire text. Empty segments (including empty
+/// matches) are left out.
+fn re_segments_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+) -> Result<Vec<SegmentSer<'a>>, Error> {
+    let text_bytes = text.as_bytes();
+    let mut segments = Vec::new();
+    let mut push_segment = |start: usize, end: usize, is_match: bool| {
+        if start < end {
+            segments.push(SegmentSer {
+                text: str_from_utf8_rep(text_bytes, start, end),
+                is_match,
+            });
+        }
+    };
+
+    let mut last_end = 0;
+    if let Some(State { re, global, .. }) = re_build(reg_exp, flags)? {
+        let limit = if global { usize::MAX } else { 1 };
+        for m in re.find_iter(text_bytes).take(limit) {
+            push_segment(last_end, m.start(), false);
+            push_segment(m.start(), m.end(), true);
+            last_end = m.end();
+        }
+    }
+    push_segment(last_end, text.len(), false);
+
+    Ok(segments)
+}
+
+/// Find matches like `re_find`, but give each match as a tree of groups
+/// nested by which spans contain which
+fn re_find_tree_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+) -> Result<Vec<GroupNodeSer<'a>>, Error> {
+    let res = re_find_impl(text, reg_exp, flags, &FindOptions::default())?;
+    Ok(res
+        .matches
+        .into_iter()
+        .map(GroupNodeSer::from_match)
+        .collect())
+}
+
+/// Find the innermost participating group that contains the utf16 offset, e.g.
+/// for selecting a group by clicking in highlighted text. A group contains the
+/// code unit at its start but not the one at its end, so empty groups never
+/// contain an offset. If two groups have the same span, the inner (higher
+/// numbered) one is picked. Returns `None` if no match covers the offset.
+fn re_group_at_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+    offset_utf16: usize,
+) -> Result<Option<CapSer<'a>>, Error> {
+    let res = re_find_impl(text, reg_exp, flags, &FindOptions::default())?;
+    let contains = |cap: &CapSer| match (cap.start_utf16, cap.end_utf16) {
+        (Some(start), Some(end)) => (start..end).contains(&offset_utf16),
+        _ => false,
+    };
+
+    let Some(match_) = res.matches.into_iter().find(|match_| contains(&match_[0])) else {
+        return Ok(None);
+    };
+
+    Ok(match_.into_iter().filter(contains).min_by_key(|cap| {
+        (
+            cap.end_utf16.unwrap() - cap.start_utf16.unwrap(),
+            Reverse(cap.group_num),
+        )
+    }))
+}
+
+/// A key and the content of its entire match, serialized as a two item array
+type EntrySer<'a> = (Cow<'a, str>, Cow<'a, str>);
+
+/// Collect `[key, entire match]` pairs, where the key is the content of
+/// `key_group` (a name or number), for building a JS `Map`. Matches where the
+/// key group doesn't participate are skipped.
+fn re_find_entries_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    key_group: &str,
+    flags: &str,
+) -> Result<Vec<EntrySer<'a>>, Error> {
+    let Some(State { re, global, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(Vec::new());
+    };
+    let key_idx = group_index(&re, key_group)?;
+
+    let limit = if global { usize::MAX } else { 1 };
+    let entries = re
+        .captures_iter(text.as_bytes())
+        .take(limit)
+        .filter_map(|cap_match| {
+            let key = cap_match.get(key_idx)?;
+            let entire = cap_match.get(0).unwrap();
+            Some((
+                str_from_utf8_rep(text.as_bytes(), key.start(), key.end()),
+                str_from_utf8_rep(text.as_bytes(), entire.start(), entire.end()),
+            ))
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+/// Find matches of `reg_exp` whose entire match also matches `filter_exp`,
+/// in the format of `re_find`. The filter is run on just the content of each
+/// match, so `^` and `$` in it refer to the ends of the match. An empty filter
+/// keeps every match. Match numbers only count the matches that are kept.
+fn re_find_filtered_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    filter_exp: &str,
+    flags: &str,
+) -> Result<MatchSer<'a>, Error> {
+    let mut res = re_find_impl(text, reg_exp, flags, &FindOptions::default())?;
+    let Some(filter) = re_build(filter_exp, flags)? else {
+        return Ok(res);
+    };
+
+    let (matches, participation): (Vec<_>, Vec<_>) = res
+        .matches
+        .into_iter()
+        .zip(res.participation)
+        .filter(|(match_, _)| {
+            let (Some(start), Some(end)) = (match_[0].start, match_[0].end) else {
+                return false;
+            };
+            filter.re.is_match(&text.as_bytes()[start..end])
+        })
+        .unzip();
+    res.matches = matches;
+    res.participation = participation;
+    for (match_num, match_) in res.matches.iter_mut().enumerate() {
+        for cap_ser in match_ {
+            cap_ser.match_num = match_num;
+        }
+    }
+    // Every group is kept without any options, so participation has them all
+    res.group_usage.fill(0);
+    for participating in &res.participation {
+        for (usage, &found) in res.group_usage.iter_mut().zip(participating) {
+            *usage += usize::from(found);
+        }
+    }
+    Ok(res)
+}
+
+/// Matches grouped by the content of a group, see `re_find_grouped_by_impl`
+type GroupedSer<'a> = BTreeMap<Option<Cow<'a, str>>, Vec<Vec<CapSer<'a>>>>;
+
+/// Sort matches into buckets by the content of `group` (a name or number), in
+/// the format of `re_find`. Buckets are ordered by that content, and matches
+/// where the group doesn't participate go under `None`, which sorts first.
+fn re_find_grouped_by_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    group: &str,
+    flags: &str,
+) -> Result<GroupedSer<'a>, Error> {
+    let Some(state) = re_build(reg_exp, flags)? else {
+        return Ok(BTreeMap::new());
+    };
+    let group_idx = group_index(&state.re, group)?;
+
+    let mut res = collect_matches(&state, text.as_bytes(), 0, &[], &FindOptions::default());
+    res.update_indices_utf16(text);
+
+    let mut grouped = GroupedSer::new();
+    for match_ in res.matches {
+        let key = match_[group_idx].content.clone();
+        grouped.entry(key).or_default().push(match_);
+    }
+    Ok(grouped)
+}
+
+/// Find matches in the shape of JS `RegExpMatchArray`s, so they can be used in
+/// place of the results of `RegExp.prototype.exec`. The `input` property is
+/// left out since the caller already has the text.
+fn re_find_jsmatch_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+) -> Result<Vec<JsMatchSer<'a>>, Error> {
+    let res = re_find_impl(text, reg_exp, flags, &FindOptions::default())?;
+    Ok(res
+        .matches
+        .into_iter()
+        .map(JsMatchSer::from_match)
+        .collect())
+}
+
+/// Collect the content of each capture group (excluding the entire match) into
+/// a grid, with one row per match and one column per group. Non-participating
+/// groups are `None`.
+fn re_extract_groups_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+) -> Result<Vec<Vec<Option<Cow<'a, str>>>>, Error> {
+    let Some(State { re, global, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(Vec::new());
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+
+    let grid = re
+        .captures_iter(text.as_bytes())
+        .take(limit)
+        .map(|cap_match| {
+            (1..re.captures_len())
+                .map(|i| {
+                    cap_match
+                        .get(i)
+                        .map(|m| str_from_utf8_rep(text.as_bytes(), m.start(), m.end()))
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(grid)
+}
+
+/// Format matches as CSV with a header row. Columns are the capture groups
+/// (excluding the entire match), headed by the group name or index; a pattern
+/// without groups gets a single column `0` with the entire match.
+/// Non-participating groups are empty.
+fn re_find_csv_impl(text: &str, reg_exp: &str, flags: &str) -> Result<String, Error> {
+    let mut csv = String::new();
+    let Some(State { re, global, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(csv);
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+    let groups = if re.captures_len() > 1 {
+        1..re.captures_len()
+    } else {
+        0..1
+    };
+
+    let header: Vec<String> = re
+        .capture_names()
+        .enumerate()
+        .skip(groups.start)
+        .map(|(i, name)| name.map_or_else(|| i.to_string(), str::to_owned))
+        .collect();
+    push_csv_row(&mut csv, header.iter().map(String::as_str));
+
+    for cap_match in re.captures_iter(text.as_bytes()).take(limit) {
+        let row: Vec<Cow<str>> = groups
+            .clone()
+            .map(|i| {
+                cap_match.get(i).map_or(Cow::Borrowed(""), |m| {
+                    str_from_utf8_rep(text.as_bytes(), m.start(), m.end())
+                })
+            })
+            .collect();
+        push_csv_row(&mut csv, row.iter().map(AsRef::as_ref));
+    }
+
+    Ok(csv)
+}
+
+/// Find the utf16 start offset of every match
+fn re_find_starts_impl(text: &str, reg_exp: &str, flags: &str) -> Result<Vec<u32>, Error> {
+    let Some(State { re, global, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(Vec::new());
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+    let starts: Vec<usize> = re
+        .find_iter(text.as_bytes())
+        .take(limit)
+        .map(|m| m.start())
+        .collect();
+
+    // Match starts are already sorted and unique, so the index map lines up
+    // one to one with our matches
+    Ok(utf16_index_bytes_slice(text, starts)
+        .into_iter()
+        .map(|(_, idx16)| idx16 as u32)
+        .collect())
+}
+
+/// Text made of segments, such as the pieces of a piece table, searched as if
+/// the segments were joined with nothing between them. The joined text is kept
+/// in one buffer that is built once and then edited a segment at a time with
+/// `splice`, so a search doesn't need the whole text to be joined and copied
+/// into the module again. Searches need one contiguous haystack anyway, for
+/// captures and for assertions like `\b` that look across a boundary.
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct SegmentedText {
+    /// Every segment joined with no separator
+    text: String,
+    /// Start of each segment in `text`, in utf8
+    starts: Vec<usize>,
+    /// Start of each segment in `text`, in utf16
+    starts_utf16: Vec<usize>,
+    /// Length of `text` in utf16
+    len_utf16: usize,
+}
+
+impl SegmentedText {
+    /// Replace `delete_count` segments from index `start` with `segments`, like
+    /// js `Array.prototype.splice`. Only the replaced part of the buffer is
+    /// rewritten.
+    fn splice_impl<S: AsRef<str>>(
+        &mut self,
+        start: usize,
+        delete_count: usize,
+        segments: &[S],
+    ) -> Result<(), Error> {
+        let count = self.starts.len();
+        if start > count || delete_count > count - start {
+            return Err(Error::InvalidOffset(format!(
+                "cannot remove {delete_count} segments from index {start} of {count}"
+            )));
+        }
+
+        // Where the removed segments are in the buffer
+        let end = start + delete_count;
+        let (from, from_utf16) = self.start_of(start);
+        let (to, to_utf16) = self.start_of(end);
+
+        let mut inserted = String::new();
+        let mut new_starts = Vec::with_capacity(segments.len());
+        let mut new_starts_utf16 = Vec::with_capacity(segments.len());
+        let mut len_utf16 = 0;
+        for segment in segments {
+            let segment = segment.as_ref();
+            new_starts.push(from + inserted.len());
+            new_starts_utf16.push(from_utf16 + len_utf16);
+            inserted.push_str(segment);
+            len_utf16 += utf16_len_bytes(segment.as_bytes());
+        }
+
+        // Segments after the removed ones move by the change in length
+        let shift = |starts: &mut [usize], old_len: usize, new_len: usize| {
+            for s in starts {
+                *s = *s - old_len + new_len;
+            }
+        };
+        shift(&mut self.starts[end..], to - from, inserted.len());
+        shift(
+            &mut self.starts_utf16[end..],
+            to_utf16 - from_utf16,
+            len_utf16,
+        );
+
+        self.len_utf16 = self.len_utf16 - (to_utf16 - from_utf16) + len_utf16;
+        self.text.replace_range(from..to, &inserted);
+        self.starts.splice(start..end, new_starts);
+        self.starts_utf16.splice(start..end, new_starts_utf16);
+        Ok(())
+    }
+
+    /// Start of the segment at `index` in utf8 and utf16, or the end of the
+    /// text if `index` is the number of segments
+    fn start_of(&self, index: usize) -> (usize, usize) {
+        match self.starts.get(index) {
+            Some(&start) => (start, self.starts_utf16[index]),
+            None => (self.text.len(), self.len_utf16),
+        }
+    }
+
+    /// Search the joined text and locate each match in the segments as well as
+    /// in the whole text
+    fn find_impl(
+        &self,
+        reg_exp: &str,
+        flags: &str,
+        opts: &FindOptions,
+    ) -> Result<SegmentsFindSer<'_>, Error> {
+        if self.starts.is_empty() {
+            return Err(Error::InvalidOptions("the text has no segments".to_owned()));
+        }
+
+        let result = re_find_impl(&self.text, reg_exp, flags, opts)?;
+        let spans = result
+            .matches
+            .iter()
+            .map(|match_| {
+                let entire = &match_[0];
+                let (start, end) = (entire.start.unwrap(), entire.end.unwrap());
+                // A match at a boundary starts in the later segment and ends in
+                // the earlier one
+                let start_segment = self.starts.partition_point(|&s| s <= start) - 1;
+                let end_segment = if start == end {
+                    start_segment
+                } else {
+                    self.starts.partition_point(|&s| s < end) - 1
+                };
+                SegmentSpanSer {
+                    start_segment,
+                    start_offset_utf16: entire.start_utf16.unwrap()
+                        - self.starts_utf16[start_segment],
+                    end_segment,
+                    end_offset_utf16: entire.end_utf16.unwrap() - self.starts_utf16[end_segment],
+                }
+            })
+            .collect();
+
+        Ok(SegmentsFindSer { result, spans })
+    }
+}
+
+/// Search each of `lines` separately, giving the matches for every line that
+/// has any. The global flag applies within each line.
+fn re_find_in_lines_impl<'a, S: AsRef<str>>(
+    lines: &'a [S],
+    reg_exp: &str,
+    flags: &str,
+) -> Result<Vec<LineFindSer<'a>>, Error> {
+    let Some(state) = re_build(reg_exp, flags)? else {
+        return Ok(Vec::new());
+    };
+
+    let opts = FindOptions::default();
+    let mut ret = Vec::new();
+    for (line_idx, line) in lines.iter().enumerate() {
+        let line = line.as_ref();
+        let mut res = collect_matches(&state, line.as_bytes(), 0, &[], &opts);
+        if res.matches.is_empty() {
+            continue;
+        }
+        res.update_indices_utf16(line);
+        ret.push(LineFindSer {
+            line: line_idx,
+            matches: res.matches,
+        });
+    }
+
+    Ok(ret)
+}
+
+/// Test each line of `text` on its own and return the lines that the pattern
+/// matches entirely, like `grep -x`. Lines are split on `\n`.
+fn re_match_lines_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+    opts: &BuildOptions,
+) -> Result<Vec<LineMatchSer<'a>>, Error> {
+    let Some(Parsed { hir, .. }) = re_parse_with(reg_exp, flags, opts)? else {
+        return Ok(Vec::new());
+    };
+
+    // Anchoring the parsed pattern rather than the pattern string means that
+    // nothing in the pattern, like an `(?x)` comment, can affect the anchors
+    let anchored = Hir::concat(vec![Hir::look(Look::Start), hir, Hir::look(Look::End)]);
+    let re = Regex::from_hir(&anchored, opts.size_limit, opts.line_terminator)?;
+
+    Ok(text
+        .split('\n')
+        .enumerate()
+        .filter(|(_, line)| re.is_match(line.as_bytes()))
+        .map(|(idx, content)| LineMatchSer {
+            line: idx + opts.line_base(),
+            content,
+        })
+        .collect())
+}
+
+/// Check a pattern against a list of `(text, should_match)` cases, compiling
+/// it only once. An empty pattern matches nothing, as with `re_find`.
+fn re_test_cases_impl<S: AsRef<str>>(
+    reg_exp: &str,
+    flags: &str,
+    cases: &[(S, bool)],
+) -> Result<TestCasesSer, Error> {
+    let state = re_build(reg_exp, flags)?;
+    let cases: Vec<_> = cases
+        .iter()
+        .map(|(text, expected)| {
+            let matched = state
+                .as_ref()
+                .is_some_and(|state| state.re.is_match(text.as_ref().as_bytes()));
+            TestCaseSer {
+                matched,
+                passed: matched == *expected,
+            }
+        })
+        .collect();
+
+    Ok(TestCasesSer {
+        passed: cases.iter().all(|case| case.passed),
+        cases,
+    })
+}
+
+/// Split the text on each match, like `str::split`. Without the global flag,
+/// only the first match splits. An empty pattern gives the whole text.
+fn re_split_impl<'a>(
+    text: &'a str,
+    reg_exp: &str,
+    flags: &str,
+) -> Result<Vec<Cow<'a, str>>, Error> {
+    let Some(State { re, global, .. }) = re_build(reg_exp, flags)? else {
+        return Ok(vec![Cow::Borrowed(text)]);
+    };
+
+    let limit = if global { usize::MAX } else { 1 };
+    let text_bytes = text.as_bytes();
+    let mut pieces = Vec::new();
+    let mut last_end = 0;
+    for m in re.find_iter(text_bytes).take(limit) {
+        pieces.push(str_from_utf8_rep(text_bytes, last_end, m.start()));
+        last_end = m.end();
+    }
+    pieces.push(str_from_utf8_rep(text_bytes, last_end, text.len()));
+
+    Ok(pieces)
+}
+
+/// Run one of several operations, chosen by name, so js only needs a single
+/// entry point. `rep` is ignored by operations that don't replace. Each
+/// operation uses its default options.
+fn re_run_impl<'a>(
+    op: &str,
+    text: &'a str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+) -> Result<RunSer<'a>, Error> {
+    let res = match op {
+        "find" => RunSer::Find(re_find_impl(text, reg_exp, flags, &FindOptions::default())?),
+        "replace" => RunSer::Replace(re_replace_impl(
+            text,
+            reg_exp,
+            rep,
+            flags,
+            &ReplaceOptions::default(),
+        )?),
+        "replaceList" => RunSer::Replace(re_replace_list_impl(
+            text,
+            reg_exp,
+            rep,
+            flags,
+            &ReplaceListOptions::default(),
+        )?),
+        "split" => RunSer::Split(re_split_impl(text, reg_exp, flags)?),
+        "isMatch" => RunSer::IsMatch(
+            re_build(reg_exp, flags)?.is_some_and(|state| state.re.is_match(text.as_bytes())),
+        ),
+        _ => return Err(Error::InvalidOptions(format!("unknown op '{op}'"))),
+    };
+    Ok(res)
+}
+
+/// Wrapper for `re_find_impl`
+#[wasm_bindgen]
+pub fn re_find(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    options: JsValue,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let opts: FindOptions = parse_options(options)?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+
+        // Hex text gets decoded to bytes, which may not be valid UTF-8
+        if matches!(text_sep.as_deref().into(), StrType::HexBytes) {
+            let (bytes, pair_offsets) = decode_hex(text).map_err(|e| (e, UnescapeSource::Text))?;
+            return re_find_hex_impl(text, &bytes, &pair_offsets, &reg_exp_esc, flags, &opts)
+                .map(|res| res.to_js_value());
+        }
+
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        re_find_impl(&text_esc, &reg_exp_esc, flags, &opts).map(|res| res.to_js_value())
+    })
+}
+
+/// Same as `re_find`, but the result only has utf16 offsets. `startUtf16` and
+/// `endUtf16` become `start` and `end`, and the utf8 offsets are left out.
+#[wasm_bindgen]
+pub fn re_find_utf16(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    options: JsValue,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let opts: FindOptions = parse_options(options)?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+
+        if matches!(text_sep.as_deref().into(), StrType::HexBytes) {
+            let (bytes, pair_offsets) = decode_hex(text).map_err(|e| (e, UnescapeSource::Text))?;
+            return re_find_hex_impl(text, &bytes, &pair_offsets, &reg_exp_esc, flags, &opts)
+                .map(|res| to_js_value(&MatchUtf16Ser::from(res)));
+        }
+
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        re_find_impl(&text_esc, &reg_exp_esc, flags, &opts)
+            .map(|res| to_js_value(&MatchUtf16Ser::from(res)))
+    })
+}
+
+/// Wrapper for `re_find_each_impl`. `callback` is called with the list of
+/// groups for each match, and iteration stops if it returns `false`. If it
+/// throws, iteration stops and the exception is rethrown.
+#[wasm_bindgen]
+pub fn re_find_each(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    callback: &js_sys::Function,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let mut thrown = None;
+    let res = wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let count = re_find_each_impl(&text_esc, &reg_exp_esc, flags, |match_| {
+            match callback.call1(&JsValue::NULL, &to_js_value(&match_)) {
+                Ok(ret) => ret != JsValue::FALSE,
+                Err(e) => {
+                    thrown = Some(e);
+                    false
+                }
+            }
+        })?;
+        Ok(to_js_value(&count))
+    });
+
+    match thrown {
+        Some(e) => Err(e),
+        None => Ok(res),
+    }
+}
+
+/// Wrapper for `re_find_last_impl`
+#[wasm_bindgen]
+pub fn re_find_last(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        re_find_last_impl(&text_esc, &reg_exp_esc, flags).map(|res| res.to_js_value())
+    })
+}
+
+/// Wrapper for `re_replace_impl`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_replace(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+    options: JsValue,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let opts: ReplaceOptions = parse_options(options)?;
+        re_replace_impl(&text_esc, &reg_exp_esc, &rep_esc, flags, &opts)
+            .map(|res| res.to_js_value())
+    })
+}
+
+/// Wrapper for `re_replace_bounds_impl`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_replace_bounds(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let res = re_replace_bounds_impl(&text_esc, &reg_exp_esc, &rep_esc, flags)?;
+        Ok(to_js_value(&res))
+    })
+}
+
+/// Wrapper for `re_replace_group_impl`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_replace_group(
+    text: &str,
+    reg_exp: &str,
+    group_name: &str,
+    rep: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        re_replace_group_impl(&text_esc, &reg_exp_esc, group_name, &rep_esc, flags)
+            .map(|res| res.to_js_value())
+    })
+}
+
+/// Wrapper for `re_run_impl`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_run(
+    op: &str,
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let res = re_run_impl(op, &text_esc, &reg_exp_esc, &rep_esc, flags)?;
+        Ok(to_js_value(&res))
+    })
+}
+
+/// Wrapper for `re_replace_offsets_impl`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_replace_offsets(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let offsets = re_replace_offsets_impl(&text_esc, &reg_exp_esc, &rep_esc, flags)?;
+        Ok(to_js_value(&offsets))
+    })
+}
+
+/// Wrapper for `line_prefix_impl`
+#[wasm_bindgen]
+pub fn line_prefix(
+    text: &str,
+    prefix: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    prefix_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let prefix_esc =
+            unescape(prefix, &prefix_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        Ok(line_prefix_impl(&text_esc, &prefix_esc, flags)?.into())
+    })
+}
+
+/// Wrapper for `re_would_change_impl`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_would_change(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let changed = re_would_change_impl(&text_esc, &reg_exp_esc, &rep_esc, flags)?;
+        Ok(changed.into())
+    })
+}
+
+/// Same as `re_replace` but the result is split into lines
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_replace_lines(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+    options: JsValue,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let opts: ReplaceOptions = parse_options(options)?;
+        let lines = re_replace_lines_impl(&text_esc, &reg_exp_esc, &rep_esc, flags, &opts)?;
+        Ok(to_js_value(&lines))
+    })
+}
+
+/// Wrapper for `re_replace_list_impl`, `re_replace_list_gaps_impl`, and
+/// `re_replace_list_tagged_impl`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_replace_list(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+    options: JsValue,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_exc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let opts: ReplaceListOptions = parse_options(options)?;
+        if opts.include_gaps && opts.tagged {
+            return Err(Error::InvalidOptions(
+                "includeGaps and tagged can't be used together".to_owned(),
+            ));
+        }
+        if opts.tagged {
+            let outputs =
+                re_replace_list_tagged_impl(&text_esc, &reg_exp_esc, &rep_exc, flags, &opts)?;
+            Ok(to_js_value(&outputs))
+        } else if opts.include_gaps {
+            let segments =
+                re_replace_list_gaps_impl(&text_esc, &reg_exp_esc, &rep_exc, flags, &opts)?;
+            Ok(to_js_value(&segments))
+        } else {
+            re_replace_list_impl(&text_esc, &reg_exp_esc, &rep_exc, flags, &opts)
+                .map(|res| res.to_js_value())
+        }
+    })
+}
+
+/// Wrapper for `re_replace_step_impl`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn re_replace_step(
+    text: &str,
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    from_offset: usize,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let res = re_replace_step_impl(&text_esc, &reg_exp_esc, &rep_esc, flags, from_offset)?;
+        Ok(to_js_value(&res))
+    })
+}
+
+/// Wrapper for `re_segments_impl`
+#[wasm_bindgen]
+pub fn re_segments(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let segments = re_segments_impl(&text_esc, &reg_exp_esc, flags)?;
+        Ok(to_js_value(&segments))
+    })
+}
+
+/// Wrapper for `re_find_tree_impl`
+#[wasm_bindgen]
+pub fn re_find_tree(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let trees = re_find_tree_impl(&text_esc, &reg_exp_esc, flags)?;
+        Ok(to_js_value(&trees))
+    })
+}
+
+/// Wrapper for `re_group_at_impl`
+#[wasm_bindgen]
+pub fn re_group_at(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    offset_utf16: usize,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let group = re_group_at_impl(&text_esc, &reg_exp_esc, flags, offset_utf16)?;
+        Ok(to_js_value(&group))
+    })
+}
+
+/// Wrapper for `re_find_entries_impl`
+#[wasm_bindgen]
+pub fn re_find_entries(
+    text: &str,
+    reg_exp: &str,
+    key_group: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let entries = re_find_entries_impl(&text_esc, &reg_exp_esc, key_group, flags)?;
+        Ok(to_js_value(&entries))
+    })
+}
+
+/// Wrapper for `re_find_grouped_by_impl`. The result is a JS `Map`, with
+/// `null` as the key for matches where the group doesn't participate.
+#[wasm_bindgen]
+pub fn re_find_grouped_by(
+    text: &str,
+    reg_exp: &str,
+    group_name: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let grouped = re_find_grouped_by_impl(&text_esc, &reg_exp_esc, group_name, flags)?;
+        let serializer = serde_wasm_bindgen::Serializer::new().serialize_missing_as_null(true);
+        Ok(grouped
+            .serialize(&serializer)
+            .expect("failed to serialize result"))
+    })
+}
+
+/// Wrapper for `re_find_jsmatch_impl`
+#[wasm_bindgen]
+pub fn re_find_jsmatch(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let matches = re_find_jsmatch_impl(&text_esc, &reg_exp_esc, flags)?;
+        Ok(to_js_value(&matches))
+    })
+}
+
+/// Wrapper for `re_find_filtered_impl`
+#[wasm_bindgen]
+pub fn re_find_filtered(
+    text: &str,
+    reg_exp: &str,
+    filter_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let filter_esc =
+            unescape(filter_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let res = re_find_filtered_impl(&text_esc, &reg_exp_esc, &filter_esc, flags)?;
+        Ok(res.to_js_value())
+    })
+}
+
+/// Wrapper for `re_extract_groups_impl`
+#[wasm_bindgen]
+pub fn re_extract_groups(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let grid = re_extract_groups_impl(&text_esc, &reg_exp_esc, flags)?;
+        Ok(to_js_value(&grid))
+    })
+}
+
+/// Wrapper for `re_find_csv_impl`
+#[wasm_bindgen]
+pub fn re_find_csv(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let csv = re_find_csv_impl(&text_esc, &reg_exp_esc, flags)?;
+        Ok(to_js_value(&csv))
+    })
+}
+
+/// Wrapper for `re_find_starts_impl`, returning a `Uint32Array`
+#[wasm_bindgen]
+pub fn re_find_starts(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let starts = re_find_starts_impl(&text_esc, &reg_exp_esc, flags)?;
+        Ok(js_sys::Uint32Array::from(starts.as_slice()).into())
+    })
+}
+
+/// Wrapper for `re_find_in_lines_impl`. `text_sep` applies to every line.
+#[wasm_bindgen]
+pub fn re_find_in_lines(
+    lines: Vec<String>,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let lines_esc = lines
+            .iter()
+            .map(|line| unescape(line, &text_sep))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let res = re_find_in_lines_impl(&lines_esc, &reg_exp_esc, flags)?;
+        Ok(to_js_value(&res))
+    })
+}
+
+/// `SegmentedText` as a js class
+#[wasm_bindgen]
+impl SegmentedText {
+    /// Create a text with no segments, to be filled with `splice`
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of segments in the text
+    #[wasm_bindgen(getter)]
+    pub fn segment_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Wrapper for `splice_impl`. `text_sep` applies to every segment. Gives
+    /// `undefined`, or an error if nothing was changed.
+    pub fn splice(
+        &mut self,
+        start: usize,
+        delete_count: usize,
+        segments: Vec<String>,
+        text_sep: Option<String>,
+    ) -> JsValue {
+        wrap_erroring_fn(|| {
+            let segments_esc = segments
+                .iter()
+                .map(|segment| unescape(segment, &text_sep))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| (e, UnescapeSource::Text))?;
+            self.splice_impl(start, delete_count, &segments_esc)?;
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Wrapper for `find_impl`, with the same options as `re_find`
+    pub fn find(
+        &self,
+        reg_exp: &str,
+        flags: &str,
+        reg_exp_sep: Option<String>,
+        options: JsValue,
+    ) -> JsValue {
+        wrap_erroring_fn(|| {
+            let opts: FindOptions = parse_options(options)?;
+            let reg_exp_esc =
+                unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+            let res = self.find_impl(&reg_exp_esc, flags, &opts)?;
+            Ok(to_js_value(&res))
+        })
+    }
+}
+
+/// Wrapper for `re_test_cases_impl`. `cases` is an array of
+/// `[text, shouldMatch]` pairs, and `text_sep` applies to every text.
+#[wasm_bindgen]
+pub fn re_test_cases(
+    reg_exp: &str,
+    flags: &str,
+    cases: JsValue,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let cases: Vec<(String, bool)> = parse_options(cases)?;
+        let cases_esc = cases
+            .iter()
+            .map(|(text, expected)| unescape(text, &text_sep).map(|text| (text, *expected)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let res = re_test_cases_impl(&reg_exp_esc, flags, &cases_esc)?;
+        Ok(to_js_value(&res))
+    })
+}
+
+/// Wrapper for `re_match_lines_impl`
+#[wasm_bindgen]
+pub fn re_match_lines(
+    text: &str,
+    reg_exp: &str,
+    flags: &str,
+    text_sep: Option<String>,
+    reg_exp_sep: Option<String>,
+    options: JsValue,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let opts: BuildOptions = parse_options(options)?;
+        let text_esc = unescape(text, &text_sep).map_err(|e| (e, UnescapeSource::Text))?;
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let lines = re_match_lines_impl(&text_esc, &reg_exp_esc, flags, &opts)?;
+        Ok(to_js_value(&lines))
+    })
+}
+
+/// Wrapper for `re_expand_template_impl`. `groups` is an array of strings or
+/// nulls and `named` is an object of strings.
+#[wasm_bindgen]
+pub fn re_expand_template(
+    rep: &str,
+    groups: JsValue,
+    named: JsValue,
+    rep_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let groups: Vec<Option<String>> = parse_options(groups)?;
+        let named: HashMap<String, String> = parse_options(named)?;
+        Ok(to_js_value(&re_expand_template_impl(
+            &rep_esc, &groups, &named,
+        )))
+    })
+}
+
+/// Escape every metacharacter in `text`, so the result matches `text`
+/// literally
+#[wasm_bindgen]
+pub fn re_escape(text: &str) -> String {
+    regex_syntax::escape(text)
+}
+
+/// Undo `re_escape`, see `strops::unescape_pattern`
+#[wasm_bindgen]
+pub fn re_unescape_pattern(escaped: &str) -> String {
+    unescape_pattern(escaped)
+}
+
+/// Wrapper for `re_capabilities_impl`
+#[wasm_bindgen]
+pub fn re_capabilities() -> JsValue {
+    to_js_value(&re_capabilities_impl())
+}
+
+/// Read a flags string into an object with one boolean per flag, see
+/// `RegexFlags`
+#[wasm_bindgen]
+pub fn flags_to_object(flags: &str) -> JsValue {
+    wrap_erroring_fn(|| Ok(to_js_value(&RegexFlags::parse(flags)?)))
+}
+
+/// Turn an object like the result of `flags_to_object` back into a flags
+/// string, in canonical order. Missing keys are unset flags.
+#[wasm_bindgen]
+pub fn flags_from_object(obj: JsValue) -> JsValue {
+    wrap_erroring_fn(|| {
+        let flag_set: RegexFlags = parse_options(obj)?;
+        Ok(flag_set.to_flag_string().into())
+    })
+}
+
+/// Give the utf16 offset of the start of each line in `text`
+#[wasm_bindgen]
+pub fn line_offsets_utf16(text: &str) -> js_sys::Uint32Array {
+    js_sys::Uint32Array::from(line_starts_utf16(text).as_slice())
+}
+
+/// Wrapper for `re_replace_bytes_impl`. Takes and returns a `Uint8Array`, so
+/// invalid UTF-8 survives the round trip.
+#[wasm_bindgen]
+pub fn re_replace_bytes(
+    text: &[u8],
+    reg_exp: &str,
+    rep: &str,
+    flags: &str,
+    reg_exp_sep: Option<String>,
+    rep_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let rep_esc = unescape(rep, &rep_sep).map_err(|e| (e, UnescapeSource::Replacement))?;
+        let res = re_replace_bytes_impl(text, &reg_exp_esc, &rep_esc, flags)?;
+        Ok(js_sys::Uint8Array::from(res.as_ref()).into())
+    })
+}
+
+/// Check a pattern for likely mistakes, returning a list of warnings
+#[wasm_bindgen]
+pub fn re_lint(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let warnings = lint::lint(&reg_exp_esc, flags)?;
+        Ok(to_js_value(&warnings))
+    })
+}
+
+/// If the pattern is invalid, render the error with the pattern and an
+/// underline. Gives `None` for a valid pattern.
+fn re_error_render_impl(reg_exp: &str, flags: &str) -> Option<String> {
+    re_build(reg_exp, flags).err().map(|e| e.render(reg_exp))
+}
+
+/// Wrapper for `re_error_render_impl`. Returns `null` for a valid pattern.
+#[wasm_bindgen]
+pub fn re_error_render(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        Ok(to_js_value(&re_error_render_impl(&reg_exp_esc, flags)))
+    })
+}
+
+/// Describe the parts of a pattern, see `pattern::PatternInfo`
+#[wasm_bindgen]
+pub fn re_info(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let hir = re_hir(&reg_exp_esc, flags)?;
+        let info = pattern::info(&reg_exp_esc, flags, &hir)?;
+        Ok(to_js_value(&info))
+    })
+}
+
+/// Estimate how expensive a pattern is, see `pattern::complexity`
+#[wasm_bindgen]
+pub fn re_complexity(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let hir = re_hir(&reg_exp_esc, flags)?;
+        Ok(to_js_value(&pattern::complexity(&hir)))
+    })
+}
+
+/// Warn about parts of a pattern that behave differently on text that isn't
+/// valid utf8, see `pattern::byte_compat`
+#[wasm_bindgen]
+pub fn re_byte_compat(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let hir = re_hir(&reg_exp_esc, flags)?;
+        Ok(to_js_value(&pattern::byte_compat(&hir)))
+    })
+}
+
+/// Convert a pattern to a glob where possible, see `pattern::to_glob`
+#[wasm_bindgen]
+pub fn re_to_glob(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let hir = re_hir(&reg_exp_esc, flags)?;
+        Ok(to_js_value(&pattern::to_glob(&reg_exp_esc, flags, &hir)?))
+    })
+}
+
+/// Find where each capture group is defined, see `pattern::group_spans`
+#[wasm_bindgen]
+pub fn re_group_spans(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let spans = pattern::group_spans(&reg_exp_esc, flags)?;
+        Ok(to_js_value(&spans))
+    })
+}
+
+/// Remove whitespace and comments from a verbose (`x` flag) pattern, giving an
+/// equivalent pattern that can be used without `x`
+#[wasm_bindgen]
+pub fn re_strip_comments(reg_exp: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let stripped = pattern::strip_comments(&reg_exp_esc)?;
+        Ok(to_js_value(&stripped))
+    })
+}
+
+/// List capture group names that are used more than once, which would fail to
+/// compile
+#[wasm_bindgen]
+pub fn re_duplicate_group_names(reg_exp: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let dups = pattern::duplicate_group_names(&reg_exp_esc)?;
+        Ok(to_js_value(&dups))
+    })
+}
+
+/// Try to compile a pattern with every combination of the flags that change
+/// how it is parsed: `u`, `x`, and `o`. The other flags don't affect whether
+/// a pattern is valid. Combinations are ordered like binary counting, starting
+/// with no flags.
+fn re_validate_all_flags_impl(reg_exp: &str) -> Vec<FlagValiditySer> {
+    const PARSE_FLAGS: [char; 3] = ['u', 'x', 'o'];
+
+    (0..1 << PARSE_FLAGS.len())
+        .map(|mask| {
+            let flags: String = PARSE_FLAGS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, flag)| flag)
+                .collect();
+            let error = re_build(reg_exp, &flags).err();
+            FlagValiditySer {
+                flags,
+                valid: error.is_none(),
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Memory used by the compiled pattern, in bytes, see `engine::nfa_size`. The
+/// pattern is only compiled once. Empty patterns give 0.
+fn re_program_size_impl(reg_exp: &str, flags: &str) -> Result<usize, Error> {
+    let opts = BuildOptions::default();
+    match re_parse_with(reg_exp, flags, &opts)? {
+        Some(parsed) => engine::nfa_size(&parsed.hir, opts.size_limit),
+        None => Ok(0),
+    }
+}
+
+/// Give the range of lengths that a match can have, in utf8 bytes. The
+/// pattern matches a fixed length string if `min == max`.
+fn re_fixed_len_impl(reg_exp: &str, flags: &str) -> Result<FixedLenSer, Error> {
+    let hir = re_hir(reg_exp, flags)?;
+    let props = hir.properties();
+    Ok(FixedLenSer {
+        min: props.minimum_len(),
+        max: props.maximum_len(),
+    })
+}
+
+/// List the ranges of a pattern that is a single character class, after case
+/// folding from the `i` flag. Without the `u` flag the class is of bytes. A
+/// single character like `[a]` is translated to a literal, so it is accepted
+/// as a class of one. Any other pattern gives `Error::NotAClass`.
+fn re_class_ranges_impl(reg_exp: &str, flags: &str) -> Result<Vec<ClassRangeSer>, Error> {
+    let hir = re_hir(reg_exp, flags)?;
+    let range = |start: u32, end: u32| ClassRangeSer {
+        start_codepoint: start,
+        end_codepoint: end,
+    };
+    match hir.kind() {
+        HirKind::Class(Class::Unicode(cls)) => Ok(cls
+            .ranges()
+            .iter()
+            .map(|r| range(r.start().into(), r.end().into()))
+            .collect()),
+        HirKind::Class(Class::Bytes(cls)) => Ok(cls
+            .ranges()
+            .iter()
+            .map(|r| range(r.start().into(), r.end().into()))
+            .collect()),
+        HirKind::Literal(lit) => {
+            let mut chars = str::from_utf8(&lit.0)
+                .map_err(|_| Error::NotAClass)?
+                .chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Ok(vec![range(ch.into(), ch.into())]),
+                _ => Err(Error::NotAClass),
+            }
+        }
+        _ => Err(Error::NotAClass),
+    }
+}
+
+/// Wrapper for `re_class_ranges_impl`
+#[wasm_bindgen]
+pub fn re_class_ranges(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let ranges = re_class_ranges_impl(&reg_exp_esc, flags)?;
+        Ok(to_js_value(&ranges))
+    })
+}
+
+/// Wrapper for `re_fixed_len_impl`
+#[wasm_bindgen]
+pub fn re_fixed_len(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let len = re_fixed_len_impl(&reg_exp_esc, flags)?;
+        Ok(to_js_value(&len))
+    })
+}
+
+/// Wrapper for `re_validate_all_flags_impl`
+#[wasm_bindgen]
+pub fn re_validate_all_flags(reg_exp: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        Ok(to_js_value(&re_validate_all_flags_impl(&reg_exp_esc)))
+    })
+}
+
+/// Wrapper for `re_program_size_impl`
+#[wasm_bindgen]
+pub fn re_program_size(reg_exp: &str, flags: &str, reg_exp_sep: Option<String>) -> JsValue {
+    wrap_erroring_fn(|| {
+        let reg_exp_esc =
+            unescape(reg_exp, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let size = re_program_size_impl(&reg_exp_esc, flags)?;
+        Ok(to_js_value(&size))
+    })
+}
+
+/// Check whether two patterns match the same language. This is conservative:
+/// it compares the patterns after normalizing alternation order, captures and
+/// greediness, so `false` may be returned for patterns that are equivalent in
+/// a way we don't detect. It never returns `true` for different languages.
+fn re_equivalent_impl(pattern_a: &str, pattern_b: &str, flags: &str) -> Result<bool, Error> {
+    let hir_a = re_hir(pattern_a, flags)?;
+    let hir_b = re_hir(pattern_b, flags)?;
+    Ok(pattern::normalize_hir(&hir_a) == pattern::normalize_hir(&hir_b))
+}
+
+/// Wrapper for `re_equivalent_impl`
+#[wasm_bindgen]
+pub fn re_equivalent(
+    pattern_a: &str,
+    pattern_b: &str,
+    flags: &str,
+    reg_exp_sep: Option<String>,
+) -> JsValue {
+    wrap_erroring_fn(|| {
+        let a_esc = unescape(pattern_a, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let b_esc = unescape(pattern_b, &reg_exp_sep).map_err(|e| (e, UnescapeSource::RegExp))?;
+        let equivalent = re_equivalent_impl(&a_esc, &b_esc, flags)?;
+        Ok(equivalent.into())
+    })
+}
+
+/* helper functions */
+
+/// Replace the first match, or all matches if the global flag is set
+fn replace_bytes<'a>(state: &State, text: &'a [u8], rep: &[u8]) -> Cow<'a, [u8]> {
+    if state.global {
+        state.re.replace_all(text, rep)
+    } else {
+        state.re.replace(text, rep)
+    }
+}
+
+/// Rewrite references in a replacement template that use a name from
+/// `aliases` to refer to the group it maps to instead. `$$` and references
+/// that aren't aliases are left alone.
+fn apply_aliases<'a>(
+    rep: &'a str,
+    aliases: &HashMap<String, String>,
+    re: &Regex,
+) -> Result<Cow<'a, str>, Error> {
+    if aliases.is_empty() {
+        return Ok(Cow::Borrowed(rep));
+    }
+
+    for (alias, group) in aliases {
+        let known = match group.parse::<usize>() {
+            Ok(idx) => idx < re.captures_len(),
+            Err(_) => re.capture_names().flatten().any(|name| name == group),
+        };
+        if !known {
+            return Err(Error::InvalidOptions(format!(
+                "alias '{alias}' refers to unknown group '{group}'"
+            )));
+        }
+    }
+
+    let mut res = String::with_capacity(rep.len());
+    let mut rest = rep;
+    while let Some(pos) = rest.find('$') {
+        res.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            res.push_str("$$");
+            rest = after;
+            continue;
+        }
+
+        let alias = split_template_ref(rest)
+            .and_then(|(name, after)| aliases.get(name).map(|group| (group, after)));
+        match alias {
+            Some((group, after)) => {
+                res.push_str("${");
+                res.push_str(group);
+                res.push('}');
+                rest = after;
+            }
+            // Copy the reference as-is on the next iteration
+            None => res.push('$'),
+        }
+    }
+    res.push_str(rest);
+
+    Ok(Cow::Owned(res))
+}
+
+/// Expand a replacement template with the given group values rather than a
+/// match. References work like `Captures::expand`: `$1` or `${1}` is an entry
+/// of `groups`, `$name` or `${name}` is an entry of `named`, and `$$` is a
+/// literal `$`. References to a group that isn't given, or is `None`, expand
+/// to an empty string.
+fn re_expand_template_impl(
+    rep: &str,
+    groups: &[Option<String>],
+    named: &HashMap<String, String>,
+) -> String {
+    let mut res = String::with_capacity(rep.len());
+    let mut rest = rep;
+    while let Some(pos) = rest.find('$') {
+        res.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            res.push('$');
+            rest = after;
+            continue;
+        }
+
+        let Some((name, after)) = split_template_ref(rest) else {
+            res.push('$');
+            continue;
+        };
+        let value = match name.parse::<usize>() {
+            Ok(idx) => groups.get(idx).and_then(Option::as_deref),
+            Err(_) => named.get(name).map(String::as_str),
+        };
+        res.push_str(value.unwrap_or_default());
+        rest = after;
+    }
+    res.push_str(rest);
+
+    res
+}
+
+/// Check that every `$` in a replacement template is either `$$` or a group
+/// reference. `Captures::expand` treats anything else as a literal `$`, which
+/// is usually a typo.
+fn check_template(rep: &str) -> Result<(), Error> {
+    let mut pos = 0;
+    while let Some(found) = rep[pos..].find('$') {
+        let dollar = pos + found;
+        let rest = &rep[dollar + 1..];
+        pos = dollar + 1;
+
+        if rest.starts_with('$') {
+            pos += 1;
+            continue;
+        }
+        if split_template_ref(rest).is_some() {
+            continue;
+        }
+
+        let (len, message) = match rest.chars().next() {
+            None => (
+                1,
+                "trailing '$' is not a group reference; use '$$' for a literal '$'".to_owned(),
+            ),
+            Some('{') if !rest.contains('}') => (2, "unclosed '${'".to_owned()),
+            Some('{') => (3, "empty group name in '${}'".to_owned()),
+            Some(ch) => (
+                1 + ch.len_utf8(),
+                format!("'${ch}' is not a group reference; use '$$' for a literal '$'"),
+            ),
+        };
+        let range = dollar..dollar + len;
+        return Err(Error::InvalidReplacement(Box::new(
+            InvalidReplacement::new(rep, range, message),
+        )));
+    }
+    Ok(())
+}
+
+/// Split the group name off the start of `rest`, which follows a `$` in a
+/// replacement template. Uses the same rules as `Captures::expand`: anything
+/// up to `}` in braces, otherwise the longest run of word characters. Returns
+/// the name and the text after it, or `None` if this isn't a reference.
+fn split_template_ref(rest: &str) -> Option<(&str, &str)> {
+    let (name, after) = match rest.strip_prefix('{') {
+        Some(braced) => {
+            let end = braced.find('}')?;
+            (&braced[..end], &braced[end + 1..])
+        }
+        None => {
+            let end = rest
+                .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            rest.split_at(end)
+        }
+    };
+    (!name.is_empty()).then_some((name, after))
+}
+
+/// Helper method that lets us use `?` to propegate errors, and serializes
+/// everything to a `JsValue`
+fn wrap_erroring_fn<F>(f: F) -> JsValue
+where
+    F: FnOnce() -> Result<JsValue, Error>,
+{
+    match f() {
+        Ok(v) => v,
+        Err(e) => serde_wasm_bindgen::to_value(&e).expect("failed to serialize result"),
+    }
+}
+
+/// Read an options object passed from js. `undefined` or `null` gives the
+/// default options.
+fn parse_options<T: DeserializeOwned + Default>(value: JsValue) -> Result<T, Error> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(T::default());
+    }
+    serde_wasm_bindgen::from_value(value).map_err(|e| Error::InvalidOptions(e.to_string()))
+}
+
+/// Serialize any result to a `JsValue`
+fn to_js_value<T: Serialize + ?Sized>(value: &T) -> JsValue {
+    serde_wasm_bindgen::to_value(value).expect("failed to serialize result")
 }
 
 #[cfg(test)]
@@ -0,0 +1,235 @@
+//! Warnings for patterns that are valid (or nearly so) but probably don't do
+//! what the user expects
+
+use regex_syntax::ast::{self, Ast, ClassSet, ClassSetItem, Flag, GroupKind, LiteralKind};
+use serde::Serialize;
+
+use crate::error::{convert_re_spans, Error, Span, DEFAULT_LINE_BASE};
+
+/// A single warning about a pattern
+#[derive(Debug, Serialize, PartialEq)]
+pub struct LintWarning {
+    /// Identifier for the warning kind
+    pub kind: &'static str,
+    /// Description of the problem
+    pub message: String,
+    /// Location of the problem
+    pub span: Span,
+    /// Location of the problem with js offsets
+    pub span_utf16: Span,
+    /// Location of the problem with char offsets
+    pub span_char: Span,
+    /// Text that can replace the span to fix the problem, if there is one
+    pub suggestion: Option<String>,
+}
+
+/// Parse a pattern with the given flags and collect all warnings about it
+pub fn lint(reg_exp: &str, flags: &str) -> Result<Vec<LintWarning>, Error> {
+    let ast = ast::parse::ParserBuilder::new()
+        .ignore_whitespace(flags.contains('x'))
+        .octal(flags.contains('o'))
+        .build()
+        .parse(reg_exp)
+        .map_err(regex_syntax::Error::from)?;
+
+    let mut linter = Linter {
+        pattern: reg_exp,
+        warnings: Vec::new(),
+    };
+    linter.visit(&ast, flags.contains('u'));
+
+    Ok(linter.warnings)
+}
+
+/// State for walking the AST
+struct Linter<'a> {
+    pattern: &'a str,
+    warnings: Vec<LintWarning>,
+}
+
+impl<'a> Linter<'a> {
+    fn warn(
+        &mut self,
+        kind: &'static str,
+        message: String,
+        span: &ast::Span,
+        suggestion: Option<String>,
+    ) {
+        let (span, span_utf16, span_char) = convert_re_spans(self.pattern, span, DEFAULT_LINE_BASE);
+        self.warnings.push(LintWarning {
+            kind,
+            message,
+            span,
+            span_utf16,
+            span_char,
+            suggestion,
+        });
+    }
+
+    /// Check a single AST node. `unicode` is whether the unicode flag is
+    /// enabled at this point; the return value is the flag state after this
+    /// node, since inline flags like `(?u)` apply to the rest of their group.
+    fn visit(&mut self, ast: &Ast, unicode: bool) -> bool {
+        match ast {
+            Ast::Flags(set) => set.flags.flag_state(Flag::Unicode).unwrap_or(unicode),
+            Ast::ClassUnicode(cls) => {
+                if !unicode {
+                    self.warn_unicode_class(&cls.span);
+                }
+                unicode
+            }
+            Ast::ClassBracketed(cls) => {
+                self.visit_class_set(&cls.kind, unicode);
+                unicode
+            }
+            Ast::Literal(lit) => {
+                self.check_escape(lit, false);
+                unicode
+            }
+            Ast::Repetition(rep) => {
+                self.visit(&rep.ast, unicode);
+                unicode
+            }
+            Ast::Group(group) => {
+                let inner = match &group.kind {
+                    GroupKind::NonCapturing(flags) => {
+                        flags.flag_state(Flag::Unicode).unwrap_or(unicode)
+                    }
+                    _ => unicode,
+                };
+                self.visit(&group.ast, inner);
+                unicode
+            }
+            Ast::Alternation(alt) => alt
+                .asts
+                .iter()
+                .fold(unicode, |state, branch| self.visit(branch, state)),
+            Ast::Concat(concat) => concat
+                .asts
+                .iter()
+                .fold(unicode, |state, item| self.visit(item, state)),
+            Ast::Empty(_) | Ast::Dot(_) | Ast::Assertion(_) | Ast::ClassPerl(_) => unicode,
+        }
+    }
+
+    /// Check the contents of a bracketed class, where `unicode` is whether the
+    /// unicode flag is enabled
+    fn visit_class_set(&mut self, set: &ClassSet, unicode: bool) {
+        match set {
+            ClassSet::Item(item) => self.visit_class_item(item, unicode),
+            ClassSet::BinaryOp(op) => {
+                self.visit_class_set(&op.lhs, unicode);
+                self.visit_class_set(&op.rhs, unicode);
+            }
+        }
+    }
+
+    fn visit_class_item(&mut self, item: &ClassSetItem, unicode: bool) {
+        match item {
+            ClassSetItem::Unicode(cls) => {
+                if !unicode {
+                    self.warn_unicode_class(&cls.span);
+                }
+            }
+            ClassSetItem::Bracketed(cls) => self.visit_class_set(&cls.kind, unicode),
+            ClassSetItem::Union(union) => {
+                union
+                    .items
+                    .iter()
+                    .for_each(|inner| self.visit_class_item(inner, unicode));
+            }
+            ClassSetItem::Literal(lit) => self.check_escape(lit, true),
+            ClassSetItem::Range(range) => {
+                self.check_escape(&range.start, true);
+                self.check_escape(&range.end, true);
+            }
+            ClassSetItem::Empty(_) | ClassSetItem::Ascii(_) | ClassSetItem::Perl(_) => (),
+        }
+    }
+
+    /// Warn about an escaped literal that would mean the same thing without
+    /// the backslash. Escaped whitespace is never flagged since it is needed
+    /// with the `x` flag, and escapes are kept wherever the character could
+    /// have a meaning (e.g. `\-` in a class or `\#` anywhere).
+    fn check_escape(&mut self, lit: &ast::Literal, in_class: bool) {
+        let unneeded = match lit.kind {
+            LiteralKind::Superfluous => !lit.c.is_whitespace(),
+            // Class set operators only mean something inside a class, and
+            // repetition and grouping characters only outside of one
+            LiteralKind::Meta if in_class => ".+*?()|{}$".contains(lit.c),
+            LiteralKind::Meta => "-&~".contains(lit.c),
+            _ => false,
+        };
+        if !unneeded {
+            return;
+        }
+
+        let escape = &self.pattern[lit.span.start.offset..lit.span.end.offset];
+        let context = if in_class {
+            "in a class"
+        } else {
+            "outside of a class"
+        };
+        self.warn(
+            "RedundantEscape",
+            format!(
+                "'{escape}' doesn't need to be escaped {context}; try '{}'",
+                lit.c
+            ),
+            &lit.span,
+            Some(lit.c.to_string()),
+        );
+    }
+
+    fn warn_unicode_class(&mut self, span: &ast::Span) {
+        let cls = &self.pattern[span.start.offset..span.end.offset];
+        self.warn(
+            "UnicodeClassWithoutFlag",
+            format!("unicode class '{cls}' requires the unicode flag; try adding 'u'"),
+            span,
+            None,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unicode_class_without_flag() {
+        let warnings = lint(r"a\p{L}", "").unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, "UnicodeClassWithoutFlag");
+        assert_eq!(warnings[0].span.start.offset, 1);
+        assert_eq!(warnings[0].span.end.offset, 6);
+
+        assert_eq!(lint(r"[a\pL]", "").unwrap().len(), 1);
+        assert!(lint(r"a\p{L}", "u").unwrap().is_empty());
+        assert!(lint(r"(?u:\p{L})", "").unwrap().is_empty());
+        assert!(lint(r"(?u)a|\p{L}", "").unwrap().is_empty());
+        assert_eq!(lint(r"(?-u:\p{L})", "u").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_redundant_escape() {
+        let warnings = lint(r"a\/b", "").unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, "RedundantEscape");
+        assert_eq!(warnings[0].span.start.offset, 1);
+        assert_eq!(warnings[0].span.end.offset, 3);
+        assert_eq!(warnings[0].suggestion.as_deref(), Some("/"));
+
+        // `-` only needs escaping in a class, and `.` only outside of one
+        assert_eq!(lint(r"a\-b", "").unwrap().len(), 1);
+        assert!(lint(r"[a\-b]", "").unwrap().is_empty());
+        assert_eq!(lint(r"[\.\/]", "").unwrap().len(), 2);
+        assert!(lint(r"\.\*\[\]\\", "").unwrap().is_empty());
+        assert_eq!(
+            lint(r"[\.-z]", "").unwrap()[0].suggestion.as_deref(),
+            Some(".")
+        );
+        // Escaped spaces are needed with `x`
+        assert!(lint(r"(?x)a\ b", "").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,1019 @@
+//! Helpers that inspect or rewrite a pattern without running it
+
+use std::ops::Range;
+
+use regex_syntax::ast::{self, Ast, Flag, GroupKind};
+use regex_syntax::hir::{
+    Capture, Class, ClassBytes, ClassBytesRange, ClassUnicode, ClassUnicodeRange, Hir, HirKind,
+    Look, Repetition,
+};
+use serde::Serialize;
+
+use crate::error::{convert_re_spans, Error, RepetitionTooLarge, Span, DEFAULT_LINE_BASE};
+
+/// A capture group name that is used more than once
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DuplicateGroupName {
+    pub name: String,
+    /// Location of every group with this name, in order
+    pub spans: Vec<Span>,
+    /// The same locations with js offsets
+    pub spans_utf16: Vec<Span>,
+    /// The same locations with char offsets
+    pub spans_char: Vec<Span>,
+}
+
+/// Parse a pattern into its AST
+fn parse_ast(reg_exp: &str, ignore_whitespace: bool) -> Result<Ast, Error> {
+    let ast = ast::parse::ParserBuilder::new()
+        .ignore_whitespace(ignore_whitespace)
+        .build()
+        .parse(reg_exp)
+        .map_err(regex_syntax::Error::from)?;
+    Ok(ast)
+}
+
+/// Information about a pattern for display in an editor
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PatternInfo {
+    /// Every repetition operator. A repetition comes before any that it
+    /// contains, e.g. `+` before `*` in `(a*)+`.
+    pub repetitions: Vec<RepetitionInfo>,
+    /// Shortest possible match in utf8 bytes, 0 if the pattern can match
+    /// empty. `None` if the pattern can never match, e.g. `[^\s\S]`.
+    pub min_len: Option<usize>,
+    /// Shortest possible match in utf16 code units
+    pub min_len_utf16: Option<usize>,
+    /// Shortest possible match in chars
+    pub min_len_char: Option<usize>,
+}
+
+/// A single repetition operator like `*` or `{2,}?`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct RepetitionInfo {
+    /// Whether the repetition is greedy after applying the `U` flag, either
+    /// from the flags argument or inline
+    pub greedy: bool,
+    /// Location of the operator
+    pub span: Span,
+    /// Location of the operator with js offsets
+    pub span_utf16: Span,
+    /// Location of the operator with char offsets
+    pub span_char: Span,
+}
+
+/// Where a capture group is defined in the pattern
+#[derive(Debug, Serialize, PartialEq)]
+pub struct GroupSpan {
+    /// Index of the group, the same as `groupNum` in a match
+    pub group_num: u32,
+    pub name: Option<String>,
+    /// Location of the opening of the group, e.g. `(` or `(?P<name>`
+    pub span: Span,
+    /// The same location with js offsets
+    pub span_utf16: Span,
+    /// The same location with char offsets
+    pub span_char: Span,
+}
+
+/// A rough estimate of how expensive a pattern is to compile and run, see
+/// `complexity`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Complexity {
+    /// Overall score. Each node counts once for every time a counted
+    /// repetition around it repeats it, and each alternation branch counts
+    /// extra, since both make the compiled automaton larger.
+    pub score: u64,
+    /// Number of nodes in the parsed pattern
+    pub nodes: usize,
+    /// Total number of branches in all alternations
+    pub branches: usize,
+    /// Sum of the upper bound of every repetition. An unbounded repetition
+    /// like `a{2,}` counts as one more than its lower bound.
+    pub repeats: u64,
+}
+
+/// A part of a pattern that may not behave as expected when the text isn't
+/// valid utf8, see `byte_compat`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ByteCompatWarning {
+    /// Identifier for the warning kind
+    pub kind: &'static str,
+    /// Description of the problem
+    pub message: &'static str,
+}
+
+/// Collect `PatternInfo` for a pattern with the given flags. `hir` is the
+/// pattern translated with `flags`.
+pub fn info(reg_exp: &str, flags: &str, hir: &Hir) -> Result<PatternInfo, Error> {
+    let ast = ast::parse::ParserBuilder::new()
+        .ignore_whitespace(flags.contains('x'))
+        .octal(flags.contains('o'))
+        .build()
+        .parse(reg_exp)
+        .map_err(regex_syntax::Error::from)?;
+
+    let min_len = hir.properties().minimum_len();
+    let min_units = min_len.map(|_| min_len_units(hir));
+    let mut ret = PatternInfo {
+        repetitions: Vec::new(),
+        min_len,
+        min_len_utf16: min_units.map(|(utf16, _)| utf16),
+        min_len_char: min_units.map(|(_, chars)| chars),
+    };
+    collect_repetitions(reg_exp, &ast, flags.contains('U'), &mut ret.repetitions);
+    Ok(ret)
+}
+
+/// The shortest possible match of a HIR in `(utf16 code units, chars)`. A byte
+/// class counts as one of each, as if the byte were an ASCII char. Only
+/// meaningful if the HIR can match at all.
+fn min_len_units(hir: &Hir) -> (usize, usize) {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => (0, 0),
+        HirKind::Literal(lit) => {
+            let lit = String::from_utf8_lossy(&lit.0);
+            (lit.encode_utf16().count(), lit.chars().count())
+        }
+        HirKind::Class(Class::Unicode(cls)) => {
+            // Only a class without any char in the BMP needs a surrogate pair
+            let bmp = cls.ranges().first().is_none_or(|r| r.start() <= '\u{FFFF}');
+            (if bmp { 1 } else { 2 }, 1)
+        }
+        HirKind::Class(Class::Bytes(_)) => (1, 1),
+        HirKind::Repetition(rep) => {
+            let (utf16, chars) = min_len_units(&rep.sub);
+            let min = rep.min as usize;
+            (utf16.saturating_mul(min), chars.saturating_mul(min))
+        }
+        HirKind::Capture(cap) => min_len_units(&cap.sub),
+        HirKind::Concat(subs) => subs.iter().map(min_len_units).fold((0, 0), |acc, units| {
+            (acc.0.saturating_add(units.0), acc.1.saturating_add(units.1))
+        }),
+        HirKind::Alternation(subs) => subs
+            .iter()
+            .map(min_len_units)
+            .fold((usize::MAX, usize::MAX), |acc, units| {
+                (acc.0.min(units.0), acc.1.min(units.1))
+            }),
+    }
+}
+
+/// Add every repetition in `ast` to `dest`. `swap_greed` is whether the `U`
+/// flag is enabled at this point; the return value is the flag state after
+/// this node, since inline flags like `(?U)` apply to the rest of their group.
+fn collect_repetitions(
+    pattern: &str,
+    ast: &Ast,
+    swap_greed: bool,
+    dest: &mut Vec<RepetitionInfo>,
+) -> bool {
+    match ast {
+        Ast::Flags(set) => set.flags.flag_state(Flag::SwapGreed).unwrap_or(swap_greed),
+        Ast::Repetition(rep) => {
+            let (span, span_utf16, span_char) =
+                convert_re_spans(pattern, &rep.op.span, DEFAULT_LINE_BASE);
+            dest.push(RepetitionInfo {
+                greedy: rep.greedy != swap_greed,
+                span,
+                span_utf16,
+                span_char,
+            });
+            collect_repetitions(pattern, &rep.ast, swap_greed, dest);
+            swap_greed
+        }
+        Ast::Group(group) => {
+            let inner = match &group.kind {
+                GroupKind::NonCapturing(flags) => {
+                    flags.flag_state(Flag::SwapGreed).unwrap_or(swap_greed)
+                }
+                _ => swap_greed,
+            };
+            collect_repetitions(pattern, &group.ast, inner, dest);
+            swap_greed
+        }
+        Ast::Alternation(alt) => alt.asts.iter().fold(swap_greed, |state, branch| {
+            collect_repetitions(pattern, branch, state, dest)
+        }),
+        Ast::Concat(concat) => concat.asts.iter().fold(swap_greed, |state, item| {
+            collect_repetitions(pattern, item, state, dest)
+        }),
+        Ast::Empty(_)
+        | Ast::Literal(_)
+        | Ast::Dot(_)
+        | Ast::Assertion(_)
+        | Ast::ClassUnicode(_)
+        | Ast::ClassPerl(_)
+        | Ast::ClassBracketed(_) => swap_greed,
+    }
+}
+
+/// Parse a pattern in verbose (`x`) mode and print it back without any of the
+/// whitespace or comments. The result means the same thing without `x`.
+pub fn strip_comments(reg_exp: &str) -> Result<String, Error> {
+    let ast = parse_ast(reg_exp, true)?;
+    let mut ret = String::with_capacity(reg_exp.len());
+    // Writing to a `String` can't fail
+    ast::print::Printer::new().print(&ast, &mut ret).unwrap();
+    Ok(ret)
+}
+
+/// Find all capture group names that are used more than once.
+///
+/// The parser stops at the first duplicate, so each time it does we rename
+/// that group to something unused and parse again. Byte ranges are mapped back
+/// to the original pattern at the end. Other syntax errors are returned as-is.
+pub fn duplicate_group_names(reg_exp: &str) -> Result<Vec<DuplicateGroupName>, Error> {
+    let mut working = reg_exp.to_owned();
+    // Renamed ranges in `working`, as `(start, original_len, new_len)`
+    let mut edits: Vec<(usize, usize, usize)> = Vec::new();
+    // Each name with byte ranges in `working`
+    let mut found: Vec<(String, Vec<Range<usize>>)> = Vec::new();
+
+    loop {
+        let err = match ast::parse::Parser::new().parse(&working) {
+            Ok(_) => break,
+            Err(e) => e,
+        };
+        let ast::ErrorKind::GroupNameDuplicate { original } = err.kind() else {
+            return Err(regex_syntax::Error::Parse(err).into());
+        };
+
+        let dup = err.span().start.offset..err.span().end.offset;
+        let name = working[dup.clone()].to_owned();
+        match found.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, ranges)) => ranges.push(dup.clone()),
+            None => found.push((
+                name,
+                vec![original.start.offset..original.end.offset, dup.clone()],
+            )),
+        }
+
+        // Pick a name that doesn't appear anywhere in the pattern
+        let mut placeholder = format!("_dup{}", edits.len());
+        while working.contains(&placeholder) {
+            placeholder.push('_');
+        }
+        edits.push((dup.start, dup.len(), placeholder.len()));
+        working.replace_range(dup, &placeholder);
+    }
+
+    // Undo the length changes of every edit before a position
+    let to_original = |pos: usize| {
+        edits
+            .iter()
+            .filter(|(start, _, new_len)| start + new_len <= pos)
+            .fold(pos, |acc, (_, old_len, new_len)| acc + old_len - new_len)
+    };
+
+    let ret = found
+        .into_iter()
+        .map(|(name, ranges)| {
+            let mut dup = DuplicateGroupName {
+                name,
+                spans: Vec::with_capacity(ranges.len()),
+                spans_utf16: Vec::with_capacity(ranges.len()),
+                spans_char: Vec::with_capacity(ranges.len()),
+            };
+            for range in ranges {
+                let range = to_original(range.start)..to_original(range.end);
+                let (span, span_utf16, span_char) =
+                    Span::from_offsets(reg_exp, range, DEFAULT_LINE_BASE);
+                dup.spans.push(span);
+                dup.spans_utf16.push(span_utf16);
+                dup.spans_char.push(span_char);
+            }
+            dup
+        })
+        .collect();
+
+    Ok(ret)
+}
+
+/// Find the opening of every capture group, in group index order. The entire
+/// match (group 0) isn't included since it has no definition.
+pub fn group_spans(reg_exp: &str, flags: &str) -> Result<Vec<GroupSpan>, Error> {
+    let ast = ast::parse::ParserBuilder::new()
+        .ignore_whitespace(flags.contains('x'))
+        .octal(flags.contains('o'))
+        .build()
+        .parse(reg_exp)
+        .map_err(regex_syntax::Error::from)?;
+    ast::visit(
+        &ast,
+        GroupSpanCollector {
+            pattern: reg_exp,
+            spans: Vec::new(),
+        },
+    )
+}
+
+/// Visitor for `group_spans`. Groups are visited in the order their opening
+/// parens appear, which is group index order.
+struct GroupSpanCollector<'a> {
+    pattern: &'a str,
+    spans: Vec<GroupSpan>,
+}
+
+impl ast::Visitor for GroupSpanCollector<'_> {
+    type Output = Vec<GroupSpan>;
+    type Err = Error;
+
+    fn finish(self) -> Result<Vec<GroupSpan>, Error> {
+        Ok(self.spans)
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), Error> {
+        let Ast::Group(group) = ast else {
+            return Ok(());
+        };
+        let (group_num, name) = match &group.kind {
+            GroupKind::CaptureIndex(idx) => (*idx, None),
+            GroupKind::CaptureName { name, .. } => (name.index, Some(name.name.clone())),
+            GroupKind::NonCapturing(_) => return Ok(()),
+        };
+
+        // The opening is everything before the group's contents
+        let range = group.span.start.offset..group.ast.span().start.offset;
+        let (span, span_utf16, span_char) =
+            Span::from_offsets(self.pattern, range, DEFAULT_LINE_BASE);
+        self.spans.push(GroupSpan {
+            group_num,
+            name,
+            span,
+            span_utf16,
+            span_char,
+        });
+        Ok(())
+    }
+}
+
+/// Return an error if any counted repetition (e.g. `a{1000}`) has a bound
+/// larger than `limit`. This catches patterns that would compile to a huge
+/// program with a clearer error than the size limit gives. Error lines are
+/// numbered from `line_base`.
+pub fn check_repetitions(
+    reg_exp: &str,
+    ignore_whitespace: bool,
+    octal: bool,
+    limit: u32,
+    line_base: usize,
+) -> Result<(), Error> {
+    let ast = ast::parse::ParserBuilder::new()
+        .ignore_whitespace(ignore_whitespace)
+        .octal(octal)
+        .build()
+        .parse(reg_exp)
+        .map_err(|e| Error::from_syntax(e.into(), line_base))?;
+    ast::visit(
+        &ast,
+        RepetitionChecker {
+            pattern: reg_exp,
+            limit,
+            line_base,
+        },
+    )
+}
+
+/// Visitor for `check_repetitions`
+struct RepetitionChecker<'a> {
+    pattern: &'a str,
+    limit: u32,
+    line_base: usize,
+}
+
+impl ast::Visitor for RepetitionChecker<'_> {
+    type Output = ();
+    type Err = Error;
+
+    fn finish(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), Error> {
+        let Ast::Repetition(rep) = ast else {
+            return Ok(());
+        };
+        let ast::RepetitionKind::Range(range) = &rep.op.kind else {
+            return Ok(());
+        };
+        let count = match *range {
+            ast::RepetitionRange::Exactly(n)
+            | ast::RepetitionRange::AtLeast(n)
+            | ast::RepetitionRange::Bounded(_, n) => n,
+        };
+        if count > self.limit {
+            return Err(Error::RepetitionTooLarge(Box::new(
+                RepetitionTooLarge::new(
+                    self.pattern,
+                    &rep.op.span,
+                    count,
+                    self.limit,
+                    self.line_base,
+                ),
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Rewrite a HIR into a canonical form that matches the same language. This
+/// removes capture groups, makes all repetitions greedy, and sorts and dedups
+/// alternations. Classes are already canonical after translation.
+pub fn normalize_hir(hir: &Hir) -> Hir {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Literal(_) | HirKind::Class(_) | HirKind::Look(_) => hir.clone(),
+        HirKind::Repetition(rep) => Hir::repetition(Repetition {
+            greedy: true,
+            sub: Box::new(normalize_hir(&rep.sub)),
+            ..*rep
+        }),
+        HirKind::Capture(cap) => normalize_hir(&cap.sub),
+        HirKind::Concat(subs) => Hir::concat(subs.iter().map(normalize_hir).collect()),
+        HirKind::Alternation(subs) => {
+            // `Hir` isn't `Ord`, so sort by the printed pattern instead
+            let mut branches: Vec<(String, Hir)> = subs
+                .iter()
+                .map(normalize_hir)
+                .map(|sub| (sub.to_string(), sub))
+                .collect();
+            branches.sort_by(|a, b| a.0.cmp(&b.0));
+            branches.dedup_by(|a, b| a.0 == b.0);
+            Hir::alternation(branches.into_iter().map(|(_, sub)| sub).collect())
+        }
+    }
+}
+
+/// Estimate the complexity of a parsed pattern. This is a quick heuristic for
+/// warning about slow patterns, not the real size of any automaton.
+pub fn complexity(hir: &Hir) -> Complexity {
+    let mut res = Complexity {
+        score: 0,
+        nodes: 0,
+        branches: 0,
+        repeats: 0,
+    };
+    add_complexity(hir, 1, &mut res);
+    res
+}
+
+/// Add a node and everything below it to `res`. `factor` is the number of
+/// times the node is repeated by the repetitions around it.
+fn add_complexity(hir: &Hir, factor: u64, res: &mut Complexity) {
+    res.nodes += 1;
+    res.score = res.score.saturating_add(factor);
+    match hir.kind() {
+        HirKind::Empty | HirKind::Literal(_) | HirKind::Class(_) | HirKind::Look(_) => (),
+        HirKind::Repetition(rep) => {
+            let bound = u64::from(rep.max.unwrap_or(rep.min.saturating_add(1)).max(1));
+            res.repeats = res.repeats.saturating_add(bound);
+            add_complexity(&rep.sub, factor.saturating_mul(bound), res);
+        }
+        HirKind::Capture(cap) => add_complexity(&cap.sub, factor, res),
+        HirKind::Concat(subs) => subs.iter().for_each(|sub| add_complexity(sub, factor, res)),
+        HirKind::Alternation(subs) => {
+            res.branches += subs.len();
+            res.score = res
+                .score
+                .saturating_add(factor.saturating_mul(subs.len() as u64));
+            subs.iter().for_each(|sub| add_complexity(sub, factor, res));
+        }
+    }
+}
+
+/// Find the unicode features of a parsed pattern that behave differently on
+/// text that isn't valid utf8, such as binary data. Each kind of warning is
+/// given once, in the order it first appears.
+pub fn byte_compat(hir: &Hir) -> Vec<ByteCompatWarning> {
+    let mut warnings = Vec::new();
+    add_byte_compat(hir, &mut warnings);
+    warnings
+}
+
+/// Add warnings for a node and everything below it to `dest`
+fn add_byte_compat(hir: &Hir, dest: &mut Vec<ByteCompatWarning>) {
+    let warning = match hir.kind() {
+        // Only classes that reach outside ASCII need to decode utf8
+        HirKind::Class(Class::Unicode(cls))
+            if cls
+                .ranges()
+                .last()
+                .is_some_and(|range| !range.end().is_ascii()) =>
+        {
+            Some(ByteCompatWarning {
+                kind: "UnicodeClass",
+                message: "`.` and unicode classes only match valid UTF-8, so they never \
+                          match bytes of invalid UTF-8; use `(?-u:...)` to match any byte",
+            })
+        }
+        HirKind::Look(
+            Look::WordUnicode
+            | Look::WordUnicodeNegate
+            | Look::WordStartUnicode
+            | Look::WordEndUnicode
+            | Look::WordStartHalfUnicode
+            | Look::WordEndHalfUnicode,
+        ) => Some(ByteCompatWarning {
+            kind: "UnicodeWordBoundary",
+            message: "unicode word boundaries treat bytes of invalid UTF-8 as non-word \
+                      characters; use `(?-u:\\b)` for ASCII word boundaries",
+        }),
+        _ => None,
+    };
+    if let Some(warning) = warning {
+        if !dest.iter().any(|w| w.kind == warning.kind) {
+            dest.push(warning);
+        }
+    }
+
+    match hir.kind() {
+        HirKind::Empty | HirKind::Literal(_) | HirKind::Class(_) | HirKind::Look(_) => (),
+        HirKind::Repetition(rep) => add_byte_compat(&rep.sub, dest),
+        HirKind::Capture(cap) => add_byte_compat(&cap.sub, dest),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => {
+            subs.iter().for_each(|sub| add_byte_compat(sub, dest))
+        }
+    }
+}
+
+/// Convert a pattern to a glob that matches the same names, where `*` matches
+/// any string, `?` any char, and `[...]` or `[!...]` a class. The glob has to
+/// match the entire name, so the pattern is treated as if it were anchored at
+/// both ends. `hir` is the pattern translated with `flags`.
+///
+/// This is best effort: anything beyond literals, classes, groups, and
+/// repetitions of `.` or with an exact count is rejected. The AST is checked
+/// for alternations since translation turns `a|b` into the class `[ab]`.
+pub fn to_glob(reg_exp: &str, flags: &str, hir: &Hir) -> Result<String, Error> {
+    let ast = ast::parse::ParserBuilder::new()
+        .ignore_whitespace(flags.contains('x'))
+        .octal(flags.contains('o'))
+        .build()
+        .parse(reg_exp)
+        .map_err(regex_syntax::Error::from)?;
+    ast::visit(&ast, AlternationChecker)?;
+
+    let mut glob = String::new();
+    push_glob(hir, &mut glob)?;
+    Ok(glob)
+}
+
+/// Visitor for `to_glob` that rejects any alternation
+struct AlternationChecker;
+
+impl ast::Visitor for AlternationChecker {
+    type Output = ();
+    type Err = Error;
+
+    fn finish(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), Error> {
+        match ast {
+            Ast::Alternation(_) => Err(not_glob("alternation")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Error for a part of a pattern that globs can't express
+fn not_glob(what: &str) -> Error {
+    Error::NotGlobConvertible(format!("{what} can't be written as a glob"))
+}
+
+/// Append the glob for a node and everything below it to `dest`
+fn push_glob(hir: &Hir, dest: &mut String) -> Result<(), Error> {
+    match hir.kind() {
+        HirKind::Empty => (),
+        HirKind::Literal(lit) => {
+            let lit = std::str::from_utf8(&lit.0)
+                .map_err(|_| not_glob("a literal that isn't valid UTF-8"))?;
+            for ch in lit.chars() {
+                match ch {
+                    // Brackets are the most portable way to escape in globs
+                    '*' | '?' | '[' => {
+                        dest.extend(['[', ch, ']']);
+                    }
+                    '\\' => return Err(not_glob("a backslash")),
+                    _ => dest.push(ch),
+                }
+            }
+        }
+        HirKind::Class(cls) => push_glob_class(cls, dest)?,
+        HirKind::Repetition(rep) => match rep.sub.kind() {
+            HirKind::Class(cls) if is_any_char(cls) => {
+                if rep.max.is_some_and(|max| max != rep.min) {
+                    return Err(not_glob("a repetition of `.` with an upper bound"));
+                }
+                dest.push_str(&"?".repeat(rep.min as usize));
+                if rep.max.is_none() {
+                    dest.push('*');
+                }
+            }
+            _ if rep.max == Some(rep.min) => {
+                let mut sub = String::new();
+                push_glob(&rep.sub, &mut sub)?;
+                dest.push_str(&sub.repeat(rep.min as usize));
+            }
+            _ => return Err(not_glob("a repetition of something other than `.`")),
+        },
+        HirKind::Capture(cap) => push_glob(&cap.sub, dest)?,
+        HirKind::Concat(subs) => {
+            for sub in subs {
+                push_glob(sub, dest)?;
+            }
+        }
+        HirKind::Alternation(_) => return Err(not_glob("alternation")),
+        HirKind::Look(_) => return Err(not_glob("an anchor or word boundary")),
+    }
+    Ok(())
+}
+
+/// Whether a class matches any char, except possibly a newline, like `.`
+fn is_any_char(cls: &Class) -> bool {
+    let mut negated = cls.clone();
+    negated.negate();
+    match negated {
+        Class::Unicode(cls) => cls
+            .ranges()
+            .iter()
+            .all(|r| r.start() == '\n' && r.end() == '\n'),
+        Class::Bytes(cls) => cls
+            .ranges()
+            .iter()
+            .all(|r| r.start() == b'\n' && r.end() == b'\n'),
+    }
+}
+
+/// Append the glob for a class to `dest`: `?` for any char, otherwise a
+/// bracket expression with whichever of the class or its negation has fewer
+/// ranges
+fn push_glob_class(cls: &Class, dest: &mut String) -> Result<(), Error> {
+    if is_any_char(cls) {
+        dest.push('?');
+        return Ok(());
+    }
+
+    let mut negated = cls.clone();
+    negated.negate();
+    let mut candidates = [(false, cls), (true, &negated)];
+    candidates.sort_by_key(|(_, cls)| class_ranges(cls).map_or(usize::MAX, |r| r.len()));
+    let (negate, cls) = candidates[0];
+    let mut ranges = class_ranges(cls).ok_or_else(|| not_glob("a class of non-ASCII bytes"))?;
+
+    // `]` only works as the first item and `-` as the last
+    ranges.sort_by_key(|&range| match range {
+        (']', ']') => 0,
+        ('-', '-') => 2,
+        _ => 1,
+    });
+    dest.push('[');
+    if negate {
+        dest.push('!');
+    }
+    for (idx, &(start, end)) in ranges.iter().enumerate() {
+        let special = |ch| match ch {
+            ']' => !(idx == 0 && start == end),
+            '-' => !(idx == ranges.len() - 1 && start == end),
+            '\\' | '[' | '!' | '^' => true,
+            _ => false,
+        };
+        if special(start) || special(end) {
+            return Err(not_glob(
+                "a class containing `\\`, `[`, `]`, `-`, `!` or `^`",
+            ));
+        }
+        dest.push(start);
+        if start != end {
+            dest.push('-');
+            dest.push(end);
+        }
+    }
+    dest.push(']');
+    Ok(())
+}
+
+/// The ranges of a class as chars, or `None` for a byte class that has
+/// non-ASCII bytes
+fn class_ranges(cls: &Class) -> Option<Vec<(char, char)>> {
+    match cls {
+        Class::Unicode(cls) => Some(cls.ranges().iter().map(|r| (r.start(), r.end())).collect()),
+        Class::Bytes(cls) => cls
+            .ranges()
+            .iter()
+            .map(|r| {
+                r.end()
+                    .is_ascii()
+                    .then(|| (char::from(r.start()), char::from(r.end())))
+            })
+            .collect(),
+    }
+}
+
+/// Make every ASCII letter in a HIR match either case, leaving all other
+/// characters alone. This is case insensitivity without unicode case folding,
+/// e.g. `k` doesn't match the Kelvin sign `\u{212A}`.
+pub fn fold_ascii_case(hir: &Hir) -> Hir {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => hir.clone(),
+        HirKind::Literal(lit) => Hir::concat(
+            lit.0
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_alphabetic() {
+                        let (lower, upper) = (b.to_ascii_lowercase(), b.to_ascii_uppercase());
+                        Hir::class(Class::Bytes(ClassBytes::new([
+                            ClassBytesRange::new(upper, upper),
+                            ClassBytesRange::new(lower, lower),
+                        ])))
+                    } else {
+                        Hir::literal([b])
+                    }
+                })
+                .collect(),
+        ),
+        HirKind::Class(Class::Unicode(cls)) => {
+            let mut cls = cls.clone();
+            let other_case = cls
+                .ranges()
+                .iter()
+                .flat_map(|r| swap_ascii_case(u32::from(r.start()), u32::from(r.end())))
+                .map(|(start, end)| {
+                    // Both ends are ASCII letters
+                    ClassUnicodeRange::new(char::from(start as u8), char::from(end as u8))
+                });
+            cls.union(&ClassUnicode::new(other_case));
+            Hir::class(Class::Unicode(cls))
+        }
+        HirKind::Class(Class::Bytes(cls)) => {
+            let mut cls = cls.clone();
+            let other_case = cls
+                .ranges()
+                .iter()
+                .flat_map(|r| swap_ascii_case(u32::from(r.start()), u32::from(r.end())))
+                .map(|(start, end)| ClassBytesRange::new(start as u8, end as u8));
+            cls.union(&ClassBytes::new(other_case));
+            Hir::class(Class::Bytes(cls))
+        }
+        HirKind::Repetition(rep) => Hir::repetition(Repetition {
+            sub: Box::new(fold_ascii_case(&rep.sub)),
+            ..*rep
+        }),
+        HirKind::Capture(cap) => Hir::capture(Capture {
+            index: cap.index,
+            name: cap.name.clone(),
+            sub: Box::new(fold_ascii_case(&cap.sub)),
+        }),
+        HirKind::Concat(subs) => Hir::concat(subs.iter().map(fold_ascii_case).collect()),
+        HirKind::Alternation(subs) => Hir::alternation(subs.iter().map(fold_ascii_case).collect()),
+    }
+}
+
+/// The parts of the range `start..=end` that are ASCII letters, converted to
+/// the other case
+fn swap_ascii_case(start: u32, end: u32) -> impl Iterator<Item = (u32, u32)> {
+    const LOWER: (u32, u32) = (b'a' as u32, b'z' as u32);
+    const UPPER: (u32, u32) = (b'A' as u32, b'Z' as u32);
+    const SHIFT: u32 = LOWER.0 - UPPER.0;
+
+    let lower = (start.max(LOWER.0), end.min(LOWER.1));
+    let upper = (start.max(UPPER.0), end.min(UPPER.1));
+    [
+        (lower.0 <= lower.1).then(|| (lower.0 - SHIFT, lower.1 - SHIFT)),
+        (upper.0 <= upper.1).then(|| (upper.0 + SHIFT, upper.1 + SHIFT)),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_comments() {
+        let pat = r"
+            (?P<year>\d{4}) # the year
+            -               # separator
+            (?P<month>\d{2})
+            [ a - c ]       # whitespace in classes is ignored too
+            \ \#            # but escaped whitespace and hashes stay
+        ";
+        assert_eq!(
+            strip_comments(pat).unwrap(),
+            r"(?P<year>\d{4})-(?P<month>\d{2})[a-c]\ \#"
+        );
+        assert_eq!(strip_comments("a b c").unwrap(), "abc");
+        assert!(strip_comments("a (b").is_err());
+    }
+
+    #[test]
+    fn test_info_min_len() {
+        let min_len = |pat| {
+            let info = info(pat, "", &regex_syntax::parse(pat).unwrap()).unwrap();
+            (info.min_len, info.min_len_utf16, info.min_len_char)
+        };
+
+        assert_eq!(min_len(r"\d{4}"), (Some(4), Some(4), Some(4)));
+        assert_eq!(min_len("a*"), (Some(0), Some(0), Some(0)));
+        assert_eq!(min_len("é|😀+"), (Some(2), Some(1), Some(1)));
+        assert_eq!(min_len("😀{2}"), (Some(8), Some(4), Some(2)));
+        assert_eq!(min_len(r"[^\s\S]"), (None, None, None));
+    }
+
+    #[test]
+    fn test_info_greedy() {
+        let greedy = |pat, flags| -> Vec<(bool, usize)> {
+            info(pat, flags, &regex_syntax::parse(pat).unwrap())
+                .unwrap()
+                .repetitions
+                .iter()
+                .map(|rep| (rep.greedy, rep.span.start.offset))
+                .collect()
+        };
+
+        assert_eq!(greedy("a+?b*", ""), [(false, 1), (true, 4)]);
+        assert_eq!(greedy("a+?b*", "U"), [(true, 1), (false, 4)]);
+        // Inline flags, scoped to their group
+        assert_eq!(greedy("(?U:a*)b*", ""), [(false, 5), (true, 8)]);
+        assert_eq!(greedy("a*(?U)b*", ""), [(true, 1), (false, 7)]);
+        assert_eq!(greedy("(?-U)a*", "U"), [(true, 6)]);
+        // Nested repetitions
+        assert_eq!(greedy("(a{2}?)+", ""), [(true, 7), (false, 2)]);
+    }
+
+    #[test]
+    fn test_group_spans() {
+        let spans = |pat, flags| -> Vec<(u32, Option<String>, usize, usize)> {
+            group_spans(pat, flags)
+                .unwrap()
+                .into_iter()
+                .map(|g| (g.group_num, g.name, g.span.start.offset, g.span.end.offset))
+                .collect()
+        };
+
+        assert_eq!(
+            spans("(a)(?P<b>c)", ""),
+            [(1, None, 0, 1), (2, Some("b".to_owned()), 3, 9)]
+        );
+        // Nested groups are in index order, and non-capturing groups are left
+        // out
+        assert_eq!(
+            spans("(?:((?<x>a)))", ""),
+            [(1, None, 3, 4), (2, Some("x".to_owned()), 4, 9)]
+        );
+
+        let group = &group_spans("é(?P<n>a)", "").unwrap()[0];
+        assert_eq!(group.span_utf16.start.offset, 1);
+        assert_eq!(group.span_utf16.end.offset, 7);
+        assert_eq!(group.span_char.end.offset, 7);
+        assert!(group_spans("(a", "").is_err());
+    }
+
+    #[test]
+    fn test_complexity() {
+        let parse = |reg_exp| regex_syntax::parse(reg_exp).unwrap();
+
+        let simple = complexity(&parse("abc"));
+        assert_eq!(simple.score, 1);
+        assert_eq!((simple.nodes, simple.branches, simple.repeats), (1, 0, 0));
+
+        let alternated = complexity(&parse("(foo|bar|baz[0-9]|qux)+"));
+        assert_eq!(alternated.branches, 4);
+        assert_eq!(alternated.repeats, 2);
+        assert!(alternated.score > simple.score);
+
+        // Counted repetitions multiply what they contain
+        let counted = complexity(&parse("(a|b){100}"));
+        assert!(counted.score > alternated.score);
+        assert_eq!(complexity(&Hir::empty()).score, 1);
+    }
+
+    #[test]
+    fn test_byte_compat() {
+        let parse = |reg_exp, unicode| {
+            regex_syntax::ParserBuilder::new()
+                .unicode(unicode)
+                .utf8(false)
+                .build()
+                .parse(reg_exp)
+                .unwrap()
+        };
+        let kinds = |hir| {
+            byte_compat(&hir)
+                .into_iter()
+                .map(|w| w.kind)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(kinds(parse(r"\bfoo\b", true)), ["UnicodeWordBoundary"]);
+        assert_eq!(
+            kinds(parse(r"(\w+|.)\B", true)),
+            ["UnicodeClass", "UnicodeWordBoundary"]
+        );
+        // ASCII-only classes and ASCII mode don't need valid utf8
+        assert!(kinds(parse(r"[a-z]+[0-9]", true)).is_empty());
+        assert!(kinds(parse(r"\b.\w\b", false)).is_empty());
+    }
+
+    #[test]
+    fn test_to_glob() {
+        let glob = |reg_exp| to_glob(reg_exp, "", &regex_syntax::parse(reg_exp).unwrap());
+
+        assert_eq!(glob(r"foo.*\.txt").unwrap(), "foo*.txt");
+        assert_eq!(glob(r"a.b.+").unwrap(), "a?b?*");
+        assert_eq!(
+            glob(r"img[0-9]{2}[^a-z]\*").unwrap(),
+            "img[0-9][0-9][!a-z][*]"
+        );
+        assert_eq!(glob(r"[-_a]").unwrap(), "[_a-]");
+        assert_eq!(glob("").unwrap(), "");
+
+        for reg_exp in ["(a|b)", "^foo", r"\bfoo", "a+", ".?", r"[\[a]"] {
+            assert!(
+                matches!(glob(reg_exp), Err(Error::NotGlobConvertible(_))),
+                "{reg_exp}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_ascii_case() {
+        let fold = |pat| {
+            let hir = regex_syntax::ParserBuilder::new()
+                .unicode(true)
+                .build()
+                .parse(pat)
+                .unwrap();
+            fold_ascii_case(&hir).to_string()
+        };
+
+        assert_eq!(fold("a1"), "(?:(?-u:[Aa])1)");
+        assert_eq!(fold("[X-c]"), r"[A-CX-cx-z]");
+        assert_eq!(fold("(?P<n>é)+?"), "(?P<n>é)+?");
+        assert_eq!(
+            swap_ascii_case(0, 0x10ffff).collect::<Vec<_>>(),
+            [(65, 90), (97, 122)]
+        );
+    }
+
+    #[test]
+    fn test_check_repetitions() {
+        let Err(Error::RepetitionTooLarge(err)) =
+            check_repetitions("ba{100000}", false, false, 1000, DEFAULT_LINE_BASE)
+        else {
+            panic!("expected a repetition error");
+        };
+        assert_eq!((err.count, err.limit), (100000, 1000));
+
+        // Every form of counted repetition, including nested ones
+        assert!(check_repetitions("a{2000,}", false, false, 1000, DEFAULT_LINE_BASE).is_err());
+        assert!(check_repetitions("a{1,2000}", false, false, 1000, DEFAULT_LINE_BASE).is_err());
+        assert!(check_repetitions("(a{5}){2000}", false, false, 1000, DEFAULT_LINE_BASE).is_err());
+        assert!(check_repetitions("(?:a{2000})?", false, false, 1000, DEFAULT_LINE_BASE).is_err());
+
+        assert!(check_repetitions("a{1000}b*c+", false, false, 1000, DEFAULT_LINE_BASE).is_ok());
+        assert!(check_repetitions(r"\{2000}", false, false, 1000, DEFAULT_LINE_BASE).is_ok());
+    }
+
+    #[test]
+    fn test_duplicate_group_names() {
+        let dups = duplicate_group_names("(?P<x>a)(?P<x>b)").unwrap();
+        assert_eq!(dups.len(), 1);
+        assert_eq!(dups[0].name, "x");
+        let offsets: Vec<_> = dups[0]
+            .spans
+            .iter()
+            .map(|sp| (sp.start.offset, sp.end.offset))
+            .collect();
+        assert_eq!(offsets, [(4, 5), (12, 13)]);
+
+        // Several names, with renames shifting later offsets
+        let pat = "(?P<long>a)(?P<b>.)(?P<long>c)(?P<b>d)(?P<long>e)";
+        let dups = duplicate_group_names(pat).unwrap();
+        let found: Vec<_> = dups
+            .iter()
+            .map(|dup| {
+                let names: Vec<_> = dup
+                    .spans
+                    .iter()
+                    .map(|sp| &pat[sp.start.offset..sp.end.offset])
+                    .collect();
+                (dup.name.as_str(), names)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("long", vec!["long", "long", "long"]),
+                ("b", vec!["b", "b"])
+            ]
+        );
+
+        assert!(duplicate_group_names("(?P<x>a)(?P<y>b)")
+            .unwrap()
+            .is_empty());
+        assert!(duplicate_group_names("(?P<x>a)(").is_err());
+    }
+}
@@ -8,12 +8,12 @@ use std::str::{self};
 use rustc_lexer::unescape::{unescape_str, EscapeError};
 use serde::Serialize;
 
-use crate::error::{Span, Unescape};
+use crate::error::{Span, Unescape, DEFAULT_LINE_BASE};
 
 /// Return a sliced string if valid UTF8. Otherwise, replace invalid unicode with an escape
 /// sequence (e.g. "this part is valid \x1f but that wasn't")
-pub fn str_from_utf8_rep(text: &str, start: usize, end: usize) -> Cow<str> {
-    let mut bslice = &text.as_bytes()[start..end];
+pub fn str_from_utf8_rep(text: &[u8], start: usize, end: usize) -> Cow<'_, str> {
+    let mut bslice = &text[start..end];
     let mut utf8_res = str::from_utf8(bslice);
 
     // Short circuit: entire slice is valid UTF8
@@ -48,7 +48,7 @@ pub fn str_from_utf8_rep(text: &str, start: usize, end: usize) -> Cow<str> {
         bslice = &bslice[valid_end..];
 
         // 2. Push all invalid bytes formatted as "\xff"
-        let invalid_end = err_len_res.map_or(bslice.len(), |elen| elen + valid_end);
+        let invalid_end = err_len_res.unwrap_or(bslice.len());
         for byte in &bslice[..invalid_end] {
             write!(ret, r"\x{byte:02x}").unwrap();
         }
@@ -60,16 +60,237 @@ pub fn str_from_utf8_rep(text: &str, start: usize, end: usize) -> Cow<str> {
     Cow::Owned(ret)
 }
 
+/// For the string that `str_from_utf8_rep` gives for the same range, map each
+/// of its utf16 code units back to the byte offset in `text` it came from.
+/// Each byte of an escape like `\xff` maps to that byte. There is one more
+/// entry than code units, which holds `end`.
+pub fn utf8_rep_offset_map(text: &[u8], start: usize, end: usize) -> Vec<usize> {
+    let mut ret = Vec::with_capacity(end - start + 1);
+    let mut pos = start;
+
+    for chunk in text[start..end].utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            ret.extend(std::iter::repeat_n(pos, ch.len_utf16()));
+            pos += ch.len_utf8();
+        }
+        for _ in chunk.invalid() {
+            // `\xNN` is four code units
+            ret.extend(std::iter::repeat_n(pos, 4));
+            pos += 1;
+        }
+    }
+
+    ret.push(end);
+    ret
+}
+
 /// Convert a single utf8 **byte** index to utf16
 pub fn utf16_index_bytes(s: &str, i: usize) -> usize {
     s[..i].chars().map(char::len_utf16).sum()
 }
 
+/// Take a single utf8 **byte** index and convert it to a char (utf32) index
+pub fn utf32_index_bytes(s: &str, i: usize) -> usize {
+    s[..i].chars().count()
+}
+
+/// Take an unsorted list of utf8 indices and return a sorted map of
+/// `utf8_index->char_index`. Indices that fall inside a char map to the index
+/// of that char.
+///
+/// Panics if an index is outside of the string
+pub fn utf32_index_bytes_slice(s: &str, mut indices: Vec<usize>) -> Vec<(usize, usize)> {
+    indices.sort_unstable();
+    indices.dedup();
+    assert!(indices.last().is_none_or(|&last| last <= s.len()));
+
+    let mut char_starts = s.char_indices().map(|(byte_idx, _)| byte_idx).peekable();
+    let mut chars_before = 0usize;
+
+    indices
+        .into_iter()
+        .map(|idxu8| {
+            while char_starts.next_if(|&start| start < idxu8).is_some() {
+                chars_before += 1;
+            }
+            // Within a char, the last char we counted is the one we are in
+            if s.is_char_boundary(idxu8) {
+                (idxu8, chars_before)
+            } else {
+                (idxu8, chars_before - 1)
+            }
+        })
+        .collect()
+}
+
+/// Length in bytes of a utf8 byte order mark at the start of `s`, or 0 if
+/// there isn't one
+pub fn bom_len(s: &str) -> usize {
+    if s.starts_with('\u{feff}') {
+        '\u{feff}'.len_utf8()
+    } else {
+        0
+    }
+}
+
+/// Number of utf16 code units in a byte slice, counting each invalid utf8
+/// sequence as a single replacement character
+pub fn utf16_len_bytes(b: &[u8]) -> usize {
+    b.utf8_chunks()
+        .map(|chunk| {
+            let valid: usize = chunk.valid().chars().map(char::len_utf16).sum();
+            valid + usize::from(!chunk.invalid().is_empty())
+        })
+        .sum()
+}
+
+/// Utf16 offset of the start of each line in `s`, in a single pass. The first
+/// line always starts at 0, and a trailing newline gives an empty last line.
+pub fn line_starts_utf16(s: &str) -> Vec<u32> {
+    let mut starts = vec![0];
+    let mut offset = 0;
+    for ch in s.chars() {
+        offset += ch.len_utf16() as u32;
+        if ch == '\n' {
+            starts.push(offset);
+        }
+    }
+    starts
+}
+
+/// Find the part of `a` that differs from `b`, as the utf16 range in `a`
+/// between their longest common prefix and longest common suffix. The prefix
+/// and suffix are whole chars and never overlap. Returns `None` if the strings
+/// are equal.
+pub fn diff_range_utf16(a: &str, b: &str) -> Option<Range<usize>> {
+    if a == b {
+        return None;
+    }
+    let prefix: usize = a
+        .chars()
+        .zip(b.chars())
+        .take_while(|(ca, cb)| ca == cb)
+        .map(|(ch, _)| ch.len_utf8())
+        .sum();
+    let max_suffix = a.len().min(b.len()) - prefix;
+    let mut suffix = 0;
+    for (ca, cb) in a[prefix..].chars().rev().zip(b[prefix..].chars().rev()) {
+        if ca != cb || suffix + ca.len_utf8() > max_suffix {
+            break;
+        }
+        suffix += ca.len_utf8();
+    }
+
+    Some(utf16_index_bytes(a, prefix)..utf16_index_bytes(a, a.len() - suffix))
+}
+
+/// Replace each run of whitespace in `s` with a single space
+pub fn collapse_whitespace(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut in_space = false;
+    for ch in s.chars() {
+        if ch.is_whitespace() {
+            if !in_space {
+                ret.push(' ');
+            }
+            in_space = true;
+        } else {
+            ret.push(ch);
+            in_space = false;
+        }
+    }
+    ret
+}
+
+/// Reverse `regex_syntax::escape`: remove the backslash before each metacharacter.
+/// Other escapes like `\n` are kept, since `regex_syntax::escape` never adds them.
+pub fn unescape_pattern(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            if let Some(&next) = chars.peek() {
+                if regex_syntax::is_meta_character(next) {
+                    ret.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        ret.push(ch);
+    }
+    ret
+}
+
+/// Append a row of fields to a CSV string, quoting as described in RFC 4180.
+/// Fields are quoted only if they contain a comma, quote, or line break.
+pub fn push_csv_row<'a>(dest: &mut String, fields: impl IntoIterator<Item = &'a str>) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            dest.push(',');
+        }
+        if field.contains([',', '"', '\r', '\n']) {
+            dest.push('"');
+            dest.push_str(&field.replace('"', "\"\""));
+            dest.push('"');
+        } else {
+            dest.push_str(field);
+        }
+    }
+    dest.push_str("\r\n");
+}
+
 /// Take a single utf8 **char** index and convert it to utf16
 pub fn utf16_index_chars(s: &str, i: usize) -> usize {
     s.chars().take(i).map(char::len_utf16).sum()
 }
 
+/// Byte length of the longest prefix of `s` that is at most `max` utf16 code
+/// units, or `None` if all of `s` fits
+pub fn utf16_prefix_len(s: &str, max: usize) -> Option<usize> {
+    let mut total = 0;
+    for (idx, ch) in s.char_indices() {
+        total += ch.len_utf16();
+        if total > max {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+/// Widen the byte range `start..end` by up to `radius` utf16 code units on
+/// each side, stopping at the ends of the string. The returned range always
+/// lies on char boundaries.
+pub fn expand_utf16_radius(s: &str, start: usize, end: usize, radius: usize) -> Range<usize> {
+    let start = s.floor_char_boundary(start);
+    let end = s.ceil_char_boundary(end);
+
+    let mut budget = radius;
+    let new_start = s[..start]
+        .char_indices()
+        .rev()
+        .take_while(|(_, ch)| {
+            let fits = ch.len_utf16() <= budget;
+            budget = budget.saturating_sub(ch.len_utf16());
+            fits
+        })
+        .last()
+        .map_or(start, |(idx, _)| idx);
+
+    let mut budget = radius;
+    let new_end = s[end..]
+        .char_indices()
+        .take_while(|(_, ch)| {
+            let fits = ch.len_utf16() <= budget;
+            budget = budget.saturating_sub(ch.len_utf16());
+            fits
+        })
+        .last()
+        .map_or(end, |(idx, ch)| end + idx + ch.len_utf8());
+
+    new_start..new_end
+}
+
 /// Take an unsorted list of utf8 indices; sort them, update, and return a
 /// map of `utf8_index->utf16_index`
 ///
@@ -120,9 +341,8 @@ pub fn utf16_index_bytes_slice(s: &str, mut indices: Vec<usize>) -> Vec<(usize,
 
         // Case 3: We have a valid index and we can find it (=), or the next
         // valid index (>).
-        let Some((byte_idx, ch8_len, u16_offset) )=
-            char_iter.find(|(b_idx, _, _)| *b_idx >= idxu8) else {
-
+        let Some((byte_idx, ch8_len, u16_offset)) = char_iter.find(|(b_idx, _, _)| *b_idx >= idxu8)
+        else {
             // Case 4: not found. If this is the case, we've hit the end of our
             // chars iterator. Just push the last known value for each remaining
             // index.
@@ -146,7 +366,18 @@ pub fn utf16_index_bytes_slice(s: &str, mut indices: Vec<usize>) -> Vec<(usize,
     ret
 }
 
+/// Look up `idxu8` in a sorted map from `utf16_index_bytes_slice` or
+/// `utf32_index_bytes_slice`
 ///
+/// Panics if `idxu8` was not one of the indices the map was made from
+pub fn lookup_index(map: &[(usize, usize)], idxu8: usize) -> usize {
+    let pos = map
+        .binary_search_by_key(&idxu8, |&(idx, _)| idx)
+        .expect("index was not mapped");
+    map[pos].1
+}
+
+/// The kind of string literal that an input should be unescaped as
 #[derive(Clone, Copy, Debug, Default, Serialize)]
 pub enum StrType {
     /// No preprocessing
@@ -162,6 +393,12 @@ pub enum StrType {
     RawStrHash2,
     RawStrHash3,
     RawStrHash4,
+    /// A raw str with the fewest hashes that can contain the input, see
+    /// `raw_auto_type`
+    RawAuto,
+    /// Hex byte pairs such as `61 ff 62`, decoded with `decode_hex`. This is
+    /// only supported for the text of `re_find`.
+    HexBytes,
 }
 
 /// Give a singular noun description of the string type
@@ -175,6 +412,8 @@ impl Display for StrType {
             StrType::RawStrHash2 => write!(f, "r##\""),
             StrType::RawStrHash3 => write!(f, "r###\""),
             StrType::RawStrHash4 => write!(f, "r####\""),
+            StrType::RawAuto => write!(f, "raw"),
+            StrType::HexBytes => write!(f, "hex"),
         }
     }
 }
@@ -189,11 +428,54 @@ impl From<Option<&str>> for StrType {
             Some("rawhash2") => Self::RawStrHash2,
             Some("rawhash3") => Self::RawStrHash3,
             Some("rawhash4") => Self::RawStrHash4,
+            Some("rawauto") => Self::RawAuto,
+            Some("hex") => Self::HexBytes,
             _ => panic!("unrecognized string type"),
         }
     }
 }
 
+/// Most hashes a raw string type can have, i.e. `rawhash4`
+pub const MAX_RAW_HASHES: usize = 4;
+
+/// Pick the raw string type with the fewest hashes that can hold `s`. Every
+/// `"` followed by `n` hashes means at least `n + 1` hashes are needed. Errors
+/// if this needs more than `MAX_RAW_HASHES`.
+fn raw_auto_type(s: &str) -> Result<StrType, Box<Unescape>> {
+    // Longest run of hashes after a quote, and where it is
+    let longest = s
+        .match_indices('"')
+        .map(|(idx, _)| {
+            let hashes = s[idx + 1..].bytes().take_while(|&b| b == b'#').count();
+            (hashes, idx)
+        })
+        .max_by_key(|&(hashes, idx)| (hashes, std::cmp::Reverse(idx)));
+
+    let ty = match longest {
+        None => StrType::RawStr,
+        Some((0, _)) => StrType::RawStrHash1,
+        Some((1, _)) => StrType::RawStrHash2,
+        Some((2, _)) => StrType::RawStrHash3,
+        Some((3, _)) => StrType::RawStrHash4,
+        Some((hashes, idx)) => {
+            let (span, span_utf16, span_char) =
+                Span::from_offsets(s, idx..(idx + 1 + hashes), DEFAULT_LINE_BASE);
+            return Err(Box::new(Unescape {
+                message: format!(
+                    "'\"' followed by {hashes} hashes needs more than the maximum of \
+                     {MAX_RAW_HASHES} hashes"
+                ),
+                kind: "TooManyHashes".to_owned(),
+                span,
+                span_utf16,
+                span_char,
+                source: None,
+            }));
+        }
+    };
+    Ok(ty)
+}
+
 /// Check for unescaped quotes
 fn check_unescaped_quotes(s: &str) -> Result<(), Box<Unescape>> {
     // bad: `"`, `\\"`. ok: `\"`
@@ -225,28 +507,51 @@ fn check_unescaped_quotes(s: &str) -> Result<(), Box<Unescape>> {
     });
     let Some(bad_range) = bad_opt else {
         // no bad quotes, return OK
-        return Ok(())
+        return Ok(());
     };
-    let (span, span_utf16) = Span::from_offsets(s, bad_range);
+    let (span, span_utf16, span_char) = Span::from_offsets(s, bad_range, DEFAULT_LINE_BASE);
     let err = Unescape {
         message: String::from(r#"unescaped '"' in string"#),
         kind: "UnescapedQuote".to_owned(),
         span,
         span_utf16,
+        span_char,
         source: None,
     };
     Err(Box::new(err))
 }
 
 /// Actual implementation of `unescape`
-fn unescape_impl(s: &str, sep: StrType) -> Result<Cow<str>, Box<Unescape>> {
+fn unescape_impl(s: &str, sep: StrType) -> Result<Cow<'_, str>, Box<Unescape>> {
     if matches!(sep, StrType::Ignore) {
         return Ok(Cow::Borrowed(s));
     }
 
+    if matches!(sep, StrType::RawAuto) {
+        return unescape_impl(s, raw_auto_type(s)?);
+    }
+
+    if matches!(sep, StrType::HexBytes) {
+        // Hex decodes to bytes rather than a string, so it needs to be handled
+        // by the caller
+        let (span, span_utf16, span_char) = if s.is_empty() {
+            Default::default()
+        } else {
+            Span::from_offsets(s, 0..s.len(), DEFAULT_LINE_BASE)
+        };
+        return Err(Box::new(Unescape {
+            message: "hex input is only supported for the text of re_find".to_owned(),
+            kind: "HexUnsupported".to_owned(),
+            span,
+            span_utf16,
+            span_char,
+            source: None,
+        }));
+    }
+
     // quickcheck patterns that we know our string can't contain
     let check_pat: Option<&str> = match sep {
-        StrType::Ignore => unreachable!(),
+        StrType::Ignore | StrType::RawAuto | StrType::HexBytes => unreachable!(),
         // This would only really be `"` but not `\"`, can't check with .find
         StrType::Str => None,
         StrType::RawStr => Some("\""),
@@ -285,12 +590,53 @@ fn unescape_impl(s: &str, sep: StrType) -> Result<Cow<str>, Box<Unescape>> {
 
     let Some(e) = err else {
         // no error, good to go!
-        return Ok(Cow::Owned(ret))
+        return Ok(Cow::Owned(ret));
     };
 
     Err(Box::new((s, e.0, e.1).into()))
 }
 
+/// Decode a string of hex byte pairs such as `61 ff 62` into bytes. Pairs may
+/// be separated by whitespace or commas, or not separated at all. Also returns
+/// the offset of each pair within the string.
+pub fn decode_hex(s: &str) -> Result<(Vec<u8>, Vec<usize>), Box<Unescape>> {
+    let mut bytes = Vec::with_capacity(s.len() / 2);
+    let mut offsets = Vec::with_capacity(s.len() / 2);
+    let is_sep = |ch: char| ch.is_ascii_whitespace() || ch == ',';
+    let mut idx = 0;
+
+    while idx < s.len() {
+        // we only ever step over ASCII, so we are always on a char boundary
+        if s[idx..].starts_with(is_sep) {
+            idx += 1;
+            continue;
+        }
+
+        let pair = s
+            .get(idx..(idx + 2))
+            .filter(|pair| pair.bytes().all(|b| b.is_ascii_hexdigit()));
+        let Some(pair) = pair else {
+            // Point at everything up to the next separator
+            let end = s[idx..].find(is_sep).map_or(s.len(), |len| idx + len);
+            let (span, span_utf16, span_char) = Span::from_offsets(s, idx..end, DEFAULT_LINE_BASE);
+            return Err(Box::new(Unescape {
+                message: format!("invalid hex byte '{}'", &s[idx..end]),
+                kind: "InvalidHexByte".to_owned(),
+                span,
+                span_utf16,
+                span_char,
+                source: None,
+            }));
+        };
+
+        bytes.push(u8::from_str_radix(pair, 16).unwrap());
+        offsets.push(idx);
+        idx += 2;
+    }
+
+    Ok((bytes, offsets))
+}
+
 /// Given an optional string type, unescape any `\` characters in a string
 ///
 /// Signature is meant to be easy from js
@@ -302,6 +648,17 @@ pub fn unescape<'a>(s: &'a str, seperator: &Option<String>) -> Result<Cow<'a, st
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_lookup_index() {
+        let s = "a😀b";
+        let map = utf16_index_bytes_slice(s, vec![6, 0, 1, 5]);
+        assert_eq!(lookup_index(&map, 5), 3);
+        assert_eq!(lookup_index(&map, 6), 4);
+        let map = utf32_index_bytes_slice(s, vec![5, 3]);
+        assert_eq!(lookup_index(&map, 3), 1);
+        assert_eq!(lookup_index(&map, 5), 2);
+    }
+
     #[test]
     fn test_unescape() {
         assert_eq!(unescape_impl("abc", StrType::Ignore).unwrap(), "abc");
@@ -315,6 +672,142 @@ mod tests {
         assert!(unescape_impl(r#"""#, StrType::Str).is_err());
     }
 
+    #[test]
+    fn test_str_from_utf8_rep_invalid_run() {
+        // Only the invalid byte is escaped. This used to also escape as many
+        // of the following bytes as there were valid bytes before it, giving
+        // `a\xff\x62c` here, or panicking if that ran past the end.
+        assert_eq!(str_from_utf8_rep(b"a\xffbc", 0, 4), r"a\xffbc");
+        assert_eq!(str_from_utf8_rep(b"abc\xff\xfed", 0, 6), r"abc\xff\xfed");
+        // A truncated sequence at the end is escaped in full
+        assert_eq!(str_from_utf8_rep(b"ab\xf0\x9f", 0, 4), r"ab\xf0\x9f");
+    }
+
+    #[test]
+    fn test_utf32_index_bytes_slice() {
+        let s = "a😀bé";
+        assert_eq!(
+            utf32_index_bytes_slice(s, vec![8, 0, 5, 2, 1, 6]),
+            [(0, 0), (1, 1), (2, 1), (5, 2), (6, 3), (8, 4)]
+        );
+        assert_eq!(utf32_index_bytes(s, 5), 2);
+    }
+
+    #[test]
+    fn test_push_csv_row() {
+        let mut csv = String::new();
+        push_csv_row(&mut csv, ["a", "", "b c"]);
+        push_csv_row(&mut csv, ["x,y", "say \"hi\"", "two\nlines"]);
+        assert_eq!(
+            csv,
+            "a,,b c\r\n\"x,y\",\"say \"\"hi\"\"\",\"two\nlines\"\r\n"
+        );
+    }
+
+    #[test]
+    fn test_raw_auto() {
+        assert!(matches!(raw_auto_type("abc#").unwrap(), StrType::RawStr));
+        assert!(matches!(
+            raw_auto_type("a\"b").unwrap(),
+            StrType::RawStrHash1
+        ));
+        assert!(matches!(
+            raw_auto_type("a\"#b").unwrap(),
+            StrType::RawStrHash2
+        ));
+        assert!(matches!(
+            raw_auto_type("a\"#b\"##c").unwrap(),
+            StrType::RawStrHash3
+        ));
+        assert!(matches!(
+            raw_auto_type("x\"###").unwrap(),
+            StrType::RawStrHash4
+        ));
+
+        let err = raw_auto_type("ab\"####c").unwrap_err();
+        assert_eq!(err.kind, "TooManyHashes");
+        assert_eq!((err.span.start.offset, err.span.end.offset), (2, 7));
+
+        // Content is passed through unchanged
+        let s = "say \"##hi\"##\\n";
+        assert_eq!(unescape_impl(s, StrType::RawAuto).unwrap(), s);
+    }
+
+    #[test]
+    fn test_line_starts_utf16() {
+        assert_eq!(line_starts_utf16(""), [0]);
+        assert_eq!(line_starts_utf16("abc"), [0]);
+        assert_eq!(line_starts_utf16("é😀\nab\r\n\nx\n"), [0, 4, 8, 9, 11]);
+    }
+
+    #[test]
+    fn test_diff_range_utf16() {
+        assert_eq!(diff_range_utf16("abc", "abc"), None);
+        assert_eq!(diff_range_utf16("😀 one end", "😀 two end"), Some(3..6));
+        // Insertions and deletions give an empty range in `a`
+        assert_eq!(diff_range_utf16("ab", "axb"), Some(1..1));
+        assert_eq!(diff_range_utf16("aaa", "aa"), Some(2..3));
+        // Chars that share a prefix of bytes aren't split
+        assert_eq!(diff_range_utf16("é", "è"), Some(0..1));
+    }
+
+    #[test]
+    fn test_collapse_whitespace() {
+        assert_eq!(collapse_whitespace("a \t\n b"), "a b");
+        assert_eq!(collapse_whitespace("  a\u{a0}b\r\n"), " a b ");
+        assert_eq!(collapse_whitespace("ab"), "ab");
+    }
+
+    #[test]
+    fn test_unescape_pattern() {
+        for s in ["a.b*c", r"\d+ (x|y) [^z]", "{1,2}?$^#&-~", "😀", ""] {
+            assert_eq!(unescape_pattern(&regex_syntax::escape(s)), s);
+        }
+        // Escapes that `regex_syntax::escape` doesn't produce are kept
+        assert_eq!(unescape_pattern(r"\n\.\"), r"\n.\");
+    }
+
+    #[test]
+    fn test_utf16_prefix_len() {
+        assert_eq!(utf16_prefix_len("abc", 3), None);
+        assert_eq!(utf16_prefix_len("abc", 2), Some(2));
+        assert_eq!(utf16_prefix_len("", 0), None);
+        // Don't split a surrogate pair
+        assert_eq!(utf16_prefix_len("a😀b", 2), Some(1));
+        assert_eq!(utf16_prefix_len("a😀b", 3), Some(5));
+    }
+
+    #[test]
+    fn test_expand_utf16_radius() {
+        let s = "the quick brown fox";
+        assert_eq!(&s[expand_utf16_radius(s, 10, 15, 4)], "ick brown fox");
+        assert_eq!(&s[expand_utf16_radius(s, 0, 3, 2)], "the q");
+        assert_eq!(&s[expand_utf16_radius(s, 16, 19, 100)], s);
+
+        // emojis take two utf16 units so only one fits on each side
+        let s = "😀😀ab😀😀";
+        assert_eq!(&s[expand_utf16_radius(s, 8, 10, 3)], "😀ab😀");
+        // start and end in the middle of a char get widened
+        assert_eq!(&s[expand_utf16_radius(s, 2, 9, 0)], "😀😀a");
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        let (bytes, offsets) = decode_hex("61 ff,62\n0A6b").unwrap();
+        assert_eq!(bytes, [0x61, 0xff, 0x62, 0x0a, 0x6b]);
+        assert_eq!(offsets, [0, 3, 6, 9, 11]);
+        assert_eq!(decode_hex("").unwrap(), (vec![], vec![]));
+
+        let err = decode_hex("61 fg 62").unwrap_err();
+        assert_eq!(err.kind, "InvalidHexByte");
+        assert_eq!(err.span.start.offset, 3);
+        assert_eq!(err.span.end.offset, 5);
+        let err = decode_hex("61 6").unwrap_err();
+        assert_eq!(err.span.start.offset, 3);
+        assert_eq!(err.span.end.offset, 4);
+        assert!(decode_hex("61 😀").is_err());
+    }
+
     #[test]
     fn test_unescaped_quotes() {
         assert!(check_unescaped_quotes(r#"abcd"#).is_ok());
@@ -115,6 +115,24 @@ fn test_u16_slice_nonutf8_startemoji() {
     test_byte_slice_sparse(s, &input, &expected);
 }
 
+#[test]
+fn test_utf8_rep_offset_map() {
+    let s1 = "a😀b";
+    // `a\xf0\x9f`
+    assert_eq!(
+        utf8_rep_offset_map(s1.as_bytes(), 0, 3),
+        [0, 1, 1, 1, 1, 2, 2, 2, 2, 3]
+    );
+    // `\x9f\x98\x80b`, with the map matching the content's length
+    let content = str_from_utf8_rep(s1.as_bytes(), 2, 6);
+    let map = utf8_rep_offset_map(s1.as_bytes(), 2, 6);
+    assert_eq!(map.len(), content.encode_utf16().count() + 1);
+    assert_eq!(&map[..4], [2, 2, 2, 2]);
+    assert_eq!(&map[12..], [5, 6]);
+    // Valid text maps each utf16 unit, so astral chars take two entries
+    assert_eq!(utf8_rep_offset_map(s1.as_bytes(), 0, 6), [0, 1, 1, 5, 6]);
+}
+
 #[test]
 fn test_str_utf8_replace() {
     let s1 = "a😀b";
@@ -131,14 +149,2193 @@ fn test_str_utf8_replace() {
 
     for (start, end, test_str, res) in expected.iter() {
         eprintln!("testing \"{test_str}\"[{start}..{end}]");
-        assert_eq!(&str_from_utf8_rep(test_str, *start, *end).as_ref(), res);
+        assert_eq!(
+            &str_from_utf8_rep(test_str.as_bytes(), *start, *end).as_ref(),
+            res
+        );
+    }
+}
+
+#[test]
+fn test_extract_groups() {
+    let res = re_extract_groups_impl("a=1 b=2", r"(\w+)=(\d+)", "g").unwrap();
+    let expected: Vec<Vec<Option<Cow<str>>>> = vec![
+        vec![Some("a".into()), Some("1".into())],
+        vec![Some("b".into()), Some("2".into())],
+    ];
+    assert_eq!(res, expected);
+
+    let res = re_extract_groups_impl("a", r"(a)(b)?", "").unwrap();
+    assert_eq!(res, vec![vec![Some(Cow::Borrowed("a")), None]]);
+}
+
+#[test]
+fn test_octal_flag() {
+    assert!(re_build(r"\141", "").is_err());
+    let state = re_build(r"\141", "o").unwrap().unwrap();
+    assert!(state.re.is_match(b"a"));
+    assert!(!state.re.is_match(b"141"));
+}
+
+#[test]
+fn test_find_max_content_bytes() {
+    let text = "ab cd ef gh";
+    let res = re_find_impl(text, r"\w+", "g", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches.len(), 4);
+    assert!(!res.truncated);
+
+    let opts = FindOptions {
+        max_content_bytes: Some(5),
+        ..FindOptions::default()
+    };
+    let res = re_find_impl(text, r"\w+", "g", &opts).unwrap();
+    assert_eq!(res.matches.len(), 2);
+    assert!(res.truncated);
+    assert_eq!(res.matches[1][0].content.as_deref(), Some("cd"));
+    assert_eq!(res.matches[1][0].end_utf16, Some(5));
+}
+
+#[test]
+fn test_find_starts() {
+    for flags in ["gu", "u"] {
+        let starts = re_find_starts_impl(TEST_S, r"a|\p{So}", flags).unwrap();
+        let found = re_find_impl(TEST_S, r"a|\p{So}", flags, &FindOptions::default()).unwrap();
+        let expected: Vec<u32> = found
+            .matches
+            .iter()
+            .map(|groups| groups[0].start_utf16.unwrap() as u32)
+            .collect();
+        assert!(!starts.is_empty());
+        assert_eq!(starts, expected);
+    }
+
+    let starts = re_find_starts_impl("a😀b😀c", "[abc]", "g").unwrap();
+    assert_eq!(starts, vec![0, 3, 6]);
+}
+
+#[test]
+fn test_line_terminator() {
+    let text = "ab\0cd\nef";
+    let opts = FindOptions {
+        build: BuildOptions {
+            line_terminator: Some(b'\0'),
+            ..BuildOptions::default()
+        },
+        ..FindOptions::default()
+    };
+    let res = re_find_impl(text, r"^\w", "gm", &opts).unwrap();
+    let found: Vec<_> = res
+        .matches
+        .iter()
+        .map(|groups| groups[0].content.as_deref().unwrap())
+        .collect();
+    assert_eq!(found, ["a", "c"]);
+
+    let res = re_find_impl(text, r"^\w", "gm", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches.len(), 2);
+    assert_eq!(res.matches[1][0].content.as_deref(), Some("e"));
+
+    let opts = FindOptions {
+        build: BuildOptions {
+            line_terminator: Some(0xff),
+            ..BuildOptions::default()
+        },
+        ..FindOptions::default()
+    };
+    assert!(matches!(
+        re_find_impl(text, r"^\w", "gm", &opts),
+        Err(Error::InvalidOptions(_))
+    ));
+}
+
+#[test]
+fn test_find_participation() {
+    let res = re_find_impl("a", r"(a)(b)?", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.participation, vec![vec![true, true, false]]);
+
+    let res = re_find_impl("ab a", r"(a)(b)?", "g", &FindOptions::default()).unwrap();
+    assert_eq!(
+        res.participation,
+        vec![vec![true, true, true], vec![true, true, false]]
+    );
+}
+
+#[test]
+fn test_replace_bytes() {
+    let res = re_replace_bytes_impl(b"a\xffb a", "a", "c", "g").unwrap();
+    assert_eq!(res.as_ref(), b"c\xffb c");
+
+    let res = re_replace_bytes_impl(b"a\xffb", r"(?-u:\xff)b", "$0$0", "").unwrap();
+    assert_eq!(res.as_ref(), b"a\xffb\xffb");
+}
+
+#[test]
+fn test_find_preview() {
+    let opts = FindOptions {
+        preview_radius: Some(4),
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("the quick brown fox", r"b(r)own", "", &opts).unwrap();
+    let cap = &res.matches[0][0];
+    assert_eq!(cap.preview, Some("ick brown fox"));
+    assert_eq!(cap.preview_offset_utf16, Some(4));
+    // only the entire match gets a preview
+    assert_eq!(res.matches[0][1].preview, None);
+
+    let res = re_find_impl("😀😀ab😀😀", "ab", "", &opts).unwrap();
+    let cap = &res.matches[0][0];
+    assert_eq!(cap.preview, Some("😀😀ab😀😀"));
+    assert_eq!(cap.preview_offset_utf16, Some(4));
+}
+
+#[test]
+fn test_ascii_case_flag() {
+    let is_match = |text: &str, reg_exp, flags| {
+        let state = re_build(reg_exp, flags).unwrap().unwrap();
+        state.re.is_match(text.as_bytes())
+    };
+
+    // The Kelvin sign folds to `k` with unicode case folding only
+    assert!(is_match("\u{212A}", "k", "iu"));
+    assert!(!is_match("\u{212A}", "k", "uA"));
+    assert!(is_match("K", "k", "uA"));
+    assert!(!is_match("É", "é", "uA"));
+    // `A` only adds to `i`, which still does full unicode case folding
+    assert!(is_match("\u{212A}", "k", "iuA"));
+    assert!(is_match("É", "é", "iuA"));
+    // Line terminators still apply to the folded pattern
+    let opts = BuildOptions {
+        line_terminator: Some(b';'),
+        ..BuildOptions::default()
+    };
+    let state = re_build_with("^b", "mA", &opts).unwrap().unwrap();
+    assert!(state.re.is_match(b"a;B"));
+    // Classes, with unicode classes still available
+    assert!(is_match("B", "[a-c]", "A"));
+    assert!(is_match("αB", r"\p{Greek}[b]", "uA"));
+    // Other flags and group names are kept
+    assert!(is_match("A\nB", "^b", "Am"));
+    assert!(!is_match("A\nB", "^b", "A"));
+    let res = re_find_impl("xAb", "(?P<n>a)b", "A", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][1].group_name.as_deref(), Some("n"));
+    assert_eq!(res.matches[0][1].content.as_deref(), Some("A"));
+}
+
+#[test]
+fn test_verbose_flag() {
+    let pat = r"
+        (?P<key>\w+)   # key
+        \ ?=\ ?         # equals, optionally surrounded by spaces
+        (?P<val>\d+)   # value
+    ";
+    let res = re_extract_groups_impl("a = 1 b=2 #=3", pat, "gx").unwrap();
+    let expected: Vec<Vec<Option<Cow<str>>>> = vec![
+        vec![Some("a".into()), Some("1".into())],
+        vec![Some("b".into()), Some("2".into())],
+    ];
+    assert_eq!(res, expected);
+
+    // The stripped pattern gives the same result without `x`
+    let stripped = pattern::strip_comments(pat).unwrap();
+    let res = re_extract_groups_impl("a = 1 b=2 #=3", &stripped, "g").unwrap();
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn test_equivalent() {
+    assert!(re_equivalent_impl("[ab]", "[ba]", "").unwrap());
+    assert!(re_equivalent_impl("a|b", "b|a", "").unwrap());
+    assert!(re_equivalent_impl("foo|bar", "bar|foo|foo", "").unwrap());
+    assert!(re_equivalent_impl("(a)+?b", "a+b", "").unwrap());
+    assert!(re_equivalent_impl("a", "A", "i").unwrap());
+    assert!(!re_equivalent_impl("a", "A", "").unwrap());
+    assert!(!re_equivalent_impl("a+", "a*", "").unwrap());
+    assert!(!re_equivalent_impl("ab", "ba", "").unwrap());
+    assert!(re_equivalent_impl("(", "a", "").is_err());
+}
+
+#[test]
+fn test_find_last() {
+    let text = "a1 😀b22 c333";
+    let res = re_find_last_impl(text, r"([a-z])(\d+)", "").unwrap();
+    let all = re_find_impl(text, r"([a-z])(\d+)", "g", &FindOptions::default()).unwrap();
+    assert_eq!(all.matches.len(), 3);
+    assert_eq!(res.matches.len(), 1);
+    assert_eq!(res.matches[0], all.matches[2]);
+    assert_eq!(res.matches[0][0].match_num, 2);
+    assert_eq!(res.matches[0][2].content.as_deref(), Some("333"));
+    assert_eq!(res.matches[0][0].start_utf16, Some(9));
+
+    let res = re_find_last_impl(text, "x", "").unwrap();
+    assert!(res.matches.is_empty());
+}
+
+#[test]
+fn test_find_hex() {
+    let hex = "61 ff 62, 61 62";
+    let (bytes, pair_offsets) = decode_hex(hex).unwrap();
+    let res = re_find_hex_impl(
+        hex,
+        &bytes,
+        &pair_offsets,
+        "a.b",
+        "g",
+        &FindOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(res.matches.len(), 1);
+    let cap = &res.matches[0][0];
+    assert_eq!(cap.content.as_deref(), Some(r"a\xffb"));
+    assert_eq!((cap.start, cap.end), (Some(0), Some(3)));
+    assert_eq!((cap.start_utf16, cap.end_utf16), (Some(0), Some(8)));
+
+    let res = re_find_hex_impl(
+        hex,
+        &bytes,
+        &pair_offsets,
+        "ab",
+        "g",
+        &FindOptions::default(),
+    )
+    .unwrap();
+    let cap = &res.matches[0][0];
+    assert_eq!((cap.start_utf16, cap.end_utf16), (Some(10), Some(15)));
+
+    // Ranges are in decoded bytes, and don't need to be on char boundaries
+    let find_range = |range_start, range_end| {
+        let opts = FindOptions {
+            range_start: Some(range_start),
+            range_end: Some(range_end),
+            ..FindOptions::default()
+        };
+        re_find_hex_impl(hex, &bytes, &pair_offsets, r"[ab]|\xff", "g", &opts)
+    };
+    let found: Vec<_> = find_range(1, 4)
+        .unwrap()
+        .matches
+        .iter()
+        .map(|m| {
+            (
+                m[0].start.unwrap(),
+                m[0].end.unwrap(),
+                m[0].start_utf16.unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(found, [(1, 2, 3), (2, 3, 6), (3, 4, 10)]);
+    assert!(matches!(find_range(0, 6), Err(Error::InvalidOffset(_))));
+    assert!(matches!(find_range(3, 2), Err(Error::InvalidOffset(_))));
+
+    // Options that need a string are rejected rather than ignored
+    let string_only = [
+        FindOptions {
+            strip_bom: true,
+            ..FindOptions::default()
+        },
+        FindOptions {
+            preview_radius: Some(2),
+            ..FindOptions::default()
+        },
+        FindOptions {
+            char_offsets: true,
+            ..FindOptions::default()
+        },
+        FindOptions {
+            neighbors: 1,
+            ..FindOptions::default()
+        },
+    ];
+    for opts in string_only {
+        let res = re_find_hex_impl(hex, &bytes, &pair_offsets, "a", "g", &opts);
+        assert!(matches!(res, Err(Error::InvalidOptions(_))), "{opts:?}");
     }
+
+    // Content offsets are in the decoded bytes
+    let opts = FindOptions {
+        content_offsets: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_hex_impl(hex, &bytes, &pair_offsets, "a.b", "", &opts).unwrap();
+    let offsets = res.matches[0][0].content_offsets.as_deref().unwrap();
+    assert_eq!(offsets, [0, 1, 1, 1, 1, 2, 3]);
+}
+
+#[test]
+fn test_find_escape_groups() {
+    let hex = "61 ff 62";
+    let (bytes, pair_offsets) = decode_hex(hex).unwrap();
+    let find = |escape_groups| {
+        let opts = FindOptions {
+            escape_groups,
+            ..FindOptions::default()
+        };
+        re_find_hex_impl(hex, &bytes, &pair_offsets, "a(.)(b)", "", &opts).unwrap()
+    };
+    let contents = |res: &MatchSer| {
+        res.matches[0]
+            .iter()
+            .map(|cap| cap.content.as_deref().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    // Escaped by default, like the entire match
+    let escaped = find(None);
+    assert_eq!(contents(&escaped), [r"a\xffb", r"\xff", "b"]);
+    assert_eq!(find(Some(true)), escaped);
+
+    // Only inner groups become lossy
+    assert_eq!(contents(&find(Some(false))), [r"a\xffb", "\u{fffd}", "b"]);
+}
+
+#[test]
+fn test_find_stats() {
+    let res = re_find_impl("a1 b2 c3", r"\d", "g", &FindOptions::default()).unwrap();
+    assert!(res.stats.is_none());
+
+    let opts = FindOptions {
+        stats: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("a1 b2 c3", r"\d", "g", &opts).unwrap();
+    let stats = res.stats.unwrap();
+    assert_eq!(stats.match_count, 3);
+    assert!(stats.elapsed_ms >= 0.0);
+}
+
+#[test]
+fn test_segments() {
+    let seg = |text, is_match| SegmentSer {
+        text: Cow::Borrowed(text),
+        is_match,
+    };
+
+    let res = re_segments_impl("a1b2", r"\d", "g").unwrap();
+    assert_eq!(
+        res,
+        [
+            seg("a", false),
+            seg("1", true),
+            seg("b", false),
+            seg("2", true)
+        ]
+    );
+
+    let res = re_segments_impl("12ab3", r"\d+", "").unwrap();
+    assert_eq!(res, [seg("12", true), seg("ab3", false)]);
+
+    let res = re_segments_impl("ab", "", "g").unwrap();
+    assert_eq!(res, [seg("ab", false)]);
+}
+
+#[test]
+fn test_match_lines() {
+    let lines = |text, reg_exp, flags| -> Vec<(usize, &str)> {
+        re_match_lines_impl(text, reg_exp, flags, &BuildOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.line, l.content))
+            .collect()
+    };
+
+    assert_eq!(lines("12a\n345\na67", r"\d+", ""), [(2, "345")]);
+    // Leftmost-first would only find `a`, but the line still matches in full
+    assert_eq!(lines("ab\na", "a|ab", ""), [(1, "ab"), (2, "a")]);
+    // Empty lines, and a trailing comment with `x`
+    assert_eq!(lines("a\n\nb", "a? # maybe a", "x"), [(1, "a"), (2, "")]);
+    assert_eq!(lines("a\nb", "(?x)a|b # comment", ""), [(1, "a"), (2, "b")]);
+    assert!(lines("a\nb", "", "").is_empty());
+    // Line numbers follow the line base, in results and in errors
+    let zero_based = BuildOptions {
+        line_base: Some(0),
+        ..BuildOptions::default()
+    };
+    let res = re_match_lines_impl("12a\n345\na67", r"\d+", "", &zero_based).unwrap();
+    assert_eq!((res[0].line, res[0].content), (1, "345"));
+    let err = re_match_lines_impl("a", "a\n(", "x", &zero_based).unwrap_err();
+    let err = serde_json::to_value(&err).unwrap();
+    assert_eq!(err["error"]["span"]["start"]["line"], 1);
+    // Errors point into the pattern as given
+    let err = re_match_lines_impl("a", "a(", "", &BuildOptions::default()).unwrap_err();
+    let err = serde_json::to_value(&err).unwrap();
+    assert_eq!(err["error"]["pattern"], "a(");
+    assert_eq!(err["error"]["span"]["start"]["offset"], 1);
+}
+
+#[test]
+fn test_expand_template() {
+    let groups = [Some("all".to_owned()), Some("one".to_owned()), None];
+    let named: HashMap<String, String> = [("two".to_owned(), "2".to_owned())].into();
+    let expand = |rep| re_expand_template_impl(rep, &groups, &named);
+
+    assert_eq!(expand("$1-${two}"), "one-2");
+    assert_eq!(expand("$0 ${1}x $two"), "all onex 2");
+    // Unknown and `None` groups are empty, like `Captures::expand`
+    assert_eq!(expand("[$2][$5][$three][$1x]"), "[][][][]");
+    // Things that aren't references are left alone
+    assert_eq!(expand("$$1 $ ${1"), "$1 $ ${1");
+}
+
+#[test]
+fn test_find_tree() {
+    // Render each tree as `name(children)` to compare the shape
+    fn shape(node: &GroupNodeSer) -> String {
+        let name = node
+            .group_name
+            .clone()
+            .unwrap_or_else(|| node.group_num.to_string());
+        let children: Vec<_> = node.children.iter().map(shape).collect();
+        if children.is_empty() {
+            name
+        } else {
+            format!("{name}({})", children.join(" "))
+        }
+    }
+    let shapes = |text, reg_exp| -> Vec<String> {
+        re_find_tree_impl(text, reg_exp, "g")
+            .unwrap()
+            .iter()
+            .map(shape)
+            .collect()
+    };
+
+    let trees = re_find_tree_impl("ab", "(?P<outer>(?P<inner>a)b)", "").unwrap();
+    let outer = &trees[0].children[0];
+    assert_eq!(outer.group_name.as_deref(), Some("outer"));
+    assert_eq!(outer.children[0].group_name.as_deref(), Some("inner"));
+    assert_eq!(outer.children[0].content, "a");
+    assert_eq!(
+        shapes("ab", "(?P<outer>(?P<inner>a)b)"),
+        ["0(outer(inner))"]
+    );
+
+    // Siblings, nonparticipating groups, and identical spans
+    assert_eq!(shapes("abcab", "(a)(x)?(b(c)?)"), ["0(1 3(4))", "0(1 3)"]);
+    assert_eq!(shapes("a", "((a))"), ["0(1(2))"]);
+    assert!(shapes("a", "b").is_empty());
+}
+
+#[test]
+fn test_find_in_lines() {
+    let lines = ["ab1", "cd", "😀e2 f3"];
+    let found = |flags| -> Vec<(usize, Vec<(usize, usize)>)> {
+        re_find_in_lines_impl(&lines, r"[a-z]\d", flags)
+            .unwrap()
+            .into_iter()
+            .map(|line| {
+                let offsets = line
+                    .matches
+                    .iter()
+                    .map(|m| (m[0].start.unwrap(), m[0].start_utf16.unwrap()))
+                    .collect();
+                (line.line, offsets)
+            })
+            .collect()
+    };
+
+    // Offsets are relative to their own line
+    assert_eq!(found("g"), [(0, vec![(1, 1)]), (2, vec![(4, 2), (7, 5)])]);
+    assert_eq!(found(""), [(0, vec![(1, 1)]), (2, vec![(4, 2)])]);
+}
+
+#[test]
+fn test_would_change() {
+    // Replacing each match with itself changes nothing
+    assert!(!re_would_change_impl("a1 b2", r"(\w)(\d)", "$1$2", "g").unwrap());
+    assert!(!re_would_change_impl("a1 b2", r"\d", "${0}", "g").unwrap());
+    assert!(re_would_change_impl("a1 b2", r"(\w)(\d)", "$2$1", "g").unwrap());
+    // Only a later match differs
+    assert!(re_would_change_impl("x1 x2", r"x(\d)", "x1", "g").unwrap());
+    assert!(!re_would_change_impl("x1 x2", r"x(\d)", "x1", "").unwrap());
+    assert!(!re_would_change_impl("abc", r"\d", "9", "g").unwrap());
+    assert!(!re_would_change_impl("abc", "", "9", "g").unwrap());
+}
+
+#[test]
+fn test_replace_offsets() {
+    let offsets = |text, reg_exp, rep, flags| -> Vec<(usize, usize, isize)> {
+        re_replace_offsets_impl(text, reg_exp, rep, flags)
+            .unwrap()
+            .iter()
+            .map(|o| (o.start_utf16, o.end_utf16, o.delta))
+            .collect()
+    };
+
+    let text = "a=1 😀=22 c=333";
+    let (reg_exp, rep) = (r"(\S)=(\d+)", "$2");
+    let found = offsets(text, reg_exp, rep, "gu");
+    assert_eq!(found, [(0, 3, -2), (4, 9, -3), (10, 15, -2)]);
+
+    // The deltas add up to the change in length
+    let result = re_replace_impl(text, reg_exp, rep, "gu", &ReplaceOptions::default()).unwrap();
+    let total: isize = found.iter().map(|o| o.2).sum();
+    assert_eq!(
+        utf16_index_bytes(text, text.len()) as isize + total,
+        utf16_index_bytes(&result.result, result.result.len()) as isize
+    );
+
+    assert_eq!(offsets("ab", "b", "😀😀", ""), [(1, 2, 3)]);
+    assert!(offsets("ab", "x", "y", "g").is_empty());
+}
+
+#[test]
+fn test_replace_group() {
+    let re = r"item-(?P<num>\d+)-end";
+    let res = re_replace_group_impl("item-42-end, item-7-end", re, "num", "<$num>", "g").unwrap();
+    assert_eq!(res.result, "item-<42>-end, item-<7>-end");
+
+    // Groups can be given by number, and non-participating groups are skipped
+    let res = re_replace_group_impl("a1 b", r"[ab](\d)?", "1", "#", "g").unwrap();
+    assert_eq!(res.result, "a# b");
+    let res = re_replace_group_impl("b", r"[ab](\d)?", "1", "#", "g").unwrap();
+    assert!(matches!(res.result, Cow::Borrowed(_)));
+
+    assert!(matches!(
+        re_replace_group_impl("a", "a", "missing", "", ""),
+        Err(Error::InvalidOptions(_))
+    ));
+}
+
+#[test]
+fn test_run() {
+    let run = |op| re_run_impl(op, "a1 b2", r"(\w)(\d)", "$2$1", "g").unwrap();
+
+    let RunSer::Find(res) = run("find") else {
+        panic!("expected find result");
+    };
+    assert_eq!(res.matches.len(), 2);
+    let RunSer::Replace(res) = run("replace") else {
+        panic!("expected replace result");
+    };
+    assert_eq!(res.result, "1a 2b");
+    let RunSer::Replace(res) = run("replaceList") else {
+        panic!("expected replace result");
+    };
+    assert_eq!(res.result, "1a2b");
+    assert_eq!(
+        run("split"),
+        RunSer::Split(vec!["".into(), " ".into(), "".into()])
+    );
+    assert_eq!(run("isMatch"), RunSer::IsMatch(true));
+
+    let res = re_run_impl("split", "a,b,c", ",", "", "").unwrap();
+    assert_eq!(res, RunSer::Split(vec!["a".into(), "b,c".into()]));
+    let res = re_run_impl("isMatch", "abc", "x", "", "").unwrap();
+    assert_eq!(res, RunSer::IsMatch(false));
+    assert!(matches!(
+        re_run_impl("count", "abc", "x", "", ""),
+        Err(Error::InvalidOptions(_))
+    ));
+}
+
+#[test]
+fn test_replace_bounds() {
+    let res = re_replace_bounds_impl("😀 keep 12 and 34 keep", r"\d+", "#", "g").unwrap();
+    assert_eq!(res.result, "😀 keep # and # keep");
+    assert_eq!(
+        (res.first_diff_utf16, res.last_diff_utf16),
+        (Some(8), Some(17))
+    );
+
+    // A replacement that gives the same text counts as no change
+    let res = re_replace_bounds_impl("abc", "b", "b", "g").unwrap();
+    assert_eq!((res.first_diff_utf16, res.last_diff_utf16), (None, None));
+    let res = re_replace_bounds_impl("abc", "x", "y", "g").unwrap();
+    assert_eq!((res.first_diff_utf16, res.last_diff_utf16), (None, None));
+}
+
+#[test]
+fn test_replace_mark() {
+    let opts = ReplaceOptions {
+        mark: Some(("\u{2}".to_owned(), "\u{3}".to_owned())),
+        ..ReplaceOptions::default()
+    };
+    let res = re_replace_impl("a1 b22", r"\d+", "<$0>", "g", &opts).unwrap();
+    assert_eq!(res.result, "a\u{2}<1>\u{3} b\u{2}<22>\u{3}");
+
+    // Markers are literal, even with `$`
+    let opts = ReplaceOptions {
+        mark: Some(("$0{".to_owned(), "}".to_owned())),
+        ..ReplaceOptions::default()
+    };
+    let res = re_replace_impl("ab", "b", "", "", &opts).unwrap();
+    assert_eq!(res.result, "a$0{}");
+
+    let opts = ReplaceOptions {
+        redact: Some('*'),
+        ..opts
+    };
+    let res = re_replace_impl("a12", r"\d+", "unused", "", &opts).unwrap();
+    assert_eq!(res.result, "a$0{**}");
+}
+
+#[test]
+fn test_replace_had_matches() {
+    let opts = ReplaceOptions::default();
+    // Deleting every match gives an empty result, but with matches
+    let res = re_replace_impl("aaa", "a", "", "g", &opts).unwrap();
+    assert_eq!(res.result, "");
+    assert!(res.had_matches);
+
+    let res = re_replace_impl("", "", "", "g", &opts).unwrap();
+    assert_eq!(res.result, "");
+    assert!(!res.had_matches);
+
+    let res = re_replace_impl("abc", r"\d", "", "g", &opts).unwrap();
+    assert!(!res.had_matches);
+
+    // A replacement with the same text still counts
+    let res = re_replace_impl("abc", "b", "b", "g", &opts).unwrap();
+    assert!(res.had_matches);
+
+    let opts = ReplaceOptions {
+        redact: Some('*'),
+        redact_group: Some("1".to_owned()),
+        ..ReplaceOptions::default()
+    };
+    assert!(
+        re_replace_impl("ab", "a(b)", "", "", &opts)
+            .unwrap()
+            .had_matches
+    );
+    assert!(
+        !re_replace_impl("a", "a(b)?", "", "", &opts)
+            .unwrap()
+            .had_matches
+    );
+
+    assert!(
+        re_replace_group_impl("ab", "a(b)", "1", "", "")
+            .unwrap()
+            .had_matches
+    );
+    assert!(
+        !re_replace_group_impl("a", "a(b)?", "1", "", "")
+            .unwrap()
+            .had_matches
+    );
+}
+
+#[test]
+fn test_replace_redact() {
+    let redact = |text, reg_exp, flags, group: Option<&str>| {
+        let opts = ReplaceOptions {
+            redact: Some('*'),
+            redact_group: group.map(str::to_owned),
+            ..ReplaceOptions::default()
+        };
+        re_replace_impl(text, reg_exp, "unused", flags, &opts)
+            .unwrap()
+            .result
+            .into_owned()
+    };
+
+    assert_eq!(redact("id 12345", r"\d+", "", None), "id *****");
+    assert_eq!(redact("a1 b22 c333", r"\d+", "", None), "a* b22 c333");
+    assert_eq!(redact("a1 b22 c333", r"\d+", "g", None), "a* b** c***");
+    // Length is in UTF-16, so the emoji takes two
+    assert_eq!(redact("x=é😀!", r"x=(\S+)", "", None), "******");
+    assert_eq!(
+        redact(
+            "user=bob pw=hunter2",
+            r"(?P<key>\w+)=(?P<val>\w+)",
+            "g",
+            Some("val")
+        ),
+        "user=*** pw=*******"
+    );
+    assert_eq!(redact("k=v", r"(\w)=(\w)", "", Some("1")), "*=v");
+    // Non-participating groups are left alone
+    assert_eq!(redact("ab", r"a(x)?b", "", Some("1")), "ab");
+}
+
+#[test]
+fn test_replace_alias() {
+    let alias = |rep, aliases: &[(&str, &str)]| {
+        let opts = ReplaceOptions {
+            alias: aliases
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..ReplaceOptions::default()
+        };
+        re_replace_impl("ab cd", r"(?P<x>\w)(\w)", rep, "g", &opts)
+            .map(|res| res.result.into_owned())
+    };
+
+    assert_eq!(alias("${name}", &[("name", "2")]).unwrap(), "b d");
+    assert_eq!(alias("$name-$x", &[("name", "2")]).unwrap(), "b-a d-c");
+    assert_eq!(alias("${first}", &[("first", "x")]).unwrap(), "a c");
+    // Aliases can be used alongside other references and escaped dollars
+    assert_eq!(
+        alias("$$name $1", &[("name", "2")]).unwrap(),
+        "$name a $name c"
+    );
+    // Aliases that refer to a group that doesn't exist are an error
+    assert!(matches!(
+        alias("${name}", &[("name", "3")]),
+        Err(Error::InvalidOptions(_))
+    ));
+    assert!(matches!(
+        alias("", &[("name", "y")]),
+        Err(Error::InvalidOptions(_))
+    ));
+}
+
+#[test]
+fn test_replace_strict() {
+    let opts = ReplaceOptions {
+        strict: true,
+        ..ReplaceOptions::default()
+    };
+    let replace = |rep| re_replace_impl("ab", "(?P<x>a)", rep, "", &opts);
+    let err_span = |rep| match replace(rep) {
+        Err(Error::InvalidReplacement(e)) => {
+            let json = serde_json::to_value(&e).unwrap();
+            (
+                json["span"]["start"]["offset"].as_u64().unwrap(),
+                json["span"]["end"]["offset"].as_u64().unwrap(),
+            )
+        }
+        other => panic!("expected an error, got {other:?}"),
+    };
+
+    assert_eq!(err_span("x$"), (1, 2));
+    assert_eq!(err_span("$-"), (0, 2));
+    assert_eq!(err_span("$1 ${x"), (3, 5));
+    assert_eq!(err_span("${}"), (0, 3));
+    assert_eq!(replace("$$-$1${x}$x").unwrap().result, "$-aaab");
+
+    // Without strict, these are a literal `$`
+    let res = re_replace_impl("ab", "a", "$-$", "", &ReplaceOptions::default()).unwrap();
+    assert_eq!(res.result, "$-$b");
+}
+
+#[test]
+fn test_replace_invalid_utf8() {
+    // Replacing only the first byte of `é` leaves its second byte on its own
+    let replace = |on_invalid_utf8| {
+        let opts = ReplaceOptions {
+            on_invalid_utf8,
+            ..ReplaceOptions::default()
+        };
+        re_replace_impl("aé", r"(?-u:\xc3)", "x", "", &opts).map(|res| res.result.into_owned())
+    };
+    assert_eq!(replace(OnInvalidUtf8::Lossy).unwrap(), "ax\u{fffd}");
+    assert_eq!(replace(OnInvalidUtf8::Escape).unwrap(), "ax\\xa9");
+    assert!(matches!(
+        replace(OnInvalidUtf8::Error),
+        Err(Error::InvalidUtf8Result { offset: 2 })
+    ));
+
+    let list = |on_invalid_utf8| {
+        let opts = ReplaceListOptions {
+            on_invalid_utf8,
+            ..ReplaceListOptions::default()
+        };
+        re_replace_list_impl("é", r"(?-u:\xc3)(?-u:(.))", "$1", "", &opts)
+            .map(|res| res.result.into_owned())
+    };
+    assert_eq!(list(OnInvalidUtf8::Lossy).unwrap(), "\u{fffd}");
+    assert_eq!(list(OnInvalidUtf8::Escape).unwrap(), "\\xa9");
+    assert!(matches!(
+        list(OnInvalidUtf8::Error),
+        Err(Error::InvalidUtf8Result { offset: 0 })
+    ));
+
+    // Valid results are the same with every option
+    let opts = ReplaceOptions {
+        on_invalid_utf8: OnInvalidUtf8::Error,
+        ..ReplaceOptions::default()
+    };
+    assert_eq!(
+        re_replace_impl("aé", "é", "e", "", &opts).unwrap().result,
+        "ae"
+    );
+}
+
+#[test]
+fn test_find_page() {
+    let text = "a1 a2 a3 a4 a5 a6 a7";
+    let opts = FindOptions {
+        offset: 3,
+        limit: Some(3),
+        ..FindOptions::default()
+    };
+    let res = re_find_impl(text, r"a\d", "g", &opts).unwrap();
+    let found: Vec<_> = res
+        .matches
+        .iter()
+        .map(|m| (m[0].match_num, m[0].content.as_deref().unwrap(), m[0].start))
+        .collect();
+    assert_eq!(
+        found,
+        [(3, "a4", Some(9)), (4, "a5", Some(12)), (5, "a6", Some(15))]
+    );
+    assert_eq!(res.participation.len(), 3);
+    assert_eq!(res.total, Some(7));
+
+    // The last page can be short, and a page past the end is empty
+    let opts = FindOptions { offset: 6, ..opts };
+    let res = re_find_impl(text, r"a\d", "g", &opts).unwrap();
+    assert_eq!(res.matches.len(), 1);
+    let opts = FindOptions { offset: 10, ..opts };
+    let res = re_find_impl(text, r"a\d", "g", &opts).unwrap();
+    assert!(res.matches.is_empty());
+    assert_eq!(res.total, Some(7));
+
+    // Without paging there is no total
+    let res = re_find_impl(text, r"a\d", "g", &FindOptions::default()).unwrap();
+    assert_eq!(res.total, None);
+}
+
+#[test]
+fn test_find_neighbors() {
+    let opts = FindOptions {
+        neighbors: 2,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("😀é-42-x", r"\d+", "g", &opts).unwrap();
+    assert_eq!(res.matches[0][0].before, Some("é-"));
+    assert_eq!(res.matches[0][0].after, Some("-x"));
+
+    // Neighbors are clamped at the ends of the text
+    let res = re_find_impl("ab", "a", "", &opts).unwrap();
+    assert_eq!(res.matches[0][0].before, Some(""));
+    assert_eq!(res.matches[0][0].after, Some("b"));
+
+    // A match that splits a char doesn't split its neighbors
+    let res = re_find_impl("xé", r"\xA9", "", &opts).unwrap();
+    assert_eq!(res.matches[0][0].before, Some("x"));
+    assert_eq!(res.matches[0][0].after, Some(""));
+
+    let res = re_find_impl("ab", "a", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][0].before, None);
+}
+
+#[test]
+fn test_max_text_len() {
+    let opts = FindOptions {
+        max_text_len: Some(5),
+        ..FindOptions::default()
+    };
+    assert!(matches!(
+        re_find_impl("abcdef", "b", "", &opts),
+        Err(Error::TextTooLong { len: 6, max: 5 })
+    ));
+    let res = re_find_impl("abcde", "b", "", &opts).unwrap();
+    assert_eq!(res.matches.len(), 1);
+
+    // Checked even if the pattern is empty, and in bytes rather than chars
+    let opts = ReplaceOptions {
+        max_text_len: Some(5),
+        ..ReplaceOptions::default()
+    };
+    assert!(matches!(
+        re_replace_impl("ééé", "", "", "", &opts),
+        Err(Error::TextTooLong { len: 6, max: 5 })
+    ));
+    let res = re_replace_impl("abcde", "b", "x", "", &opts).unwrap();
+    assert_eq!(res.result, "axcde");
+}
+
+#[test]
+fn test_utf8_indices_repeated() {
+    // Every empty match starts and ends at the same place
+    let res = re_find_impl("abc", "x*", "g", &FindOptions::default()).unwrap();
+    assert_eq!(res.utf8_indices(), [0, 1, 2, 3]);
+    let starts: Vec<_> = res.matches.iter().map(|m| m[0].start_utf16).collect();
+    assert_eq!(starts, [Some(0), Some(1), Some(2), Some(3)]);
+    assert!(res
+        .matches
+        .iter()
+        .all(|m| m[0].start_utf16 == m[0].end_utf16));
+
+    // Adjacent matches share an index
+    let res = re_find_impl("aaa", "a", "g", &FindOptions::default()).unwrap();
+    assert_eq!(res.utf8_indices(), [0, 1, 2, 3]);
+    let ends: Vec<_> = res.matches.iter().map(|m| m[0].end_utf16).collect();
+    assert_eq!(ends, [Some(1), Some(2), Some(3)]);
+}
+
+#[test]
+fn test_find_coerce_numbers() {
+    let opts = FindOptions {
+        coerce_numbers: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("v 3.25", r"(\d+)\.(\d+)", "", &opts).unwrap();
+    let values: Vec<_> = res.matches[0].iter().map(|cap| cap.number_value).collect();
+    assert_eq!(values, [Some(3.25), Some(3.0), Some(25.0)]);
+
+    let res = re_find_impl("-1e3 x nan", r"\S+", "g", &opts).unwrap();
+    let values: Vec<_> = res.matches.iter().map(|m| m[0].number_value).collect();
+    assert_eq!(values, [Some(-1000.0), None, None]);
+
+    let res = re_find_impl("12", r"\d+", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][0].number_value, None);
+}
+
+#[test]
+fn test_find_max_content_len() {
+    let opts = FindOptions {
+        max_content_len: Some(4),
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("key: 😀 long value", r"\w+: (.*)", "", &opts).unwrap();
+    let (entire, group) = (&res.matches[0][0], &res.matches[0][1]);
+    assert_eq!(entire.content.as_deref(), Some("key:…"));
+    assert_eq!(entire.truncated, Some(true));
+    assert_eq!((entire.start, entire.end), (Some(0), Some(20)));
+    assert_eq!((entire.start_utf16, entire.end_utf16), (Some(0), Some(18)));
+    // The emoji counts as two utf16 code units
+    assert_eq!(group.content.as_deref(), Some("😀 l…"));
+    assert_eq!((group.start_utf16, group.end_utf16), (Some(5), Some(18)));
+
+    let res = re_find_impl("ab", r"a(b)", "", &opts).unwrap();
+    let truncated: Vec<_> = res.matches[0].iter().map(|cap| cap.truncated).collect();
+    assert_eq!(truncated, [Some(false), Some(false)]);
+    assert_eq!(res.matches[0][0].content.as_deref(), Some("ab"));
+}
+
+#[test]
+fn test_find_exclude_ranges() {
+    let opts = FindOptions {
+        exclude_ranges: vec![(5, 7)],
+        ..FindOptions::default()
+    };
+    // Offsets are utf16, so the emoji counts as two
+    let res = re_find_impl("😀 ab cd ef", r"\w+", "g", &opts).unwrap();
+    let contents: Vec<_> = res
+        .matches
+        .iter()
+        .map(|m| m[0].content.as_deref().unwrap())
+        .collect();
+    assert_eq!(contents, ["ab", "ef"]);
+    // Match numbers only count kept matches
+    assert_eq!(res.matches[1][0].match_num, 1);
+
+    // Touching a range isn't overlapping it
+    let opts = FindOptions {
+        exclude_ranges: vec![(2, 3), (5, 5)],
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("ab cd", r"\w+", "g", &opts).unwrap();
+    assert_eq!(res.matches.len(), 2);
+
+    let opts = FindOptions {
+        exclude_ranges: vec![(3, 2)],
+        ..FindOptions::default()
+    };
+    assert!(matches!(
+        re_find_impl("ab cd", r"\w+", "g", &opts),
+        Err(Error::InvalidOffset(_))
+    ));
+}
+
+#[test]
+fn test_find_sentinel_nonparticipating() {
+    let opts = FindOptions {
+        sentinel_nonparticipating: true,
+        char_offsets: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("😀 b", r"(a)?(b)", "", &opts).unwrap();
+    let (entire, missing, found) = (&res.matches[0][0], &res.matches[0][1], &res.matches[0][2]);
+    assert!(!missing.is_participating);
+    assert_eq!(missing.content, None);
+    assert_eq!((missing.start, missing.end), (Some(5), Some(5)));
+    assert_eq!((missing.start_utf16, missing.end_utf16), (Some(3), Some(3)));
+    assert_eq!((missing.start_char, missing.end_char), (Some(2), Some(2)));
+    assert_eq!(
+        (missing.rel_start_utf16, missing.rel_end_utf16),
+        (Some(0), Some(0))
+    );
+    assert_eq!(entire.start_utf16, Some(3));
+    assert!(found.is_participating);
+    assert_eq!(found.start_utf16, Some(3));
+
+    let res = re_find_impl("b", r"(a)?b", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][1].start_utf16, None);
+}
+
+#[test]
+fn test_find_line_span() {
+    let res = re_find_impl(
+        "a\nfoo\nbar\nb",
+        r"foo\sbar|a|b",
+        "g",
+        &FindOptions::default(),
+    )
+    .unwrap();
+    let spans: Vec<_> = res.matches.iter().map(|m| m[0].line_span).collect();
+    assert_eq!(spans, [Some(1), Some(2), Some(1)]);
+
+    // Only the entire match has a span
+    let res = re_find_impl("x\r\n\ny", r"x(\s+)y", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][0].line_span, Some(3));
+    assert_eq!(res.matches[0][1].line_span, None);
+
+    // Counted after trimming
+    let opts = FindOptions {
+        trim: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("a\n", r"a\s*", "", &opts).unwrap();
+    assert_eq!(res.matches[0][0].line_span, Some(1));
+}
+
+#[test]
+fn test_find_trim() {
+    let opts = FindOptions {
+        trim: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("😀  foo  ", r"[a-z ]+", "", &opts).unwrap();
+    let entire = &res.matches[0][0];
+    assert_eq!(entire.content.as_deref(), Some("foo"));
+    assert_eq!((entire.start, entire.end), (Some(6), Some(9)));
+    assert_eq!((entire.start_utf16, entire.end_utf16), (Some(4), Some(7)));
+
+    // Groups keep their untrimmed spans, even outside of the trimmed match
+    let res = re_find_impl(" a ", r"(\s*)a\s*", "", &opts).unwrap();
+    let (entire, group) = (&res.matches[0][0], &res.matches[0][1]);
+    assert_eq!((entire.start, entire.end), (Some(1), Some(2)));
+    assert_eq!((group.start, group.end), (Some(0), Some(1)));
+    assert_eq!(group.rel_start_utf16, None);
+
+    // All whitespace becomes empty
+    let res = re_find_impl("x  ", r"\s+", "", &opts).unwrap();
+    let entire = &res.matches[0][0];
+    assert_eq!(
+        (entire.start, entire.end, entire.content.as_deref()),
+        (Some(1), Some(1), Some(""))
+    );
+}
+
+#[test]
+fn test_find_snap_graphemes() {
+    let opts = FindOptions {
+        snap_graphemes: true,
+        ..FindOptions::default()
+    };
+    // The match starts on a combining accent, in the middle of `é`
+    let res = re_find_impl("ae\u{301}x", "\u{301}x", "u", &opts).unwrap();
+    let entire = &res.matches[0][0];
+    assert_eq!(entire.content.as_deref(), Some("e\u{301}x"));
+    assert_eq!((entire.start, entire.end), (Some(1), Some(5)));
+    assert_eq!((entire.start_utf16, entire.end_utf16), (Some(1), Some(4)));
+
+    // A skin tone modifier is part of the emoji before it, and a match that
+    // splits a char is widened to whole chars
+    let res = re_find_impl("👍🏽!", "👍", "", &opts).unwrap();
+    assert_eq!(res.matches[0][0].content.as_deref(), Some("👍🏽"));
+    let res = re_find_impl("é", "\\xA9", "", &opts).unwrap();
+    assert_eq!(res.matches[0][0].content.as_deref(), Some("é"));
+
+    let res = re_find_impl("ae\u{301}x", "\u{301}x", "u", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][0].start, Some(2));
+}
+
+#[test]
+fn test_find_dedup() {
+    let opts = FindOptions {
+        dedup: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("foo bar foo baz foo", r"\w+", "g", &opts).unwrap();
+    let found: Vec<_> = res
+        .matches
+        .iter()
+        .map(|m| {
+            (
+                m[0].match_num,
+                m[0].content.as_deref().unwrap(),
+                m[0].start_utf16,
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (0, "foo", Some(0)),
+            (1, "bar", Some(4)),
+            (2, "baz", Some(12))
+        ]
+    );
+    assert_eq!(res.participation.len(), 3);
+
+    let res = re_find_impl("foo foo foo", "foo", "g", &opts).unwrap();
+    assert_eq!(res.matches.len(), 1);
+
+    let res = re_find_impl("foo foo foo", "foo", "g", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches.len(), 3);
+}
+
+#[test]
+fn test_find_char_offsets() {
+    let opts = FindOptions {
+        char_offsets: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("😀é😀 ab", "ab", "", &opts).unwrap();
+    let cap = &res.matches[0][0];
+    assert_eq!((cap.start, cap.end), (Some(11), Some(13)));
+    assert_eq!((cap.start_utf16, cap.end_utf16), (Some(6), Some(8)));
+    assert_eq!((cap.start_char, cap.end_char), (Some(4), Some(6)));
+
+    let res = re_find_impl("😀 ab", "ab", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][0].start_char, None);
+}
+
+#[test]
+fn test_replace_step() {
+    let text = "a=1, b=2";
+    let pat = r"(\w)=(\d)";
+
+    let step = re_replace_step_impl(text, pat, "$2:$1", "", 0).unwrap();
+    assert_eq!(step.result, "1:a, b=2");
+    assert_eq!(step.next_offset, Some(3));
+
+    let step = re_replace_step_impl(&step.result, pat, "$2:$1", "", 3).unwrap();
+    assert_eq!(step.result, "1:a, 2:b");
+    assert_eq!(step.next_offset, Some(8));
+
+    let step = re_replace_step_impl(&step.result, pat, "$2:$1", "", 8).unwrap();
+    assert_eq!(step.result, "1:a, 2:b");
+    assert_eq!(step.next_offset, None);
+
+    // Empty matches still make progress
+    let step = re_replace_step_impl("ab", "x*", "-", "", 0).unwrap();
+    assert_eq!((step.result.as_str(), step.next_offset), ("-ab", Some(2)));
+    let step = re_replace_step_impl("éb", "x*", "-", "", 0).unwrap();
+    assert_eq!((step.result.as_str(), step.next_offset), ("-éb", Some(3)));
+
+    // Without `u`, `.` matches half of `é`, which can't be replaced on its own
+    assert!(matches!(
+        re_replace_step_impl("é", ".", "x", "", 0),
+        Err(Error::InvalidUtf8Result { offset: 1 })
+    ));
+    let step = re_replace_step_impl("é", ".", "x", "u", 0).unwrap();
+    assert_eq!((step.result.as_str(), step.next_offset), ("x", Some(1)));
+
+    assert!(matches!(
+        re_replace_step_impl("😀", "x", "", "", 1),
+        Err(Error::InvalidOffset(_))
+    ));
+    assert!(matches!(
+        re_replace_step_impl("ab", "x", "", "", 3),
+        Err(Error::InvalidOffset(_))
+    ));
+}
+
+#[test]
+fn test_find_csv() {
+    let text = "apples,3\n\"big, red\",12\npears,x";
+    let csv = re_find_csv_impl(text, r#"([\w" ,]+),(\d+)"#, "g").unwrap();
+    assert_eq!(csv, "1,2\r\napples,3\r\n\"\"\"big, red\"\"\",12\r\n");
+
+    let csv = re_find_csv_impl("a=1 b", r"(?P<key>\w)(?:=(?P<val>\d))?", "g").unwrap();
+    assert_eq!(csv, "key,val\r\na,1\r\nb,\r\n");
+
+    // No groups uses the entire match
+    let csv = re_find_csv_impl("a b", r"\w", "g").unwrap();
+    assert_eq!(csv, "0\r\na\r\nb\r\n");
+}
+
+#[test]
+fn test_find_text_len() {
+    let res = re_find_impl(TEST_S, "a", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.text_len_utf8, TEST_S.len());
+    assert_eq!(res.text_len_utf16, TEST_S.encode_utf16().count());
+    assert_eq!((res.text_len_utf8, res.text_len_utf16), (35, 17));
+
+    // Still set without a pattern
+    let res = re_find_impl(TEST_S, "", "", &FindOptions::default()).unwrap();
+    assert_eq!((res.text_len_utf8, res.text_len_utf16), (35, 17));
+}
+
+#[test]
+fn test_literal() {
+    let literal = BuildOptions {
+        literal: true,
+        ..BuildOptions::default()
+    };
+    let find_opts = FindOptions {
+        build: literal,
+        ..FindOptions::default()
+    };
+    let contents = |text, reg_exp, flags| -> Vec<String> {
+        re_find_impl(text, reg_exp, flags, &find_opts)
+            .unwrap()
+            .matches
+            .iter()
+            .map(|m| m[0].content.as_deref().unwrap().to_owned())
+            .collect()
+    };
+
+    assert_eq!(contents("axb a.b", "a.b", "g"), ["a.b"]);
+    assert_eq!(contents("A.B a.b", "a.b", "gi"), ["A.B", "a.b"]);
+    assert_eq!(contents("(a+) b", "(a+) b", "x"), ["(a+) b"]);
+    assert!(contents("axb", "a.b", "g").is_empty());
+
+    let replace_opts = ReplaceOptions {
+        build: BuildOptions {
+            literal: true,
+            ..BuildOptions::default()
+        },
+        ..ReplaceOptions::default()
+    };
+    let res = re_replace_impl("1+1 11", "1+1", "2", "g", &replace_opts).unwrap();
+    assert_eq!(res.result, "2 11");
+}
+
+#[test]
+fn test_strip_bom() {
+    let text = "\u{feff}abc";
+    let opts = FindOptions {
+        strip_bom: true,
+        char_offsets: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl(text, "^a", "", &opts).unwrap();
+    let cap = &res.matches[0][0];
+    assert_eq!(cap.content.as_deref(), Some("a"));
+    // Offsets include the BOM
+    assert_eq!((cap.start, cap.end), (Some(3), Some(4)));
+    assert_eq!((cap.start_utf16, cap.end_utf16), (Some(1), Some(2)));
+    assert_eq!((cap.start_char, cap.end_char), (Some(1), Some(2)));
+
+    let res = re_find_impl(text, "^a", "", &FindOptions::default()).unwrap();
+    assert!(res.matches.is_empty());
+
+    let opts = ReplaceOptions {
+        strip_bom: true,
+        ..ReplaceOptions::default()
+    };
+    let res = re_replace_impl(text, "^a", "x", "", &opts).unwrap();
+    assert_eq!(res.result, "\u{feff}xbc");
+    let res = re_replace_impl(text, "^z", "x", "", &opts).unwrap();
+    assert_eq!(res.result, text);
+}
+
+#[test]
+fn test_pattern_empty() {
+    let opts = FindOptions::default();
+
+    let res = re_find_impl("abc", "", "g", &opts).unwrap();
+    assert!(res.matches.is_empty());
+    assert!(res.pattern_empty);
+
+    let res = re_find_impl("abc", "x", "g", &opts).unwrap();
+    assert!(res.matches.is_empty());
+    assert!(!res.pattern_empty);
+
+    let res = re_find_impl("abc", "b", "g", &opts).unwrap();
+    assert_eq!(res.matches.len(), 1);
+    assert!(!res.pattern_empty);
+
+    assert!(re_find_last_impl("abc", "", "").unwrap().pattern_empty);
+    assert!(!re_find_last_impl("abc", "x", "").unwrap().pattern_empty);
+}
+
+#[test]
+fn test_max_repetition() {
+    let opts = FindOptions {
+        build: BuildOptions {
+            max_repetition: Some(1000),
+            ..BuildOptions::default()
+        },
+        ..FindOptions::default()
+    };
+    assert!(matches!(
+        re_find_impl("aaa", "a{100000}", "", &opts),
+        Err(Error::RepetitionTooLarge(_))
+    ));
+    assert!(re_find_impl("aaa", "a{3}", "", &opts).is_ok());
+    // Whitespace flag is respected when parsing
+    assert!(matches!(
+        re_find_impl("aaa", "a {2000}", "x", &opts),
+        Err(Error::RepetitionTooLarge(_))
+    ));
+
+    assert!(matches!(
+        re_find_impl("aaa", "a{100001}", "", &FindOptions::default()),
+        Err(Error::RepetitionTooLarge(_))
+    ));
+}
+
+#[test]
+fn test_program_size() {
+    let trivial = re_program_size_impl("a", "").unwrap();
+    let complex = re_program_size_impl(r"(\w+)@(\w+)\.(com|org|net){1,5}", "u").unwrap();
+    assert!(trivial > 0);
+    assert!(complex > trivial, "{complex} <= {trivial}");
+    assert_eq!(re_program_size_impl("", "").unwrap(), 0);
+    assert!(re_program_size_impl("(", "").is_err());
+
+    // Repetitions are compiled as copies
+    let small = re_program_size_impl("a{10}", "").unwrap();
+    let large = re_program_size_impl("a{100}", "").unwrap();
+    assert!(large > small * 2, "{large} <= 2 * {small}");
+
+    // Too large for the default limit
+    assert!(matches!(
+        re_program_size_impl(r"\w{1000}", "u"),
+        Err(Error::RegexCompiledTooBig(_))
+    ));
+}
+
+#[test]
+fn test_fixed_len() {
+    let fixed_len = |reg_exp, flags| {
+        let len = re_fixed_len_impl(reg_exp, flags).unwrap();
+        (len.min, len.max)
+    };
+
+    assert_eq!(fixed_len(r"\d{4}", ""), (Some(4), Some(4)));
+    assert_eq!(fixed_len(r"\d+", ""), (Some(1), None));
+    assert_eq!(fixed_len(r"ab?c{2,3}", ""), (Some(3), Some(5)));
+    // Bytes depend on the unicode flag
+    assert_eq!(fixed_len(r".", ""), (Some(1), Some(1)));
+    assert_eq!(fixed_len(r".", "u"), (Some(1), Some(4)));
+    assert_eq!(fixed_len("", ""), (Some(0), Some(0)));
+    assert_eq!(fixed_len("[a&&b]", ""), (None, None));
+}
+
+#[test]
+fn test_replace_list_tagged() {
+    let opts = ReplaceListOptions {
+        tagged: true,
+        ..ReplaceListOptions::default()
+    };
+    let res =
+        re_replace_list_tagged_impl("😀a=1 b=2 c=3", r"(\w)=(\d)", "$2:$1", "g", &opts).unwrap();
+    let found: Vec<_> = res
+        .iter()
+        .map(|out| {
+            (
+                out.source_match,
+                out.source_span_utf16.clone(),
+                out.output.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [(0, 2..5, "1:a"), (1, 6..9, "2:b"), (2, 10..13, "3:c")]
+    );
+
+    let json = serde_json::to_value(&res[0]).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "sourceMatch": 0,
+            "sourceSpanUtf16": { "start": 2, "end": 5 },
+            "output": "1:a",
+        })
+    );
+}
+
+#[test]
+fn test_replace_list_gaps() {
+    let opts = ReplaceListOptions {
+        include_gaps: true,
+        ..ReplaceListOptions::default()
+    };
+    let segments =
+        re_replace_list_gaps_impl("a=1, b=2.", r"(\w)=(\d)", "$2$1", "g", &opts).unwrap();
+    let found: Vec<_> = segments
+        .iter()
+        .map(|seg| (seg.text.as_ref(), seg.is_match))
+        .collect();
+    assert_eq!(
+        found,
+        [("1a", true), (", ", false), ("2b", true), (".", false)]
+    );
+
+    // Matches are kept even if they expand to nothing
+    let segments = re_replace_list_gaps_impl("xay", "a", "", "", &opts).unwrap();
+    let found: Vec<_> = segments
+        .iter()
+        .map(|seg| (seg.text.as_ref(), seg.is_match))
+        .collect();
+    assert_eq!(found, [("x", false), ("", true), ("y", false)]);
+
+    let segments = re_replace_list_gaps_impl("xay", "", "", "", &opts).unwrap();
+    assert_eq!(segments.len(), 1);
+
+    let res = re_replace_list_impl("xay", "", "-", "g", &opts).unwrap();
+    assert_eq!(res.result, "");
+}
+
+#[test]
+fn test_line_prefix() {
+    let prefix = |text| line_prefix_impl(text, "> ", "").unwrap();
+    assert_eq!(prefix("a\nb\r\nc"), "> a\n> b\r\n> c");
+    // Empty lines are prefixed, but not an empty line after a final newline
+    assert_eq!(prefix("a\n\nb\n"), "> a\n> \n> b\n");
+    assert_eq!(prefix("\n"), "> \n");
+    assert_eq!(prefix(""), "> ");
+    // The prefix is literal
+    assert_eq!(line_prefix_impl("a\nb", "$1 ", "g").unwrap(), "$1 a\n$1 b");
+
+    // A plain replacement also matches after the final newline
+    let res = re_replace_impl("a\nb\n", "^", "> ", "gm", &ReplaceOptions::default()).unwrap();
+    assert_eq!(res.result, "> a\n> b\n> ");
+}
+
+#[test]
+fn test_replace_lines() {
+    let opts = ReplaceOptions::default();
+    let lines = re_replace_lines_impl("a,b,c", ",", "\n", "g", &opts).unwrap();
+    assert_eq!(lines, ["a", "b", "c"]);
+
+    let lines = re_replace_lines_impl("k=v;\nx=y;", ";", ";\n", "g", &opts).unwrap();
+    assert_eq!(lines, ["k=v;", "", "x=y;", ""]);
+
+    assert_eq!(
+        re_replace_lines_impl("", "x", "y", "", &opts).unwrap(),
+        [""]
+    );
+}
+
+#[test]
+fn test_shortest() {
+    let shortest = FindOptions {
+        shortest: true,
+        ..FindOptions::default()
+    };
+    let default = FindOptions::default();
+    let contents = |text, reg_exp, opts: &FindOptions| -> Vec<String> {
+        re_find_impl(text, reg_exp, "g", opts)
+            .unwrap()
+            .matches
+            .iter()
+            .map(|m| m[0].content.as_deref().unwrap().to_owned())
+            .collect()
+    };
+
+    // Greedy, lazy, and shortest
+    assert_eq!(contents("aaa b", "a+", &default), ["aaa"]);
+    assert_eq!(contents("aaa b", "a+?", &default), ["a", "a", "a"]);
+    assert_eq!(contents("aaa b", "a+", &shortest), ["a", "a", "a"]);
+    // Alternation prefers the first branch, shortest doesn't
+    assert_eq!(contents("abc", "ab|a", &default), ["ab"]);
+    assert_eq!(contents("abc", "ab|a", &shortest), ["a"]);
+    // A shorter match starting later isn't used
+    assert_eq!(contents("abc", "abc|b", &shortest), ["abc"]);
+    // Assertions see the whole text, not just the match
+    assert_eq!(contents("ab", "a$|ab", &shortest), ["ab"]);
+    assert_eq!(contents("ab", r"a\b|ab", &shortest), ["ab"]);
+    assert_eq!(contents("a b", r"a\b|a b", &shortest), ["a"]);
+    // Empty matches step over whole chars
+    assert_eq!(contents("😀a", "a*", &shortest), ["", "", ""]);
+
+    // Groups come from the shortest match
+    let res = re_find_impl("xyy", "x(y+)", "", &shortest).unwrap();
+    assert_eq!(res.matches[0][1].content.as_deref(), Some("y"));
+}
+
+#[test]
+fn test_anchored_start() {
+    let opts = FindOptions {
+        anchored_start: true,
+        ..FindOptions::default()
+    };
+    let contents = |text, reg_exp, flags| -> Vec<String> {
+        re_find_impl(text, reg_exp, flags, &opts)
+            .unwrap()
+            .matches
+            .iter()
+            .map(|m| m[0].content.as_deref().unwrap().to_owned())
+            .collect()
+    };
+
+    assert_eq!(contents("12ab", r"\d+", "g"), ["12"]);
+    assert!(contents("ab12", r"\d+", "g").is_empty());
+    // Only one match even when the next would follow on directly
+    assert_eq!(contents("1a2", r"\d|a", "g"), ["1"]);
+    // `^` with the `m` flag matches after a newline, but that isn't the start
+    assert!(contents("a\n12", r"^\d+", "gm").is_empty());
+    // An empty match at the start counts
+    assert_eq!(contents("ab", r"\d*", "g"), [""]);
+}
+
+#[test]
+fn test_group_order() {
+    // Named and unnamed, nested, and non-participating groups
+    let res = re_find_impl(
+        "ab-cd ab-cd",
+        r"(?P<first>(a)(?P<b>b))-(x)?(c(d))",
+        "g",
+        &FindOptions::default(),
+    )
+    .unwrap();
+
+    for (match_num, match_) in res.matches.iter().enumerate() {
+        let nums: Vec<_> = match_.iter().map(|cap| cap.group_num).collect();
+        assert_eq!(nums, [0, 1, 2, 3, 4, 5, 6]);
+        assert!(match_.iter().all(|cap| cap.match_num == match_num));
+
+        let contents: Vec<_> = match_.iter().map(|cap| cap.content.as_deref()).collect();
+        assert_eq!(
+            contents,
+            [
+                Some("ab-cd"),
+                Some("ab"),
+                Some("a"),
+                Some("b"),
+                None,
+                Some("cd"),
+                Some("d")
+            ]
+        );
+        let names: Vec<_> = match_.iter().map(|cap| cap.group_name.as_deref()).collect();
+        assert_eq!(
+            names,
+            [None, Some("first"), None, Some("b"), None, None, None]
+        );
+    }
+}
+
+#[test]
+fn test_innermost_only() {
+    let opts = FindOptions {
+        innermost_only: true,
+        ..FindOptions::default()
+    };
+    let groups = |text, reg_exp| -> Vec<(usize, String)> {
+        let res = re_find_impl(text, reg_exp, "", &opts).unwrap();
+        assert_eq!(res.participation[0].len(), res.matches[0].len());
+        res.matches[0]
+            .iter()
+            .map(|cap| (cap.group_num, cap.content.as_deref().unwrap().to_owned()))
+            .collect()
+    };
+
+    assert_eq!(
+        groups("ab", "((a)(b))"),
+        [(0, "ab".to_owned()), (3, "b".to_owned())]
+    );
+    // Groups that don't participate are skipped
+    assert_eq!(
+        groups("ab", "((a)(b)(c)?)"),
+        [(0, "ab".to_owned()), (3, "b".to_owned())]
+    );
+    // Without any groups there is just the entire match
+    assert_eq!(groups("ab", "ab"), [(0, "ab".to_owned())]);
+}
+
+#[test]
+fn test_one_per_line() {
+    let opts = FindOptions {
+        one_per_line: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("a1 b2\nc3\n\nd4 e5 f6", r"[a-z]\d", "g", &opts).unwrap();
+    let contents: Vec<_> = res
+        .matches
+        .iter()
+        .map(|m| m[0].content.as_deref().unwrap())
+        .collect();
+    assert_eq!(contents, ["a1", "c3", "d4"]);
+    assert_eq!(res.matches[2][0].match_num, 2);
+
+    // A match spanning lines counts for the line it starts on
+    let res = re_find_impl("ab\nb b\nb", r"b\s*b|b", "g", &opts).unwrap();
+    let starts: Vec<_> = res.matches.iter().map(|m| m[0].start.unwrap()).collect();
+    assert_eq!(starts, [1, 5]);
+}
+
+#[test]
+fn test_primary_group() {
+    let entire = |reg_exp, group: &str| -> Vec<Vec<bool>> {
+        let opts = FindOptions {
+            primary_group: Some(group.to_owned()),
+            ..FindOptions::default()
+        };
+        re_find_impl("ab b", reg_exp, "g", &opts)
+            .unwrap()
+            .matches
+            .iter()
+            .map(|m| m.iter().map(|cap| cap.entire_match).collect())
+            .collect()
+    };
+
+    assert_eq!(entire("a(?P<inner>b)", "inner"), [[false, true]]);
+    assert_eq!(entire("a(?P<inner>b)", "1"), [[false, true]]);
+    assert_eq!(entire("a(?P<inner>b)", "0"), [[true, false]]);
+    // Not marked where the group doesn't participate
+    assert_eq!(
+        entire("(a)?(b)", "1"),
+        [vec![false, true, false], vec![false, false, false]]
+    );
+    assert_eq!(entire("a(b)", "nope"), [[false, false]]);
+}
+
+#[test]
+fn test_find_range() {
+    let find = |text, reg_exp, range: (usize, usize)| -> Vec<(usize, usize)> {
+        let opts = FindOptions {
+            range_start: Some(range.0),
+            range_end: Some(range.1),
+            ..FindOptions::default()
+        };
+        re_find_impl(text, reg_exp, "g", &opts)
+            .unwrap()
+            .matches
+            .iter()
+            .map(|m| (m[0].start.unwrap(), m[0].end.unwrap()))
+            .collect()
+    };
+
+    // The middle third of the text, with offsets relative to all of it
+    let text = "a1 b2 c3 d4 e5 f6";
+    assert_eq!(find(text, r"\w\d", (6, 12)), [(6, 8), (9, 11)]);
+    // Matches can't go past the end
+    assert_eq!(find("aaaa", "a+", (1, 3)), [(1, 3)]);
+    // Text before the start is still seen by assertions
+    assert!(find("ab", r"^b|\bb", (1, 2)).is_empty());
+    assert_eq!(find("a b", r"\bb", (1, 3)), [(2, 3)]);
+    // Empty matches work the same way as without a range
+    assert_eq!(find("xab", "a*", (0, 3)), [(0, 0), (1, 2), (3, 3)]);
+    assert_eq!(find("xab", "a*", (1, 3)), [(1, 2), (3, 3)]);
+
+    // Offsets have to be on char boundaries and in order
+    let err = |range: (usize, usize)| {
+        let opts = FindOptions {
+            range_start: Some(range.0),
+            range_end: Some(range.1),
+            ..FindOptions::default()
+        };
+        re_find_impl("😀a", "a", "g", &opts).unwrap_err()
+    };
+    assert!(matches!(err((1, 5)), Error::InvalidOffset(_)));
+    assert!(matches!(err((0, 6)), Error::InvalidOffset(_)));
+    assert!(matches!(err((4, 0)), Error::InvalidOffset(_)));
+}
+
+#[test]
+fn test_normalize_whitespace() {
+    let opts = FindOptions {
+        normalize_whitespace: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("x a \t\n b y", r"a\s+(b)", "", &opts).unwrap();
+    let cap = &res.matches[0][0];
+    assert_eq!(cap.display_content.as_deref(), Some("a b"));
+    assert_eq!(cap.content.as_deref(), Some("a \t\n b"));
+    assert_eq!((cap.start, cap.end), (Some(2), Some(8)));
+    assert_eq!(res.matches[0][1].display_content.as_deref(), Some("b"));
+
+    // Only set when requested, and not for groups that don't participate
+    let res = re_find_impl("a b", r"a\s(x)?", "", &opts).unwrap();
+    assert_eq!(res.matches[0][1].display_content, None);
+    let res = re_find_impl("a  b", r"a\s+", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][0].display_content, None);
+}
+
+#[test]
+fn test_find_each() {
+    let text = "a😀b1 😀c22 d333";
+    let reg_exp = r"[a-z](\d+)";
+
+    // Each match is the same as from `re_find`
+    let expected = re_find_impl(text, reg_exp, "g", &FindOptions::default()).unwrap();
+    let mut found = Vec::new();
+    let count = re_find_each_impl(text, reg_exp, "g", |match_| {
+        found.push(match_);
+        true
+    })
+    .unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(found, expected.matches);
+
+    // Returning false stops early
+    let mut calls = 0;
+    let count = re_find_each_impl(text, reg_exp, "g", |_| {
+        calls += 1;
+        calls < 2
+    })
+    .unwrap();
+    assert_eq!((count, calls), (2, 2));
+
+    let count = re_find_each_impl(text, reg_exp, "", |_| true).unwrap();
+    assert_eq!(count, 1);
+
+    // Matches that split a char give the same offsets as `re_find`
+    for (text, reg_exp) in [("é", "."), ("aéb", r"(.)(\xA9)"), ("é😀", "")] {
+        let expected = re_find_impl(text, reg_exp, "g", &FindOptions::default()).unwrap();
+        let mut found = Vec::new();
+        re_find_each_impl(text, reg_exp, "g", |match_| {
+            found.push(match_);
+            true
+        })
+        .unwrap();
+        assert_eq!(found, expected.matches, "{text:?} {reg_exp:?}");
+    }
+    let count = re_find_each_impl("é", ".", "g", |_| true).unwrap();
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn test_flags_applied() {
+    let res = re_find_impl("Abc abc", "abc", "gi", &FindOptions::default()).unwrap();
+    assert_eq!(res.flags_applied, ["global", "caseInsensitive"]);
+
+    let opts = ReplaceOptions::default();
+    let res = re_replace_impl("abc", "b", "x", "imi", &opts).unwrap();
+    assert_eq!(res.flags_applied, ["caseInsensitive", "multiLine"]);
+
+    // Whitespace is never ignored in a literal pattern
+    let mut opts = FindOptions::default();
+    opts.build.literal = true;
+    let res = re_find_impl("a b", "a b", "xu", &opts).unwrap();
+    assert_eq!(res.flags_applied, ["unicode"]);
+}
+
+#[test]
+fn test_find_jsmatch() {
+    // Same as `[..."😀 k=1 x=".matchAll(/(?<key>\w)=(\d)?(y)?/g)]` in JS,
+    // without `input`
+    let res = re_find_jsmatch_impl("😀 k=1 x=", r"(?P<key>\w)=(\d)?(y)?", "g").unwrap();
+    let json = serde_json::to_value(&res).unwrap();
+    let expected = serde_json::json!([
+        {
+            "0": "k=1", "1": "k", "2": "1", "3": null,
+            "length": 4, "index": 3, "groups": { "key": "k" },
+        },
+        {
+            "0": "x=", "1": "x", "2": null, "3": null,
+            "length": 4, "index": 7, "groups": { "key": "x" },
+        },
+    ]);
+    assert_eq!(json, expected);
+
+    // Without named groups, `groups` is undefined
+    let res = re_find_jsmatch_impl("ab", "a", "").unwrap();
+    let json = serde_json::to_value(&res).unwrap();
+    let expected = serde_json::json!([{ "0": "a", "length": 1, "index": 0, "groups": null }]);
+    assert_eq!(json, expected);
+}
+
+#[test]
+fn test_group_at() {
+    let text = "😀 x=(ab) y=(cd)";
+    let re = r"(?P<key>\w)=\((?P<value>a(?P<inner>b))\)";
+    let group_at = |offset| {
+        re_group_at_impl(text, re, "g", offset)
+            .unwrap()
+            .map(|cap| (cap.group_num, cap.group_name, cap.content.unwrap()))
+    };
+
+    // A click on `b` lands in the innermost group, `a` in its parent
+    assert_eq!(group_at(7), Some((3, Some("inner".to_owned()), "b".into())));
+    assert_eq!(
+        group_at(6),
+        Some((2, Some("value".to_owned()), "ab".into()))
+    );
+    assert_eq!(group_at(4), Some((0, None, "x=(ab)".into())));
+    assert_eq!(group_at(3), Some((1, Some("key".to_owned()), "x".into())));
+    // Before the match, just past its end, and in text that doesn't match
+    assert_eq!(group_at(0), None);
+    assert_eq!(group_at(9), None);
+    assert_eq!(group_at(13), None);
+}
+
+#[test]
+fn test_find_entries() {
+    let res = re_find_entries_impl("a=1, b=2; c", r"(?P<k>\w+)=(\w+)|;", "k", "g").unwrap();
+    assert_eq!(
+        res,
+        [("a".into(), "a=1".into()), ("b".into(), "b=2".into())]
+    );
+
+    let json = serde_json::to_value(&res).unwrap();
+    assert_eq!(json, serde_json::json!([["a", "a=1"], ["b", "b=2"]]));
+
+    assert!(matches!(
+        re_find_entries_impl("a=1", r"(\w)=", "k", "g"),
+        Err(Error::InvalidOptions(_))
+    ));
+}
+
+#[test]
+fn test_find_filtered() {
+    let res = re_find_filtered_impl("abc a1b 22 x", r"\w+", r"\d", "g").unwrap();
+    let found: Vec<_> = res
+        .matches
+        .iter()
+        .map(|m| (m[0].match_num, m[0].content.as_deref().unwrap()))
+        .collect();
+    assert_eq!(found, [(0, "a1b"), (1, "22")]);
+    assert_eq!(res.participation.len(), 2);
+    assert_eq!(res.matches[0][0].start_utf16, Some(4));
+
+    // Anchors in the filter apply to the match content
+    let res = re_find_filtered_impl("abc a1b 22 x", r"\w+", r"^\d", "g").unwrap();
+    assert_eq!(res.matches.len(), 1);
+    assert_eq!(res.matches[0][0].content.as_deref(), Some("22"));
+
+    let res = re_find_filtered_impl("ab cd", r"\w+", "", "g").unwrap();
+    assert_eq!(res.matches.len(), 2);
+}
+
+#[test]
+fn test_find_grouped_by() {
+    let res = re_find_grouped_by_impl("a1 b2 a3", r"(?P<t>\w)\d", "t", "g").unwrap();
+    let buckets: Vec<_> = res
+        .iter()
+        .map(|(key, matches)| {
+            let contents: Vec<_> = matches
+                .iter()
+                .map(|m| m[0].content.as_deref().unwrap())
+                .collect();
+            (key.as_deref(), contents)
+        })
+        .collect();
+    assert_eq!(
+        buckets,
+        [(Some("a"), vec!["a1", "a3"]), (Some("b"), vec!["b2"])]
+    );
+    assert_eq!(res[&Some("a".into())][1][0].start_utf16, Some(6));
+
+    // Non-participating groups are bucketed under `None`, first
+    let res = re_find_grouped_by_impl("x1 2", r"(?P<t>[a-z])?\d", "t", "g").unwrap();
+    let keys: Vec<_> = res.keys().map(|key| key.as_deref()).collect();
+    assert_eq!(keys, [None, Some("x")]);
+
+    assert!(matches!(
+        re_find_grouped_by_impl("a1", r"(\w)\d", "t", "g"),
+        Err(Error::InvalidOptions(_))
+    ));
+}
+
+#[test]
+fn test_test_cases() {
+    let cases = [
+        ("2024-01-02", true),
+        ("1/2/2024", false),
+        ("x", true),
+        ("0000-00-00", false),
+    ];
+    let res = re_test_cases_impl(r"\d{4}-\d{2}-\d{2}", "", &cases).unwrap();
+    let found: Vec<_> = res.cases.iter().map(|c| (c.matched, c.passed)).collect();
+    assert_eq!(
+        found,
+        [(true, true), (false, true), (false, false), (true, false)]
+    );
+    assert!(!res.passed);
+
+    let res = re_test_cases_impl("-", "", &cases[..2]).unwrap();
+    assert!(res.passed);
+    let res = re_test_cases_impl::<&str>("a", "", &[]).unwrap();
+    assert!(res.passed && res.cases.is_empty());
+}
+
+#[test]
+fn test_validate_all_flags() {
+    let res = re_validate_all_flags_impl(r"\p{L}");
+    let found: Vec<_> = res.iter().map(|r| (r.flags.as_str(), r.valid)).collect();
+    assert_eq!(
+        found,
+        [
+            ("", false),
+            ("u", true),
+            ("x", false),
+            ("ux", true),
+            ("o", false),
+            ("uo", true),
+            ("xo", false),
+            ("uxo", true)
+        ]
+    );
+    assert!(matches!(res[0].error, Some(Error::RegexSyntax(_))));
+    assert!(res[1].error.is_none());
+
+    // Backreference syntax only parses as an octal escape with `o`
+    let res = re_validate_all_flags_impl(r"\101");
+    let valid: Vec<_> = res
+        .iter()
+        .filter(|r| r.valid)
+        .map(|r| r.flags.as_str())
+        .collect();
+    assert_eq!(valid, ["o", "uo", "xo", "uxo"]);
+}
+
+#[test]
+fn test_class_ranges() {
+    let ranges = |reg_exp, flags| -> Vec<(u32, u32)> {
+        re_class_ranges_impl(reg_exp, flags)
+            .unwrap()
+            .iter()
+            .map(|r| (r.start_codepoint, r.end_codepoint))
+            .collect()
+    };
+
+    assert_eq!(ranges("[a-c0-9]", ""), [(0x30, 0x39), (0x61, 0x63)]);
+    assert_eq!(ranges("[a-c0-9]", "u"), [(0x30, 0x39), (0x61, 0x63)]);
+    assert_eq!(ranges("[é]", "u"), [(0xE9, 0xE9)]);
+    // Unicode case folding includes the Kelvin sign
+    assert_eq!(
+        ranges("k", "iu"),
+        [(0x4B, 0x4B), (0x6B, 0x6B), (0x212A, 0x212A)]
+    );
+    assert_eq!(ranges("k", "i"), [(0x4B, 0x4B), (0x6B, 0x6B)]);
+
+    for reg_exp in ["ab", "[a-c]+", "([a-c])", ""] {
+        assert!(
+            matches!(re_class_ranges_impl(reg_exp, ""), Err(Error::NotAClass)),
+            "{reg_exp}"
+        );
+    }
+}
+
+#[test]
+fn test_find_group_usage() {
+    let res = re_find_impl("ab a", "(a)(b)?", "g", &FindOptions::default()).unwrap();
+    assert_eq!(res.group_usage, [2, 2, 1]);
+
+    let res = re_find_impl("xyz", "(a)(b)?", "g", &FindOptions::default()).unwrap();
+    assert_eq!(res.group_usage, [0, 0, 0]);
+
+    // Groups dropped from the output still count
+    let opts = FindOptions {
+        innermost_only: true,
+        ..FindOptions::default()
+    };
+    let res = re_find_impl("ab", "(a)(b)", "g", &opts).unwrap();
+    assert_eq!(res.group_usage, [1, 1, 1]);
+
+    let res = re_find_filtered_impl("ab a", "(a)(b)?", "b", "g").unwrap();
+    assert_eq!(res.group_usage, [1, 1, 1]);
+}
+
+#[test]
+fn test_regex_flags() {
+    let flag_set = RegexFlags::parse("gims").unwrap();
+    assert_eq!(
+        flag_set,
+        RegexFlags {
+            global: true,
+            case_insensitive: true,
+            multi_line: true,
+            dot_matches_new_line: true,
+            ..RegexFlags::default()
+        }
+    );
+    assert_eq!(flag_set.to_flag_string(), "gims");
+
+    // Keys match the names in `flagsApplied`
+    let json = serde_json::to_value(&flag_set).unwrap();
+    assert_eq!(json["caseInsensitive"], true);
+    assert_eq!(json["unicode"], false);
+    let back: RegexFlags = serde_json::from_value(json).unwrap();
+    assert_eq!(back.to_flag_string(), "gims");
+
+    // The string is canonical: ordered and without repeats
+    let flag_set = RegexFlags::parse("xugAiUgso").unwrap();
+    assert_eq!(flag_set.to_flag_string(), "gAiosUux");
+    assert_eq!(RegexFlags::default().to_flag_string(), "");
+
+    assert!(matches!(
+        RegexFlags::parse("gz"),
+        Err(Error::InvalidOptions(_))
+    ));
+    assert!(serde_json::from_value::<RegexFlags>(serde_json::json!({ "sticky": true })).is_err());
+    assert!(matches!(
+        re_find_impl("a", "a", "q", &FindOptions::default()),
+        Err(Error::InvalidOptions(_))
+    ));
+}
+
+#[test]
+fn test_segmented_text() {
+    let spans = |res: SegmentsFindSer| -> Vec<_> {
+        res.spans
+            .iter()
+            .map(|s| {
+                (
+                    s.start_segment,
+                    s.start_offset_utf16,
+                    s.end_segment,
+                    s.end_offset_utf16,
+                )
+            })
+            .collect()
+    };
+    let opts = FindOptions::default();
+
+    let mut text = SegmentedText::default();
+    assert!(matches!(
+        text.find_impl("a", "", &opts),
+        Err(Error::InvalidOptions(_))
+    ));
+    text.splice_impl(0, 0, &["ab😀c", "de", "", "f gh"])
+        .unwrap();
+    assert_eq!(text.segment_count(), 4);
+
+    let res = text.find_impl(r"\w+|😀", "gu", &opts).unwrap();
+    let found: Vec<_> = res
+        .result
+        .matches
+        .iter()
+        .map(|m| (m[0].content.as_deref().unwrap(), m[0].start_utf16.unwrap()))
+        .collect();
+    assert_eq!(found, [("ab", 0), ("😀", 2), ("cdef", 4), ("gh", 9)]);
+    // `cdef` straddles the empty segment between `de` and `f gh`
+    assert_eq!(
+        spans(res),
+        [(0, 0, 0, 2), (0, 2, 0, 4), (0, 4, 3, 1), (3, 2, 3, 4)]
+    );
+
+    // Editing a segment moves the ones after it, in utf8 and utf16
+    text.splice_impl(0, 1, &["😀😀", "x"]).unwrap();
+    text.splice_impl(4, 1, &[" f", "gh"]).unwrap();
+    assert_eq!(text.text, "😀😀xde fgh");
+    let res = text.find_impl(r"\w+", "g", &opts).unwrap();
+    assert_eq!(spans(res), [(1, 0, 2, 2), (4, 1, 5, 2)]);
+    let res = text.find_impl("h", "", &opts).unwrap();
+    assert_eq!(res.result.matches[0][0].start_utf16, Some(10));
+
+    // A match ending at a boundary ends in the earlier segment
+    text.splice_impl(0, 6, &["ab", "cd"]).unwrap();
+    let res = text.find_impl("ab", "", &opts).unwrap();
+    assert_eq!(spans(res), [(0, 0, 0, 2)]);
+
+    assert!(matches!(
+        text.splice_impl(1, 2, &["a"]),
+        Err(Error::InvalidOffset(_))
+    ));
+    assert!(matches!(
+        text.splice_impl(3, 0, &["a"]),
+        Err(Error::InvalidOffset(_))
+    ));
+    assert_eq!(text.text, "abcd");
+}
+
+#[test]
+fn test_capabilities() {
+    let caps = re_capabilities_impl();
+    assert!(!caps.lookaround);
+    assert!(!caps.backreferences);
+    assert_eq!(caps.max_raw_hashes, 4);
+
+    // Every listed flag is accepted, and every other ASCII letter is rejected
+    let flags: Vec<char> = caps.flags.iter().map(|f| f.flag).collect();
+    assert_eq!(flags.iter().collect::<String>(), "gAimosUux");
+    for letter in ('a'..='z').chain('A'..='Z') {
+        let res = re_build("a", &letter.to_string());
+        if flags.contains(&letter) {
+            assert!(res.is_ok(), "{letter}");
+        } else {
+            assert!(matches!(res, Err(Error::InvalidOptions(_))), "{letter}");
+        }
+    }
+
+    // The reported version is the one that is actually locked
+    let lock = include_str!("../Cargo.lock");
+    let locked = lock
+        .split("[[package]]")
+        .find(|pkg| pkg.contains("name = \"regex-automata\"\n"))
+        .and_then(|pkg| pkg.lines().find_map(|l| l.strip_prefix("version = ")))
+        .unwrap();
+    assert_eq!(Some(locked.trim_matches('"')), caps.regex_automata_version);
+}
+
+#[test]
+fn test_find_utf16() {
+    let res = re_find_impl("😀a(b)", r"a\((b)\)", "", &FindOptions::default()).unwrap();
+    let json = serde_json::to_value(MatchUtf16Ser::from(res)).unwrap();
+    let cap = &json["matches"][0][1];
+    assert_eq!(cap["start"], 4);
+    assert_eq!(cap["end"], 5);
+    assert_eq!(cap["relStart"], 2);
+    assert_eq!(cap["content"], "b");
+    assert_eq!(json["textLen"], 6);
+
+    // None of the utf8 fields are present
+    for key in ["startUtf16", "endUtf16", "relStartUtf16", "contentOffsets"] {
+        assert!(cap.get(key).is_none(), "{key}");
+    }
+    assert!(json.get("textLenUtf8").is_none());
+}
+
+#[test]
+fn test_matches_empty() {
+    let opts = FindOptions::default();
+    assert!(re_find_impl("b", "a*", "g", &opts).unwrap().matches_empty);
+    assert!(re_find_impl("b", "a?|b", "g", &opts).unwrap().matches_empty);
+    assert!(re_find_impl("b", "^", "g", &opts).unwrap().matches_empty);
+    assert!(!re_find_impl("b", "a+", "g", &opts).unwrap().matches_empty);
+    assert!(!re_find_impl("b", "a*b", "g", &opts).unwrap().matches_empty);
+    // A pattern that can't match at all
+    assert!(
+        !re_find_impl("b", "[a&&b]", "g", &opts)
+            .unwrap()
+            .matches_empty
+    );
+    assert!(re_find_last_impl("b", "a*", "").unwrap().matches_empty);
+}
+
+#[test]
+fn test_relative_indices() {
+    let res = re_find_impl("xx😀ab", "(a)(b)", "", &FindOptions::default()).unwrap();
+    let rel: Vec<_> = res.matches[0]
+        .iter()
+        .map(|cap| (cap.rel_start_utf16, cap.rel_end_utf16))
+        .collect();
+    assert_eq!(rel, [(None, None), (Some(0), Some(1)), (Some(1), Some(2))]);
+    assert_eq!(res.matches[0][2].start_utf16, Some(5));
+
+    // Relative to the match that contains the group, and not set if the
+    // group doesn't participate
+    let res = re_find_impl("a😀b c", r"(\S)(\S)?(b)?", "gu", &FindOptions::default()).unwrap();
+    let cap = &res.matches[0][3];
+    assert_eq!((cap.rel_start_utf16, cap.rel_end_utf16), (Some(3), Some(4)));
+    let cap = &res.matches[1][2];
+    assert_eq!((cap.rel_start_utf16, cap.rel_end_utf16), (None, None));
+}
+
+#[test]
+fn test_content_offsets() {
+    let opts = FindOptions {
+        content_offsets: true,
+        ..FindOptions::default()
+    };
+    // `.` without the unicode flag matches single bytes
+    let res = re_find_impl("a😀b", "a..", "", &opts).unwrap();
+    let cap = &res.matches[0][0];
+    assert_eq!(cap.content.as_deref(), Some(r"a\xf0\x9f"));
+    assert_eq!(
+        cap.content_offsets.as_deref(),
+        Some([0, 1, 1, 1, 1, 2, 2, 2, 2, 3].as_slice())
+    );
+
+    let res = re_find_impl("a😀b", "a..", "", &FindOptions::default()).unwrap();
+    assert_eq!(res.matches[0][0].content_offsets, None);
+}
+
+#[test]
+fn test_error_render() {
+    assert_eq!(re_error_render_impl("a+b", ""), None);
+    let rendered = re_error_render_impl("ab(?=c)", "").unwrap();
+    let lines: Vec<_> = rendered.lines().collect();
+    assert_eq!(lines[0], "ab(?=c)");
+    // The caret starts at the error column
+    assert_eq!(lines[1], "  ^^^");
+    assert!(lines[2].starts_with("lookahead"));
+
+    // Columns are chars, not bytes: `é` is two bytes but one column
+    let rendered = re_error_render_impl("é(?=c)", "u").unwrap();
+    let lines: Vec<_> = rendered.lines().collect();
+    assert_eq!(lines[1], " ^^^");
 }
 
 #[wasm_bindgen_test]
 fn test_find_unicode() {
     let s = "😃";
-    let res = re_find(s, ".", "u", None, None);
+    let res = re_find(s, ".", "u", None, None, JsValue::UNDEFINED);
     let expected = MatchSer {
         matches: vec![vec![CapSer {
             group_name: None,
@@ -151,7 +2348,10 @@ fn test_find_unicode() {
             start: Some(0),
             end_utf16: Some(2),
             end: Some(4),
+            ..CapSer::default()
         }]],
+        participation: vec![vec![true]],
+        ..MatchSer::default()
     }
     .to_js_value();
 
@@ -161,7 +2361,7 @@ fn test_find_unicode() {
 #[wasm_bindgen_test]
 fn test_find_indices() {
     let s = "😀😃😄";
-    let res = re_find(s, ".*", "u", None, None);
+    let res = re_find(s, ".*", "u", None, None, JsValue::UNDEFINED);
     let expected = MatchSer {
         matches: vec![vec![CapSer {
             group_name: None,
@@ -174,7 +2374,10 @@ fn test_find_indices() {
             start: Some(0),
             end_utf16: Some(6),
             end: Some(12),
+            ..CapSer::default()
         }]],
+        participation: vec![vec![true]],
+        ..MatchSer::default()
     }
     .to_js_value();
 
@@ -185,7 +2388,7 @@ fn test_find_indices() {
 fn test_find_invalid_utf8() {
     // test without unicode flag
     let s = "a😀a";
-    let res = re_find(s, "..", "g", None, None);
+    let res = re_find(s, "..", "g", None, None, JsValue::UNDEFINED);
     let expected = MatchSer {
         matches: vec![
             vec![CapSer {
@@ -199,6 +2402,7 @@ fn test_find_invalid_utf8() {
                 start: Some(0),
                 end_utf16: Some(3),
                 end: Some(2),
+                ..CapSer::default()
             }],
             vec![CapSer {
                 group_name: None,
@@ -211,6 +2415,7 @@ fn test_find_invalid_utf8() {
                 start: Some(2),
                 end_utf16: Some(3),
                 end: Some(4),
+                ..CapSer::default()
             }],
             vec![CapSer {
                 group_name: None,
@@ -223,8 +2428,11 @@ fn test_find_invalid_utf8() {
                 start: Some(4),
                 end_utf16: Some(4),
                 end: Some(6),
+                ..CapSer::default()
             }],
         ],
+        participation: vec![vec![true]; 3],
+        ..MatchSer::default()
     }
     .to_js_value();
 
@@ -241,9 +2449,12 @@ fn test_replace() {
         None,
         None,
         None,
+        JsValue::UNDEFINED,
     );
     let expected = ReplacdSer {
-        result: "1234: end",
+        result: "1234: end".into(),
+        flags_applied: vec![],
+        had_matches: true,
     }
     .to_js_value();
 
@@ -252,9 +2463,20 @@ fn test_replace() {
 
 #[wasm_bindgen_test]
 fn test_replace_list() {
-    let res = re_replace_list("foo bar!", r#"\w+"#, "$0\n", "g", None, None, None);
+    let res = re_replace_list(
+        "foo bar!",
+        r#"\w+"#,
+        "$0\n",
+        "g",
+        None,
+        None,
+        None,
+        JsValue::UNDEFINED,
+    );
     let expected = ReplacdSer {
-        result: "foo\nbar\n",
+        result: "foo\nbar\n".into(),
+        flags_applied: vec!["global"],
+        had_matches: true,
     }
     .to_js_value();
 
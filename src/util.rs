@@ -30,6 +30,23 @@ fn log(s: &str) {
     eprintln!("{s}");
 }
 
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    /// High resolution timestamp in milliseconds, available in browsers,
+    /// workers, and node
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    pub fn now_ms() -> f64;
+}
+
+/// Outside of wasm, use the system clock
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |dur| dur.as_secs_f64() * 1000.0)
+}
+
 /// Use the console as the panic handler. Must be called from js to
 #[wasm_bindgen]
 #[cfg(feature = "js-console")]